@@ -39,7 +39,7 @@ impl Plugin for TimeManagerPlugin
     }
 }
 
-#[derive(Resource)]
+#[derive(Resource, Default)]
 pub struct SimulationTime
 {
     pub elapsed: Seconds,
@@ -47,19 +47,6 @@ pub struct SimulationTime
     sub_tick: Seconds,
 }
 
-impl Default for SimulationTime
-{
-    fn default() -> Self
-    {
-        Self
-        {
-            elapsed: Seconds::default(),
-            ticks: 0,
-            sub_tick: Seconds::default(),
-        }
-    }
-}
-
 fn accumulate_time(
     time: Res<Time>,
     mut simulation_time: ResMut<SimulationTime>,
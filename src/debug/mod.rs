@@ -30,23 +30,12 @@ impl Default for DebugOverlayState
     }
 }
 
-#[derive(Resource)]
+#[derive(Resource, Default)]
 struct RapierDebugState
 {
     enabled: bool,
 }
 
-impl Default for RapierDebugState
-{
-    fn default() -> Self
-    {
-        Self
-        {
-            enabled: false,
-        }
-    }
-}
-
 #[derive(Component)]
 struct DebugHudElement;
 
@@ -220,7 +209,7 @@ fn setup_debug_overlay(mut commands: Commands, asset_server: Res<AssetServer>)
                             "未接続",
                             TextStyle
                             {
-                                font: font,
+                                font,
                                 font_size: 16.0,
                                 color: Color::WHITE,
                             }
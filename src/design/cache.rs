@@ -0,0 +1,414 @@
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::{Duration, SystemTime};
+use walkdir::WalkDir;
+
+use super::collider::MeshGeometry;
+use super::model_config::{ColliderMode, DecompositionSettings};
+use super::package::PackageResolver;
+use super::xacro::{absolute_path, Expansion, Substitution};
+
+const DESCRIPTION_CACHE_DIR: &str = "descriptions";
+const COLLIDER_CACHE_DIR: &str = "colliders";
+// 起動して最初に使うときに、古いものから消してこの大きさ・期間に収める
+const MAX_CACHE_BYTES: u64 = 512 * 1024 * 1024;
+const MAX_CACHE_AGE: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+// 形式を変えたら上げて、古いキャッシュを読まないようにする
+const CACHE_VERSION: u32 = 2;
+
+const SHAPE_CUBOID: u32 = 0;
+const SHAPE_CONVEX_HULL: u32 = 1;
+
+const SUBSTITUTION_PACKAGE: u32 = 0;
+const SUBSTITUTION_ENV: u32 = 1;
+
+// ディスクに残すキーに使う FNV-1a。DefaultHasher は Rust のリリースごとに結果が変わりうる
+struct StableHasher(u64);
+
+impl Default for StableHasher {
+    fn default() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Hasher for StableHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= u64::from(*byte);
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+pub fn file_hash(path: &Path) -> Option<u64> {
+    let bytes = fs::read(path).ok()?;
+    let mut hasher = StableHasher::default();
+    hasher.write(&bytes);
+    Some(hasher.finish())
+}
+
+// xacro の展開結果と入力ファイル。記録した入力ファイルの内容か、
+// $(find) / $(env) の解決結果のどれかが変わっていれば使わない
+pub fn read_description(path: &Path, packages: &PackageResolver) -> Option<(String, Vec<PathBuf>)> {
+    let bytes = read_entry(&description_entry(path))?;
+    let mut reader = ByteReader { bytes: &bytes };
+    if reader.u32()? != CACHE_VERSION {
+        return None;
+    }
+
+    let mut inputs = Vec::new();
+    for _ in 0..reader.u32()? {
+        let input = PathBuf::from(reader.string()?);
+        let hash = reader.u64()?;
+        if file_hash(&input) != Some(hash) {
+            return None;
+        }
+        inputs.push(input);
+    }
+
+    for _ in 0..reader.u32()? {
+        let substitution = match reader.u32()? {
+            SUBSTITUTION_PACKAGE => Substitution::Package {
+                name: reader.string()?,
+                file: PathBuf::from(reader.string()?),
+                path: reader.optional_string()?.map(PathBuf::from),
+            },
+            SUBSTITUTION_ENV => Substitution::Env {
+                name: reader.string()?,
+                value: reader.optional_string()?,
+            },
+            _ => return None,
+        };
+        if !substitution.is_current(packages) {
+            return None;
+        }
+    }
+    Some((reader.string()?, inputs))
+}
+
+// 形式: [版 u32] [入力数 u32] ([パス] [内容のハッシュ u64])... [置換数 u32] ([種類 u32] [名前] ...)... [展開結果]。
+// 文字列は [長さ u32] + UTF-8、無いかもしれない文字列は [有無 u32] の後に続ける
+pub fn write_description(path: &Path, expansion: &Expansion) {
+    let mut bytes = Vec::new();
+    put_u32(&mut bytes, CACHE_VERSION);
+    put_u32(&mut bytes, expansion.inputs.len() as u32);
+    for input in &expansion.inputs {
+        let Some(hash) = file_hash(input) else {
+            return;
+        };
+        put_str(&mut bytes, &input.to_string_lossy());
+        bytes.extend_from_slice(&hash.to_le_bytes());
+    }
+
+    put_u32(&mut bytes, expansion.substitutions.len() as u32);
+    for substitution in &expansion.substitutions {
+        match substitution {
+            Substitution::Package { name, file, path } => {
+                put_u32(&mut bytes, SUBSTITUTION_PACKAGE);
+                put_str(&mut bytes, name);
+                put_str(&mut bytes, &file.to_string_lossy());
+                put_optional_str(&mut bytes, path.as_ref().map(|path| path.to_string_lossy()).as_deref());
+            }
+            Substitution::Env { name, value } => {
+                put_u32(&mut bytes, SUBSTITUTION_ENV);
+                put_str(&mut bytes, name);
+                put_optional_str(&mut bytes, value.as_deref());
+            }
+        }
+    }
+    put_str(&mut bytes, &expansion.urdf);
+
+    write_entry(&description_entry(path), &bytes);
+}
+
+fn description_entry(path: &Path) -> PathBuf {
+    let mut hasher = StableHasher::default();
+    absolute_path(path).hash(&mut hasher);
+    cache_root().join(DESCRIPTION_CACHE_DIR).join(format!("{:016x}.bin", hasher.finish()))
+}
+
+// メッシュファイルの内容と、コライダーの作り方・メッシュの置き方が同じなら同じキーになる
+pub fn collider_key(mesh_hash: u64, mode: ColliderMode, settings: DecompositionSettings, transform: &Transform) -> u64 {
+    let mut hasher = StableHasher::default();
+    CACHE_VERSION.hash(&mut hasher);
+    mesh_hash.hash(&mut hasher);
+    mode.hash(&mut hasher);
+    settings.hash(&mut hasher);
+
+    let values = transform.translation.to_array().into_iter()
+        .chain(transform.rotation.to_array())
+        .chain(transform.scale.to_array());
+    for value in values {
+        value.to_bits().hash(&mut hasher);
+    }
+    hasher.finish()
+}
+
+// メッシュファイルのハッシュが分からないときは、配置済みの頂点と三角形から作る
+pub fn geometry_key(geometry: &MeshGeometry, mode: ColliderMode, settings: DecompositionSettings) -> u64 {
+    let mut hasher = StableHasher::default();
+    CACHE_VERSION.hash(&mut hasher);
+    for vertex in &geometry.vertices {
+        vertex.to_array().map(f32::to_bits).hash(&mut hasher);
+    }
+    geometry.triangles.hash(&mut hasher);
+    mode.hash(&mut hasher);
+    settings.hash(&mut hasher);
+    hasher.finish()
+}
+
+pub fn read_colliders(key: u64) -> Option<Vec<(Vect, Rot, Collider)>> {
+    let bytes = read_entry(&collider_entry(key))?;
+    let mut reader = ByteReader { bytes: &bytes };
+
+    let mut parts = Vec::new();
+    for _ in 0..reader.u32()? {
+        let kind = reader.u32()?;
+        let translation = reader.vec3()?;
+        let rotation = Quat::from_array([reader.f32()?, reader.f32()?, reader.f32()?, reader.f32()?]);
+        let point_count = reader.u32()?;
+        let points = (0..point_count).map(|_| reader.vec3()).collect::<Option<Vec<Vec3>>>()?;
+
+        let collider = match (kind, points.as_slice()) {
+            (SHAPE_CUBOID, [half_extents]) => Collider::cuboid(half_extents.x, half_extents.y, half_extents.z),
+            (SHAPE_CONVEX_HULL, points) => Collider::convex_hull(points)?,
+            _ => return None,
+        };
+        parts.push((translation, rotation, collider));
+    }
+    Some(parts)
+}
+
+// 形式: [部品数 u32] ([種類 u32] [位置 3 x f32] [回転 4 x f32] [点数 u32] [x y z f32]...)...
+// 直方体は半径を 1 点、凸包は頂点を並べる。それ以外の形状を含むときは書かない
+pub fn write_colliders(key: u64, parts: &[(Vect, Rot, Collider)]) {
+    let mut bytes = Vec::new();
+    put_u32(&mut bytes, parts.len() as u32);
+
+    for (translation, rotation, collider) in parts {
+        let (kind, points): (u32, Vec<Vec3>) = if let Some(cuboid) = collider.raw.as_cuboid() {
+            let half = cuboid.half_extents;
+            (SHAPE_CUBOID, vec![Vec3::new(half.x, half.y, half.z)])
+        } else if let Some(hull) = collider.raw.as_convex_polyhedron() {
+            (SHAPE_CONVEX_HULL, hull.points().iter().map(|p| Vec3::new(p.x, p.y, p.z)).collect())
+        } else {
+            return;
+        };
+
+        put_u32(&mut bytes, kind);
+        put_floats(&mut bytes, &translation.to_array());
+        put_floats(&mut bytes, &rotation.to_array());
+        put_u32(&mut bytes, points.len() as u32);
+        for point in points {
+            put_floats(&mut bytes, &point.to_array());
+        }
+    }
+
+    write_entry(&collider_entry(key), &bytes);
+}
+
+fn collider_entry(key: u64) -> PathBuf {
+    cache_root().join(COLLIDER_CACHE_DIR).join(format!("{:016x}.bin", key))
+}
+
+// UDON_CACHE_DIR で指定できる。無ければ OS のキャッシュフォルダ、それも分からなければ実行ファイルの隣に置く
+fn cache_root() -> &'static Path {
+    static ROOT: OnceLock<PathBuf> = OnceLock::new();
+    ROOT.get_or_init(|| {
+        let root = std::env::var_os("UDON_CACHE_DIR").map(PathBuf::from)
+            .or_else(|| platform_cache_dir().map(|dir| dir.join("udon")))
+            .or_else(|| std::env::current_exe().ok()?.parent().map(|dir| dir.join("cache")))
+            .unwrap_or_else(|| PathBuf::from("cache"));
+        prune(&root, MAX_CACHE_BYTES, MAX_CACHE_AGE);
+        root
+    })
+}
+
+#[cfg(target_os = "windows")]
+fn platform_cache_dir() -> Option<PathBuf> {
+    std::env::var_os("LOCALAPPDATA").map(PathBuf::from)
+}
+
+#[cfg(target_os = "macos")]
+fn platform_cache_dir() -> Option<PathBuf> {
+    std::env::var_os("HOME").map(|home| PathBuf::from(home).join("Library/Caches"))
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+fn platform_cache_dir() -> Option<PathBuf> {
+    std::env::var_os("XDG_CACHE_HOME").map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+}
+
+// 使ったエントリは更新日時を進め、整理で消されにくくする
+fn read_entry(path: &Path) -> Option<Vec<u8>> {
+    let bytes = fs::read(path).ok()?;
+    if let Ok(file) = fs::File::options().write(true).open(path) {
+        let _ = file.set_modified(SystemTime::now());
+    }
+    Some(bytes)
+}
+
+// 同じフォルダの一時ファイルに書いてから置き換えるので、途中で落ちても壊れたエントリは残らない
+fn write_entry(path: &Path, bytes: &[u8]) {
+    if let Some(dir) = path.parent() {
+        if let Err(e) = fs::create_dir_all(dir) {
+            warn!("Failed to create cache directory {:?}: {}", dir, e);
+            return;
+        }
+    }
+
+    let temp = path.with_extension(format!("{}.tmp", std::process::id()));
+    if let Err(e) = fs::write(&temp, bytes).and_then(|_| fs::rename(&temp, path)) {
+        warn!("Failed to write cache entry {:?}: {}", path, e);
+        let _ = fs::remove_file(&temp);
+    }
+}
+
+// 期限切れのファイルを消し、残りも新しいものから数えて上限を超えた分を消す
+fn prune(root: &Path, max_bytes: u64, max_age: Duration) {
+    let now = SystemTime::now();
+    let mut entries: Vec<(PathBuf, u64, SystemTime)> = WalkDir::new(root)
+        .into_iter()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_file())
+        .filter_map(|entry| {
+            let meta = entry.metadata().ok()?;
+            Some((entry.into_path(), meta.len(), meta.modified().ok()?))
+        })
+        .collect();
+    entries.sort_by_key(|&(_, _, modified)| std::cmp::Reverse(modified));
+
+    let mut total = 0;
+    for (path, len, modified) in entries {
+        total += len;
+        let expired = now.duration_since(modified).is_ok_and(|age| age > max_age);
+        if expired || total > max_bytes {
+            if let Err(e) = fs::remove_file(&path) {
+                warn!("Failed to remove cache entry {:?}: {}", path, e);
+            }
+            total -= len;
+        }
+    }
+}
+
+fn put_u32(bytes: &mut Vec<u8>, value: u32) {
+    bytes.extend_from_slice(&value.to_le_bytes());
+}
+
+fn put_floats(bytes: &mut Vec<u8>, values: &[f32]) {
+    for value in values {
+        bytes.extend_from_slice(&value.to_le_bytes());
+    }
+}
+
+fn put_str(bytes: &mut Vec<u8>, value: &str) {
+    put_u32(bytes, value.len() as u32);
+    bytes.extend_from_slice(value.as_bytes());
+}
+
+fn put_optional_str(bytes: &mut Vec<u8>, value: Option<&str>) {
+    put_u32(bytes, u32::from(value.is_some()));
+    if let Some(value) = value {
+        put_str(bytes, value);
+    }
+}
+
+// すべてリトルエンディアン
+struct ByteReader<'a> {
+    bytes: &'a [u8],
+}
+
+impl ByteReader<'_> {
+    fn take<const N: usize>(&mut self) -> Option<[u8; N]> {
+        let head = self.bytes.get(..N)?.try_into().ok()?;
+        self.bytes = &self.bytes[N..];
+        Some(head)
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        self.take().map(u32::from_le_bytes)
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        self.take().map(u64::from_le_bytes)
+    }
+
+    fn f32(&mut self) -> Option<f32> {
+        self.take().map(f32::from_le_bytes)
+    }
+
+    fn vec3(&mut self) -> Option<Vec3> {
+        Some(Vec3::new(self.f32()?, self.f32()?, self.f32()?))
+    }
+
+    fn string(&mut self) -> Option<String> {
+        let len = self.u32()? as usize;
+        let bytes = self.bytes.get(..len)?;
+        self.bytes = &self.bytes[len..];
+        String::from_utf8(bytes.to_vec()).ok()
+    }
+
+    // 外側の None は読み込みの失敗、内側の None は値が無いことを表す
+    fn optional_string(&mut self) -> Option<Option<String>> {
+        match self.u32()? {
+            0 => Some(None),
+            _ => self.string().map(Some),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("udon_cache_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn write_entry_leaves_no_temporary_files() {
+        let dir = temp_dir("write");
+        let path = dir.join("colliders").join("0123.bin");
+        write_entry(&path, b"first");
+        write_entry(&path, b"second");
+
+        assert_eq!(fs::read(&path).unwrap(), b"second");
+        assert_eq!(fs::read_dir(path.parent().unwrap()).unwrap().count(), 1);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn prune_removes_expired_and_oldest_entries() {
+        let dir = temp_dir("prune");
+        fs::create_dir_all(dir.join("colliders")).unwrap();
+        let now = SystemTime::now();
+        let entries = [("new.bin", 0), ("old.bin", 60), ("older.bin", 120), ("expired.bin", 3600)];
+        for (name, age) in entries {
+            let path = dir.join("colliders").join(name);
+            fs::write(&path, [0; 10]).unwrap();
+            fs::File::options().write(true).open(&path).unwrap()
+                .set_modified(now - Duration::from_secs(age)).unwrap();
+        }
+
+        prune(&dir, 25, Duration::from_secs(600));
+
+        let mut left: Vec<String> = fs::read_dir(dir.join("colliders")).unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        left.sort();
+        assert_eq!(left, ["new.bin", "old.bin"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
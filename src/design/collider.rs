@@ -0,0 +1,89 @@
+use bevy::prelude::*;
+use bevy::render::mesh::VertexAttributeValues;
+use bevy_rapier3d::parry::math::Point;
+use bevy_rapier3d::parry::transformation::vhacd::{VHACDParameters, VHACD};
+use bevy_rapier3d::prelude::*;
+
+use super::model_config::DecompositionSettings;
+
+pub struct MeshGeometry {
+    pub vertices: Vec<Vec3>,
+    pub triangles: Vec<[u32; 3]>,
+}
+
+impl MeshGeometry {
+    pub fn from_mesh(mesh: &Mesh, transform: &Transform) -> Option<Self> {
+        let positions = match mesh.attribute(Mesh::ATTRIBUTE_POSITION) {
+            Some(VertexAttributeValues::Float32x3(positions)) => positions,
+            _ => return None,
+        };
+
+        let vertices: Vec<Vec3> = positions.iter()
+            .map(|p| transform.transform_point(Vec3::from_array(*p)))
+            .collect();
+
+        let indices: Vec<u32> = match mesh.indices() {
+            Some(indices) => indices.iter().map(|i| i as u32).collect(),
+            None => (0..vertices.len() as u32).collect(),
+        };
+
+        let triangles = indices.chunks_exact(3)
+            .map(|tri| [tri[0], tri[1], tri[2]])
+            .collect();
+
+        Some(Self { vertices, triangles })
+    }
+}
+
+pub fn aabb_collider(geometry: &MeshGeometry) -> Option<(Vect, Rot, Collider)> {
+    let first = *geometry.vertices.first()?;
+    let (min, max) = geometry.vertices.iter()
+        .fold((first, first), |(min, max), v| (min.min(*v), max.max(*v)));
+
+    let half_extents = ((max - min) / 2.0).max(Vec3::splat(1.0e-4));
+    Some(((min + max) / 2.0, Quat::IDENTITY, Collider::cuboid(half_extents.x, half_extents.y, half_extents.z)))
+}
+
+pub fn convex_hull_collider(geometry: &MeshGeometry) -> Option<(Vect, Rot, Collider)> {
+    Collider::convex_hull(&geometry.vertices).map(|collider| (Vec3::ZERO, Quat::IDENTITY, collider))
+}
+
+pub fn trimesh_collider(geometry: &MeshGeometry) -> Option<Collider> {
+    if geometry.triangles.is_empty() {
+        return None;
+    }
+    Some(Collider::trimesh(geometry.vertices.clone(), geometry.triangles.clone()))
+}
+
+// 結果は呼び出し側で他のコライダーと同じキャッシュに書く
+pub fn convex_decomposition_colliders(
+    geometry: &MeshGeometry,
+    settings: DecompositionSettings,
+) -> Vec<(Vect, Rot, Collider)> {
+    decompose(geometry, settings).iter()
+        .filter_map(|points| Collider::convex_hull(points))
+        .map(|collider| (Vec3::ZERO, Quat::IDENTITY, collider))
+        .collect()
+}
+
+fn decompose(geometry: &MeshGeometry, settings: DecompositionSettings) -> Vec<Vec<Vec3>> {
+    if geometry.triangles.is_empty() {
+        return Vec::new();
+    }
+
+    let points: Vec<Point<f32>> = geometry.vertices.iter()
+        .map(|v| Point::new(v.x, v.y, v.z))
+        .collect();
+
+    let params = VHACDParameters {
+        resolution: settings.resolution,
+        max_convex_hulls: settings.max_convex_hulls,
+        ..default()
+    };
+
+    VHACD::decompose(&params, &points, &geometry.triangles, true)
+        .compute_exact_convex_hulls(&points, &geometry.triangles)
+        .into_iter()
+        .map(|(hull, _)| hull.iter().map(|p| Vec3::new(p.x, p.y, p.z)).collect())
+        .collect()
+}
@@ -0,0 +1,445 @@
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+use chrono::{DateTime, Local};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::robot::joint::RobotJoint;
+use super::loader::{
+    geometry_alignment, inertial_mass_properties, resource_file, transform_to_pose, LinkVisual, ModelSearchPaths, RobotSource,
+};
+use super::mjcf;
+use super::registry::{RobotModelEntities, RobotRegistry};
+
+const EXPORT_DIR: &str = "exports";
+// 書き出し先のフォルダ内でメッシュ・テクスチャを置く場所
+const MESH_DIR: &str = "meshes";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExportFormat {
+    Urdf,
+    Mjcf,
+}
+
+impl ExportFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Urdf => "urdf",
+            ExportFormat::Mjcf => "xml",
+        }
+    }
+}
+
+#[derive(Event)]
+pub struct ExportRobotRequest {
+    pub slot: usize,
+    pub format: ExportFormat,
+}
+
+// スロット内のモデルごとに 1 フォルダ書き出す
+#[derive(Event)]
+pub struct RobotExported {
+    pub slot: usize,
+    pub result: Result<Vec<PathBuf>, String>,
+}
+
+pub struct ExportPlugin;
+
+impl Plugin for ExportPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<ExportRobotRequest>()
+            .add_event::<RobotExported>()
+            .add_systems(Update, export_robots);
+    }
+}
+
+type LinkState = (
+    Option<&'static AdditionalMassProperties>,
+    Option<&'static Collider>,
+    Option<&'static Children>,
+);
+
+#[allow(clippy::too_many_arguments)]
+fn export_robots(
+    mut requests: EventReader<ExportRobotRequest>,
+    registry: Res<RobotRegistry>,
+    sources: Query<&RobotSource>,
+    links: Query<LinkState>,
+    joints: Query<(&ImpulseJoint, &RobotJoint)>,
+    visuals: Query<(&LinkVisual, &Handle<StandardMaterial>)>,
+    materials: Res<Assets<StandardMaterial>>,
+    search_paths: Res<ModelSearchPaths>,
+    mut exported: EventWriter<RobotExported>,
+) {
+    for request in requests.read() {
+        // RobotSource は各モデルのルートリンクに付いている
+        let mut result = registry.get(request.slot)
+            .into_iter()
+            .flat_map(|instance| &instance.models)
+            .filter_map(|model| Some((model, model.links.values().find_map(|&link| sources.get(link).ok())?)))
+            .map(|(model, source)| {
+                let robot = runtime_robot(model, source, &links, &joints, &visuals, &materials);
+                export_robot(&robot, source, request.format, &search_paths)
+            })
+            .collect::<Result<Vec<PathBuf>, String>>();
+
+        if result.as_ref().is_ok_and(|paths| paths.is_empty()) {
+            result = Err(format!("no robot is loaded in slot {}", request.slot));
+        }
+
+        match &result {
+            Ok(paths) => info!("Exported slot {} to {:?}", request.slot, paths),
+            Err(e) => error!("Failed to export slot {}: {}", request.slot, e),
+        }
+        exported.send(RobotExported { slot: request.slot, result });
+    }
+}
+
+// 読み込んだ記述を、実行中に変更された質量・関節・コライダー・色で上書きする。
+// リンクは関節でたどらず名前で引くので、floating 関節などで拘束の無いリンクも含まれる
+fn runtime_robot(
+    model: &RobotModelEntities,
+    source: &RobotSource,
+    links: &Query<LinkState>,
+    joints: &Query<(&ImpulseJoint, &RobotJoint)>,
+    visuals: &Query<(&LinkVisual, &Handle<StandardMaterial>)>,
+    materials: &Assets<StandardMaterial>,
+) -> urdf_rs::Robot {
+    let mut robot = source.robot.clone();
+
+    for link in &mut robot.links {
+        let Some(Ok((mass, collider, link_children))) = model.links.get(&link.name).map(|&entity| links.get(entity)) else {
+            continue;
+        };
+        update_link(link, mass, collider);
+
+        for &child in link_children.into_iter().flat_map(|children| children.iter()) {
+            let Ok((visual, handle)) = visuals.get(child) else {
+                continue;
+            };
+            if let Some(material) = materials.get(handle) {
+                update_visual_color(link, visual.index, material.base_color, &source.robot.materials);
+            }
+        }
+    }
+
+    for joint in &mut robot.joints {
+        if let Some(Ok((impulse_joint, robot_joint))) = model.joints.get(&joint.name).map(|&entity| joints.get(entity)) {
+            update_joint(joint, impulse_joint, robot_joint);
+        }
+    }
+
+    robot
+}
+
+fn update_link(link: &mut urdf_rs::Link, mass: Option<&AdditionalMassProperties>, collider: Option<&Collider>) {
+    match mass {
+        // 読み込んだときのまま (下限で補った値を含む) なら元の inertial を書く
+        Some(AdditionalMassProperties::MassProperties(properties)) if *properties == inertial_mass_properties(&link.inertial) => {}
+        Some(AdditionalMassProperties::MassProperties(properties)) => {
+            // 主軸の向きを origin の回転にし、慣性テンソルは対角成分だけで表す
+            let origin = Transform::from_translation(properties.local_center_of_mass)
+                .with_rotation(properties.principal_inertia_local_frame);
+            let inertia = properties.principal_inertia;
+            link.inertial = urdf_rs::Inertial {
+                origin: transform_to_pose(&origin),
+                mass: urdf_rs::Mass { value: properties.mass as f64 },
+                inertia: urdf_rs::Inertia {
+                    ixx: inertia.x as f64,
+                    ixy: 0.0,
+                    ixz: 0.0,
+                    iyy: inertia.y as f64,
+                    iyz: 0.0,
+                    izz: inertia.z as f64,
+                },
+            };
+        }
+        Some(AdditionalMassProperties::Mass(mass)) => link.inertial.mass.value = *mass as f64,
+        None => {}
+    }
+
+    // 凸分解などメッシュ由来のコライダーは元のメッシュ参照のまま残す
+    if let Some(collisions) = collider.and_then(primitive_collisions) {
+        link.collision = collisions;
+    }
+}
+
+fn primitive_collisions(collider: &Collider) -> Option<Vec<urdf_rs::Collision>> {
+    match collider.as_unscaled_typed_shape() {
+        ColliderView::Compound(compound) => compound.shapes()
+            .map(|(translation, rotation, shape)| {
+                primitive_collision(shape, Transform::from_translation(translation).with_rotation(rotation))
+            })
+            .collect(),
+        shape => primitive_collision(shape, Transform::IDENTITY).map(|collision| vec![collision]),
+    }
+}
+
+fn primitive_collision(shape: ColliderView, transform: Transform) -> Option<urdf_rs::Collision> {
+    let geometry = match shape {
+        ColliderView::Ball(ball) => urdf_rs::Geometry::Sphere { radius: ball.radius() as f64 },
+        ColliderView::Cuboid(cuboid) => urdf_rs::Geometry::Box {
+            size: urdf_rs::Vec3((cuboid.half_extents() * 2.0).to_array().map(f64::from)),
+        },
+        ColliderView::Cylinder(cylinder) => urdf_rs::Geometry::Cylinder {
+            radius: cylinder.radius() as f64,
+            length: cylinder.half_height() as f64 * 2.0,
+        },
+        ColliderView::Capsule(capsule) => urdf_rs::Geometry::Capsule {
+            radius: capsule.radius() as f64,
+            length: capsule.half_height() as f64 * 2.0,
+        },
+        _ => return None,
+    };
+
+    let origin = transform * Transform::from_rotation(geometry_alignment(&geometry).inverse());
+    Some(urdf_rs::Collision {
+        name: None,
+        origin: transform_to_pose(&origin),
+        geometry,
+    })
+}
+
+fn update_visual_color(link: &mut urdf_rs::Link, index: usize, color: Color, named: &[urdf_rs::Material]) {
+    let name = format!("{}_visual{}", link.name, index);
+    let Some(visual) = link.visual.get_mut(index) else {
+        return;
+    };
+
+    // 名前だけで参照されていたマテリアルのテクスチャも引き継ぐ
+    let texture = visual.material.as_ref().and_then(|material| {
+        material.texture.clone().or_else(|| {
+            named.iter()
+                .find(|named| named.name == material.name)
+                .and_then(|named| named.texture.clone())
+        })
+    });
+
+    visual.material = Some(urdf_rs::Material {
+        name,
+        color: Some(urdf_rs::Color { rgba: urdf_rs::Vec4(color.as_rgba_f32().map(f64::from)) }),
+        texture,
+    });
+}
+
+fn update_joint(joint: &mut urdf_rs::Joint, impulse_joint: &ImpulseJoint, robot_joint: &RobotJoint) {
+    joint.limit.effort = robot_joint.effort as f64;
+    joint.limit.velocity = robot_joint.velocity as f64;
+
+    if let Some(limits) = impulse_joint.data.limits(robot_joint.kind.free_axis()) {
+        joint.limit.lower = limits.min as f64;
+        joint.limit.upper = limits.max as f64;
+        if joint.joint_type == urdf_rs::JointType::Continuous {
+            joint.joint_type = urdf_rs::JointType::Revolute;
+        }
+    }
+}
+
+fn export_robot(
+    robot: &urdf_rs::Robot,
+    source: &RobotSource,
+    format: ExportFormat,
+    search_paths: &ModelSearchPaths,
+) -> Result<PathBuf, String> {
+    let now: DateTime<Local> = SystemTime::now().into();
+    let name: String = robot.name.chars()
+        .map(|c| if c.is_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+        .collect();
+    let dir = Path::new(EXPORT_DIR).join(format!("{}-{}", name, now.format("%Y-%m-%d-%H-%M-%S")));
+    fs::create_dir_all(&dir).map_err(|e| format!("failed to create {}: {}", dir.display(), e))?;
+
+    let mut robot = robot.clone();
+    copy_resources(&mut robot, source, search_paths, &dir)?;
+
+    let content = match format {
+        ExportFormat::Urdf => urdf_rs::write_to_string(&robot).map_err(|e| format!("failed to write URDF: {}", e))?,
+        ExportFormat::Mjcf => {
+            let fixed_root = robot.links.iter()
+                .find(|link| !robot.joints.iter().any(|joint| joint.child.link == link.name))
+                .is_some_and(|root| source.config.fixed_links.contains(&root.name));
+            mjcf::write_robot(&robot, fixed_root, |path| PathBuf::from(path))
+        }
+    };
+
+    let path = dir.join(format!("{}.{}", name, format.extension()));
+    fs::write(&path, content).map_err(|e| format!("failed to write {}: {}", path.display(), e))?;
+    Ok(path)
+}
+
+// 参照しているメッシュ・テクスチャを書き出し先にコピーし、相対パスで参照し直す
+fn copy_resources(
+    robot: &mut urdf_rs::Robot,
+    source: &RobotSource,
+    search_paths: &ModelSearchPaths,
+    dir: &Path,
+) -> Result<(), String> {
+    let mut copied: HashMap<PathBuf, String> = HashMap::new();
+    let mut copy = |filename: &mut String| -> Result<(), String> {
+        let file = resource_file(filename, &source.description_path, search_paths);
+        if !file.is_file() {
+            warn!("Resource {} was not found; keeping the original reference", filename);
+            return Ok(());
+        }
+
+        if let Some(relative) = copied.get(&file) {
+            *filename = relative.clone();
+            return Ok(());
+        }
+
+        let file_name = file.file_name().unwrap_or_default().to_string_lossy().into_owned();
+        let relative = if copied.values().any(|relative| relative.ends_with(&format!("/{}", file_name))) {
+            format!("{}/{}_{}", MESH_DIR, copied.len(), file_name)
+        } else {
+            format!("{}/{}", MESH_DIR, file_name)
+        };
+
+        let target = dir.join(&relative);
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent).map_err(|e| format!("failed to create {}: {}", parent.display(), e))?;
+        }
+        fs::copy(&file, &target).map_err(|e| format!("failed to copy {}: {}", file.display(), e))?;
+
+        copied.insert(file, relative.clone());
+        *filename = relative;
+        Ok(())
+    };
+
+    for link in &mut robot.links {
+        for visual in &mut link.visual {
+            if let urdf_rs::Geometry::Mesh { filename, .. } = &mut visual.geometry {
+                copy(filename)?;
+            }
+            if let Some(texture) = visual.material.as_mut().and_then(|material| material.texture.as_mut()) {
+                if !texture.filename.is_empty() {
+                    copy(&mut texture.filename)?;
+                }
+            }
+        }
+        for collision in &mut link.collision {
+            if let urdf_rs::Geometry::Mesh { filename, .. } = &mut collision.geometry {
+                copy(filename)?;
+            }
+        }
+    }
+
+    for texture in robot.materials.iter_mut().filter_map(|material| material.texture.as_mut()) {
+        if !texture.filename.is_empty() {
+            copy(&mut texture.filename)?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::ecs::system::RunSystemOnce;
+    use crate::robot::joint::JointKind;
+
+    const URDF: &str = r#"
+        <robot name="rover">
+          <link name="base">
+            <inertial>
+              <mass value="0"/>
+              <inertia ixx="0" ixy="0" ixz="0" iyy="0" iyz="0" izz="0"/>
+            </inertial>
+          </link>
+          <link name="arm">
+            <inertial>
+              <origin xyz="0 0 0.1" rpy="0 0 0"/>
+              <mass value="1.5"/>
+              <inertia ixx="0.02" ixy="0" ixz="0" iyy="0.03" iyz="0" izz="0.04"/>
+            </inertial>
+          </link>
+          <link name="payload">
+            <inertial>
+              <mass value="0.5"/>
+              <inertia ixx="0.001" ixy="0" ixz="0" iyy="0.001" iyz="0" izz="0.001"/>
+            </inertial>
+          </link>
+          <joint name="shoulder" type="revolute">
+            <parent link="base"/>
+            <child link="arm"/>
+            <axis xyz="1 0 0"/>
+            <limit lower="-1" upper="1" effort="10" velocity="2"/>
+          </joint>
+          <joint name="free" type="floating">
+            <parent link="base"/>
+            <child link="payload"/>
+          </joint>
+        </robot>
+    "#;
+
+    fn link<'a>(robot: &'a urdf_rs::Robot, name: &str) -> &'a urdf_rs::Link {
+        robot.links.iter().find(|link| link.name == name).unwrap()
+    }
+
+    fn joint<'a>(robot: &'a urdf_rs::Robot, name: &str) -> &'a urdf_rs::Joint {
+        robot.joints.iter().find(|joint| joint.name == name).unwrap()
+    }
+
+    #[test]
+    fn exported_urdf_round_trips_runtime_changes() {
+        let robot = urdf_rs::read_from_string(URDF).unwrap();
+        let mut world = World::new();
+        world.init_resource::<Assets<StandardMaterial>>();
+
+        // base は読み込んだまま (下限で補った質量)、arm と payload は実行中に質量を変えたもの
+        let base = world.spawn(AdditionalMassProperties::MassProperties(inertial_mass_properties(&link(&robot, "base").inertial))).id();
+        let mut shoulder = RobotJoint::new("shoulder".to_string(), base, JointKind::Revolute, Vec3::X, &Transform::IDENTITY);
+        shoulder.effort = 20.0;
+        shoulder.velocity = 3.0;
+        let arm = world.spawn((
+            AdditionalMassProperties::Mass(3.0),
+            ImpulseJoint::new(base, RevoluteJointBuilder::new(Vec3::X).limits([-0.5, 0.5])),
+            shoulder,
+        )).id();
+        let payload = world.spawn(AdditionalMassProperties::Mass(0.25)).id();
+
+        let model = RobotModelEntities {
+            name: robot.name.clone(),
+            base: Some(base),
+            links: HashMap::from([("base".to_string(), base), ("arm".to_string(), arm), ("payload".to_string(), payload)]),
+            joints: HashMap::from([("shoulder".to_string(), arm)]),
+        };
+        let source = RobotSource {
+            robot: robot.clone(),
+            description_path: PathBuf::from("rover.urdf"),
+            config: Default::default(),
+        };
+
+        let exported = world.run_system_once(
+            move |links: Query<LinkState>,
+                  joints: Query<(&ImpulseJoint, &RobotJoint)>,
+                  visuals: Query<(&LinkVisual, &Handle<StandardMaterial>)>,
+                  materials: Res<Assets<StandardMaterial>>| {
+                runtime_robot(&model, &source, &links, &joints, &visuals, &materials)
+            },
+        );
+        let round_trip = urdf_rs::read_from_string(&urdf_rs::write_to_string(&exported).unwrap()).unwrap();
+
+        let names = |robot: &urdf_rs::Robot| robot.links.iter().map(|link| link.name.clone()).collect::<Vec<_>>();
+        assert_eq!(names(&round_trip), names(&robot));
+        assert_eq!(round_trip.joints.len(), robot.joints.len());
+
+        let base = &link(&round_trip, "base").inertial;
+        assert_eq!(base.mass.value, 0.0);
+        assert_eq!(base.inertia.ixx, 0.0);
+
+        let arm = &link(&round_trip, "arm").inertial;
+        assert_eq!(arm.mass.value, 3.0);
+        assert_eq!(arm.origin.xyz.0, [0.0, 0.0, 0.1]);
+        assert_eq!([arm.inertia.ixx, arm.inertia.iyy, arm.inertia.izz], [0.02, 0.03, 0.04]);
+
+        assert_eq!(link(&round_trip, "payload").inertial.mass.value, 0.25);
+
+        let shoulder = joint(&round_trip, "shoulder");
+        assert_eq!(shoulder.joint_type, urdf_rs::JointType::Revolute);
+        assert_eq!((shoulder.limit.lower, shoulder.limit.upper), (-0.5, 0.5));
+        assert_eq!((shoulder.limit.effort, shoulder.limit.velocity), (20.0, 3.0));
+        assert_eq!(joint(&round_trip, "free").joint_type, urdf_rs::JointType::Floating);
+    }
+}
@@ -0,0 +1,155 @@
+use bevy::prelude::*;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use super::loader::{LoadRobotRequest, RobotLoadFinished};
+use super::registry::{RobotRegistry, UnloadRobotRequest};
+use super::spawn::SpawnPoses;
+
+const POLL_INTERVAL_SECS: f32 = 1.0;
+// 最後の変更からこの時間だけ静かになったら書き出し完了とみなす
+const DEBOUNCE_SECS: f32 = 1.5;
+
+#[derive(Resource)]
+pub struct HotReloadSettings {
+    pub enabled: bool,
+}
+
+impl Default for HotReloadSettings {
+    fn default() -> Self {
+        Self {
+            enabled: std::env::var_os("UDON_HOT_RELOAD").is_some(),
+        }
+    }
+}
+
+#[derive(Resource)]
+pub(super) struct WatchedRobots {
+    robots: HashMap<usize, WatchedRobot>,
+    timer: Timer,
+}
+
+impl Default for WatchedRobots {
+    fn default() -> Self {
+        Self {
+            robots: HashMap::new(),
+            timer: Timer::from_seconds(POLL_INTERVAL_SECS, TimerMode::Repeating),
+        }
+    }
+}
+
+struct WatchedRobot {
+    request_path: PathBuf,
+    // 読み込みに使ったファイルだけを見る。フォルダ全体は走査しない
+    dependencies: Vec<PathBuf>,
+    snapshot: HashMap<PathBuf, SystemTime>,
+    changed_at: Option<f32>,
+}
+
+impl WatchedRobot {
+    // まだ無いファイル (設定ファイルなど) は記録されず、作られたときに差分になる
+    fn take_snapshot(&self) -> HashMap<PathBuf, SystemTime> {
+        self.dependencies.iter()
+            .filter_map(|path| {
+                let modified = fs::metadata(path).and_then(|meta| meta.modified()).ok()?;
+                Some((path.clone(), modified))
+            })
+            .collect()
+    }
+}
+
+// 読み込みに失敗したときは依存ファイルが分からないので、要求されたファイル
+// (フォルダならその直下のファイル) を見る
+fn requested_files(path: &Path) -> Vec<PathBuf> {
+    if !path.is_dir() {
+        return vec![path.to_path_buf()];
+    }
+    fs::read_dir(path)
+        .map(|entries| {
+            entries.filter_map(|entry| entry.ok())
+                .map(|entry| entry.path())
+                .filter(|path| path.is_file())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+pub(super) fn watch_loaded_robots(
+    mut finished_events: EventReader<RobotLoadFinished>,
+    mut watched: ResMut<WatchedRobots>,
+) {
+    for event in finished_events.read() {
+        // 失敗した読み込みも監視し、修正して書き出し直したら自動で再試行する。
+        // 直前に同じパスの読み込みが成功していれば、そのときの依存ファイルも見続ける
+        let mut dependencies = event.dependencies.clone();
+        if event.error.is_some() {
+            if let Some(previous) = watched.robots.get(&event.slot).filter(|previous| previous.request_path == event.path) {
+                dependencies.extend(previous.dependencies.iter().cloned());
+            }
+            dependencies.extend(requested_files(&event.path));
+            dependencies.sort();
+            dependencies.dedup();
+        }
+
+        let mut robot = WatchedRobot {
+            request_path: event.path.clone(),
+            dependencies,
+            snapshot: HashMap::new(),
+            changed_at: None,
+        };
+        robot.snapshot = robot.take_snapshot();
+        watched.robots.insert(event.slot, robot);
+    }
+}
+
+// 取り外したスロットはもう見ない
+pub(super) fn forget_unloaded_robots(
+    mut unload_events: EventReader<UnloadRobotRequest>,
+    mut watched: ResMut<WatchedRobots>,
+) {
+    for event in unload_events.read() {
+        watched.robots.remove(&event.slot);
+    }
+}
+
+pub(super) fn reload_changed_robots(
+    time: Res<Time>,
+    settings: Res<HotReloadSettings>,
+    mut watched: ResMut<WatchedRobots>,
+    spawn_poses: Res<SpawnPoses>,
+    registry: Res<RobotRegistry>,
+    mut load_events: EventWriter<LoadRobotRequest>,
+) {
+    if !settings.enabled || !watched.timer.tick(time.delta()).just_finished() {
+        return;
+    }
+
+    let now = time.elapsed_seconds();
+    let mut reloads = Vec::new();
+
+    for (&slot, robot) in watched.robots.iter_mut() {
+        let snapshot = robot.take_snapshot();
+        if snapshot != robot.snapshot {
+            robot.snapshot = snapshot;
+            robot.changed_at = Some(now);
+            continue;
+        }
+
+        if robot.changed_at.is_some_and(|changed_at| now - changed_at >= DEBOUNCE_SECS) {
+            robot.changed_at = None;
+            reloads.push((slot, robot.request_path.clone()));
+        }
+    }
+
+    for (slot, path) in reloads {
+        info!("Model files changed; reloading slot {} from {:?}", slot, path);
+        load_events.send(LoadRobotRequest {
+            path,
+            slot,
+            spawn: spawn_poses.pose(slot),
+            name: registry.get(slot).map(|instance| instance.name.clone()),
+        });
+    }
+}
@@ -1,27 +1,122 @@
+use bevy::asset::LoadState;
 use bevy::prelude::*;
-use bevy::render::mesh::VertexAttributeValues;
+use bevy::tasks::{block_on, poll_once, AsyncComputeTaskPool, Task};
+use bevy_rapier3d::parry::mass_properties::MassProperties as ParryMassProperties;
+use bevy_rapier3d::parry::math::Point;
+use bevy_rapier3d::parry::na::Matrix3;
 use bevy_rapier3d::prelude::*;
-use regex::Regex;
+use chrono::Local;
+use std::env;
+use std::fmt;
 use std::fs;
-use std::io::Write;
 use std::path::{Path, PathBuf};
 use std::collections::{HashMap, HashSet};
 use walkdir::WalkDir;
 use encoding_rs::SHIFT_JIS;
+use quick_xml::events::Event;
+use quick_xml::Reader;
 
+use crate::robot::actuator::JointActuator;
 use crate::robot::drive::DriveInput;
+use crate::robot::joint::{JointDynamics, JointKind, JointMimic, RobotJoint};
+use crate::physics::contact::{self, CollisionOwner, SelfCollisionRules};
 use crate::physics::drag::AirDrag;
+use super::cache;
+use super::collider::{self, MeshGeometry};
+use super::hot_reload::{self, HotReloadSettings, WatchedRobots};
+use super::mjcf::{self, MjcfError};
+use super::mesh_format::{self, RobotMeshLoader};
+use super::mesh_units;
+use super::model_config::{ColliderMode, DecompositionSettings, MeshUnits, ModelConfig, SelfCollisionConfig};
+use super::package::PackageResolver;
+use super::registry::{self, DuplicateRobotRequest, ReloadRobotRequest, RobotInstance, RobotModelEntities, RobotRegistry, UnloadRobotRequest};
+use super::sdf::{self, SdfError, SdfLight, SdfLightKind};
+use super::spawn::{self, DropToGround, ResetRobotRequest, SpawnPose, SpawnPoses, SpawnTransform};
+use super::srdf;
+use super::transmission::{self, Transmission};
+use super::validation::{self, IssueSeverity, ValidationIssue};
+use super::xacro::{self, XacroError};
+use super::xml;
+
+// SDF のワールドはこのスロットに読み込み、ロボット用のスロット 1.. とは分ける
+pub const WORLD_SLOT: usize = 0;
+
+// 質量・慣性が 0 のリンクでも動的剛体として破綻しないための下限
+const MIN_LINK_MASS: f32 = 1.0e-3;
+const MIN_PRINCIPAL_INERTIA: f32 = 1.0e-6;
 
 #[derive(Event)]
 pub struct LoadRobotRequest {
-    pub model_name: String,
+    pub path: PathBuf,
     pub slot: usize,
+    // ワールドの読み込みでは使わない
+    pub spawn: SpawnPose,
+    // インスタンス名。None なら説明ファイル中の名前を使う
+    pub name: Option<String>,
 }
 
-#[derive(Resource)]
-struct DeferredLoadRequest {
-    model_name: String,
-    pub slot: usize,
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LoadStage {
+    Parsing,
+    Meshes { loaded: usize, total: usize },
+    Colliders { remaining: usize },
+}
+
+#[derive(Resource, Default)]
+pub struct RobotLoadProgress {
+    pub slots: HashMap<usize, LoadStage>,
+}
+
+// 同じスロットに新しい要求が来たら古い Task を捨ててキャンセルする
+#[derive(Resource, Default)]
+struct RobotLoadTasks {
+    tasks: HashMap<usize, RobotLoadTask>,
+}
+
+struct RobotLoadTask {
+    path: PathBuf,
+    spawn: SpawnPose,
+    name: Option<String>,
+    task: Task<Result<ParsedDescription, RobotLoadError>>,
+}
+
+// URDF なら 1 モデル、SDF のワールドなら複数モデルと照明を持つ
+struct ParsedDescription {
+    description_path: PathBuf,
+    name: String,
+    is_world: bool,
+    models: Vec<ParsedModel>,
+    lights: Vec<SdfLight>,
+    issues: Vec<ValidationIssue>,
+    dependencies: Vec<PathBuf>,
+    // コライダーのキャッシュを引くためのメッシュファイルの内容のハッシュ
+    mesh_hashes: HashMap<PathBuf, u64>,
+}
+
+struct ParsedModel {
+    robot: urdf_rs::Robot,
+    config: ModelConfig,
+    // 生成位置 (ワールドならワールド原点、それ以外はスロットの位置) からの相対姿勢
+    pose: Transform,
+    transmissions: Vec<Transmission>,
+}
+
+// SDF・MJCF から URDF と同じ形に変換したモデル
+pub struct ImportedModel {
+    pub robot: urdf_rs::Robot,
+    // ルートリンクの位置・姿勢 (ワールドまたはファイルの座標系)
+    pub pose: Transform,
+    pub fixed_links: Vec<String>,
+    pub warnings: Vec<String>,
+    pub transmissions: Vec<Transmission>,
+}
+
+// ルートリンクに付け、エクスポート時に元のモデル記述を参照する
+#[derive(Component)]
+pub struct RobotSource {
+    pub robot: urdf_rs::Robot,
+    pub description_path: PathBuf,
+    pub config: ModelConfig,
 }
 
 #[derive(Component)]
@@ -29,6 +124,22 @@ pub struct RobotPart {
     pub slot: usize,
 }
 
+// ロボットを動かすときの基準になるリンク。DriveInput もここに付く
+#[derive(Component)]
+pub struct RobotBase;
+
+// ロボットのすべてのリンクに付け、同じロボットのベースリンクを指す
+#[derive(Component)]
+pub struct RobotRoot {
+    pub base: Entity,
+}
+
+// visual の子エンティティに付け、元の visual 要素の番号を控える
+#[derive(Component)]
+pub struct LinkVisual {
+    pub index: usize,
+}
+
 #[derive(Component)]
 struct PendingJoint {
     parent: Entity,
@@ -37,13 +148,152 @@ struct PendingJoint {
 }
 
 #[derive(Component)]
-struct PendingCollider {
-    transform: Transform,
+pub(super) struct PendingCollider {
+    shapes: Vec<PendingShape>,
+    mode: ColliderMode,
+    fixed: bool,
+    decomposition: DecompositionSettings,
 }
 
-#[derive(Resource, Default)]
-pub struct LoadedRobots {
-    pub robots: HashMap<usize, String>,
+enum PendingShape {
+    Mesh {
+        handle: Handle<Mesh>,
+        transform: Transform,
+        cache_key: Option<u64>,
+    },
+    // 前回の読み込みで作ったコライダー。メッシュの読み込みを待たない
+    Cached {
+        parts: Vec<(Vect, Rot, Collider)>,
+    },
+    Primitive {
+        collider: Collider,
+        transform: Transform,
+    },
+}
+
+// メッシュから作るコライダー (凸包・凸分解など) はバックグラウンドで計算する
+#[derive(Component)]
+struct ColliderTask {
+    task: Task<BuiltColliders>,
+}
+
+#[derive(Default)]
+struct BuiltColliders {
+    parts: Vec<(Vect, Rot, Collider)>,
+    // 三角形メッシュは複合形状に入れられないので別に持つ
+    trimeshes: Vec<Collider>,
+}
+
+enum ColliderJob {
+    Mesh {
+        geometry: MeshGeometry,
+        cache_key: Option<u64>,
+    },
+    Ready(Vec<(Vect, Rot, Collider)>),
+}
+
+#[derive(Debug)]
+pub enum RobotLoadError {
+    NotFound(PathBuf),
+    NoDescription(PathBuf),
+    Unreadable(PathBuf),
+    Xacro(XacroError),
+    Sdf(SdfError),
+    Mjcf(MjcfError),
+    Parse { path: PathBuf, message: String },
+    Invalid { path: PathBuf, errors: usize },
+}
+
+impl fmt::Display for RobotLoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RobotLoadError::NotFound(path) => write!(f, "robot description not found: {}", path.display()),
+            RobotLoadError::NoDescription(path) => write!(f, "no robot description (.urdf, .xacro, .sdf or MJCF) in {}", path.display()),
+            RobotLoadError::Unreadable(path) => write!(f, "failed to read {}", path.display()),
+            RobotLoadError::Xacro(e) => write!(f, "failed to expand xacro: {}", e),
+            RobotLoadError::Sdf(e) => write!(f, "failed to read SDF: {}", e),
+            RobotLoadError::Mjcf(e) => write!(f, "failed to read MJCF: {}", e),
+            RobotLoadError::Parse { path, message } => write!(f, "failed to parse URDF {}: {}", path.display(), message),
+            RobotLoadError::Invalid { path, errors } => write!(f, "{} has {} validation error(s)", path.display(), errors),
+        }
+    }
+}
+
+impl std::error::Error for RobotLoadError {}
+
+impl From<XacroError> for RobotLoadError {
+    fn from(e: XacroError) -> Self {
+        RobotLoadError::Xacro(e)
+    }
+}
+
+impl From<SdfError> for RobotLoadError {
+    fn from(e: SdfError) -> Self {
+        RobotLoadError::Sdf(e)
+    }
+}
+
+impl From<MjcfError> for RobotLoadError {
+    fn from(e: MjcfError) -> Self {
+        RobotLoadError::Mjcf(e)
+    }
+}
+
+// 読み込みが終わるたび (成功・失敗とも) に送られ、UI のレポートに表示される
+#[derive(Event)]
+pub struct RobotLoadFinished {
+    pub slot: usize,
+    pub path: PathBuf,
+    pub robot_name: Option<String>,
+    pub error: Option<RobotLoadError>,
+    pub issues: Vec<ValidationIssue>,
+    // 説明ファイル自身と、読み込みに使ったファイル (include・メッシュ・テクスチャ・設定など)
+    pub dependencies: Vec<PathBuf>,
+}
+
+#[derive(Resource, Clone)]
+pub struct ModelSearchPaths {
+    pub roots: Vec<PathBuf>,
+    pub packages: PackageResolver,
+}
+
+impl ModelSearchPaths {
+    // パッケージを追加・移動したあとに呼ぶ
+    pub fn rescan_packages(&mut self) {
+        self.packages = PackageResolver::scan(&self.roots);
+        info!("Found {} ROS packages", self.packages.package_count());
+    }
+}
+
+impl Default for ModelSearchPaths {
+    fn default() -> Self {
+        let mut roots = vec![PathBuf::from("assets/models")];
+        if let Some(extra) = env::var_os("UDON_MODEL_PATH") {
+            roots.extend(env::split_paths(&extra));
+        }
+        let mut search_paths = Self { roots, packages: PackageResolver::default() };
+        search_paths.rescan_packages();
+        search_paths
+    }
+}
+
+struct SpawnContext<'a> {
+    link_map: HashMap<String, &'a urdf_rs::Link>,
+    child_map: HashMap<String, Vec<(&'a String, &'a urdf_rs::Joint)>>,
+    description_path: &'a Path,
+    search_paths: &'a ModelSearchPaths,
+    mesh_hashes: &'a HashMap<PathBuf, u64>,
+    config: &'a ModelConfig,
+    material_handles: HashMap<String, Handle<StandardMaterial>>,
+    default_material: Handle<StandardMaterial>,
+    slot: usize,
+}
+
+// リンク名・関節名から生成したエンティティを引く
+#[derive(Default)]
+struct SpawnedEntities {
+    links: HashMap<String, Entity>,
+    joints: HashMap<String, Entity>,
 }
 
 pub struct RobotLoaderPlugin;
@@ -54,24 +304,47 @@ impl Plugin for RobotLoaderPlugin {
             app.add_plugins(bevy_stl::StlPlugin);
         }
         app
+            .init_asset_loader::<RobotMeshLoader>()
             .add_event::<LoadRobotRequest>()
-            .init_resource::<LoadedRobots>()
+            .add_event::<RobotLoadFinished>()
+            .add_event::<UnloadRobotRequest>()
+            .add_event::<ReloadRobotRequest>()
+            .add_event::<DuplicateRobotRequest>()
+            .init_resource::<RobotRegistry>()
+            .init_resource::<ModelSearchPaths>()
+            .init_resource::<RobotLoadProgress>()
+            .init_resource::<RobotLoadTasks>()
+            .init_resource::<HotReloadSettings>()
+            .init_resource::<WatchedRobots>()
+            .init_resource::<SpawnPoses>()
+            .add_event::<ResetRobotRequest>()
+            .add_systems(Update, (
+                registry::handle_reload_requests,
+                handle_unload_request,
+                handle_load_request,
+                poll_load_tasks,
+                apply_mesh_colliders,
+                finish_mesh_colliders,
+                update_load_progress,
+            ).chain())
             .add_systems(Update, (
-                handle_load_request, 
-                load_deferred_robot, 
-                apply_mesh_colliders
+                hot_reload::watch_loaded_robots.after(poll_load_tasks),
+                hot_reload::forget_unloaded_robots,
+                hot_reload::reload_changed_robots.before(handle_load_request),
+                spawn::drop_robots_to_ground.after(finish_mesh_colliders),
+                spawn::reset_robots,
             ));
     }
 }
 
-fn read_file_to_string_smart(path: &Path) -> Option<String> {
+pub(super) fn read_file_to_string_smart(path: &Path) -> Option<String> {
     match fs::read(path) {
         Ok(bytes) => {
             if let Ok(utf8_str) = String::from_utf8(bytes.clone()) {
                 return Some(utf8_str);
             }
             let (cow, _encoding_used, _had_errors) = SHIFT_JIS.decode(&bytes);
-            return Some(cow.into_owned());
+            Some(cow.into_owned())
         }
         Err(e) => {
             error!("Failed to read file {:?}: {}", path, e);
@@ -80,130 +353,668 @@ fn read_file_to_string_smart(path: &Path) -> Option<String> {
     }
 }
 
+// 今のインスタンスは新しいモデルが読めるまで残し、読み込みに失敗したらそのまま使い続ける
 fn handle_load_request(
-    mut commands: Commands,
     mut load_events: EventReader<LoadRobotRequest>,
-    robot_parts_query: Query<(Entity, &RobotPart)>,
+    mut load_tasks: ResMut<RobotLoadTasks>,
+    mut progress: ResMut<RobotLoadProgress>,
+    mut spawn_poses: ResMut<SpawnPoses>,
+    search_paths: Res<ModelSearchPaths>,
 ) {
     for event in load_events.read() {
-        info!("Request received. Clearing slot {} and scheduling load for: {}", event.slot, event.model_name);
+        info!("Request received. Scheduling load of {:?} into slot {}", event.path, event.slot);
 
-        for (entity, part) in robot_parts_query.iter() {
-            if part.slot == event.slot {
-                commands.entity(entity).despawn_recursive();
-            }
+        cancel_load(&mut load_tasks, event.slot);
+        if event.slot != WORLD_SLOT {
+            spawn_poses.poses.insert(event.slot, event.spawn);
         }
 
-        commands.insert_resource(DeferredLoadRequest {
-            model_name: event.model_name.clone(),
-            slot: event.slot,
+        let path = event.path.clone();
+        let search_paths = search_paths.clone();
+        let task = AsyncComputeTaskPool::get().spawn(async move { parse_robot(&path, &search_paths) });
+
+        load_tasks.tasks.insert(event.slot, RobotLoadTask {
+            path: event.path.clone(),
+            spawn: event.spawn,
+            name: event.name.clone(),
+            task,
         });
+        progress.slots.insert(event.slot, LoadStage::Parsing);
     }
 }
 
-fn load_deferred_robot(
+fn handle_unload_request(
     mut commands: Commands,
-    deferred_request: Option<Res<DeferredLoadRequest>>,
+    mut unload_events: EventReader<UnloadRobotRequest>,
+    robot_parts_query: Query<(Entity, &RobotPart)>,
+    mut load_tasks: ResMut<RobotLoadTasks>,
+    mut progress: ResMut<RobotLoadProgress>,
+    mut registry: ResMut<RobotRegistry>,
+) {
+    for event in unload_events.read() {
+        clear_slot(&mut commands, &robot_parts_query, &mut load_tasks, event.slot);
+        progress.slots.remove(&event.slot);
+        if let Some(instance) = registry.remove(event.slot) {
+            info!("Unloaded {} from slot {}", instance.name, event.slot);
+        }
+    }
+}
+
+// スロットのエンティティを消し、読み込み中のタスクがあれば捨てる
+fn clear_slot(
+    commands: &mut Commands,
+    robot_parts_query: &Query<(Entity, &RobotPart)>,
+    load_tasks: &mut RobotLoadTasks,
+    slot: usize,
+) {
+    for (entity, part) in robot_parts_query.iter() {
+        if part.slot == slot {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+    cancel_load(load_tasks, slot);
+}
+
+fn cancel_load(load_tasks: &mut RobotLoadTasks, slot: usize) {
+    if let Some(previous) = load_tasks.tasks.remove(&slot) {
+        info!("Cancelling load of {:?} in slot {}", previous.path, slot);
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn poll_load_tasks(
+    mut commands: Commands,
+    mut load_tasks: ResMut<RobotLoadTasks>,
     asset_server: Res<AssetServer>,
     mut materials: ResMut<Assets<StandardMaterial>>,
-    mut loaded_robots: ResMut<LoadedRobots>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut registry: ResMut<RobotRegistry>,
+    mut progress: ResMut<RobotLoadProgress>,
+    search_paths: Res<ModelSearchPaths>,
+    mut finished_events: EventWriter<RobotLoadFinished>,
 ) {
-    let request = match deferred_request {
-        Some(r) => r,
-        None => return,
+    let mut completed = Vec::new();
+    for (&slot, load_task) in load_tasks.tasks.iter_mut() {
+        if let Some(result) = block_on(poll_once(&mut load_task.task)) {
+            completed.push((slot, result));
+        }
+    }
+
+    for (slot, result) in completed {
+        let Some(RobotLoadTask { path, spawn, name, .. }) = load_tasks.tasks.remove(&slot) else {
+            continue;
+        };
+        progress.slots.remove(&slot);
+
+        let mut finished = RobotLoadFinished {
+            slot,
+            path: path.clone(),
+            robot_name: None,
+            error: None,
+            issues: Vec::new(),
+            dependencies: Vec::new(),
+        };
+
+        let parsed = match result {
+            Ok(parsed) => parsed,
+            Err(e) => {
+                error!("Failed to load robot: {}", e);
+                keep_previous(&registry, slot);
+                finished.error = Some(e);
+                finished_events.send(finished);
+                continue;
+            }
+        };
+
+        info!("Robot description parsed successfully: {}", parsed.name);
+        finished.robot_name = Some(parsed.name.clone());
+
+        for issue in &parsed.issues {
+            match issue.severity {
+                IssueSeverity::Warning => warn!("{}: {}", parsed.name, issue.message),
+                IssueSeverity::Error => error!("{}: {}", parsed.name, issue.message),
+            }
+        }
+
+        if validation::has_errors(&parsed.issues) {
+            let errors = parsed.issues.iter().filter(|issue| issue.severity == IssueSeverity::Error).count();
+            finished.error = Some(RobotLoadError::Invalid { path: parsed.description_path.clone(), errors });
+            keep_previous(&registry, slot);
+        } else {
+            if let Some(previous) = registry.remove(slot) {
+                info!("Replacing {} in slot {}", previous.name, slot);
+                for entity in previous.parts {
+                    commands.entity(entity).despawn_recursive();
+                }
+            }
+
+            let origin = if parsed.is_world { Transform::IDENTITY } else { spawn.transform() };
+            let mut instance = RobotInstance {
+                slot,
+                name: registry.unique_name(name.as_deref().unwrap_or(&parsed.name), slot),
+                description_name: parsed.name.clone(),
+                source: path,
+                loaded_at: Local::now(),
+                spawn,
+                models: Vec::new(),
+                parts: Vec::new(),
+            };
+            for model in &parsed.models {
+                let entities = spawn_robot_recursive_root(
+                    &mut commands,
+                    &asset_server,
+                    &mut materials,
+                    &mut meshes,
+                    &model.robot,
+                    &model.transmissions,
+                    &parsed.description_path,
+                    &search_paths,
+                    &parsed.mesh_hashes,
+                    &model.config,
+                    origin * model.pose,
+                    slot,
+                );
+                let Some(entities) = entities else {
+                    continue;
+                };
+                if let Some(base) = entities.base.filter(|_| spawn.drop_to_ground && !parsed.is_world) {
+                    commands.entity(base).insert(DropToGround);
+                }
+                instance.parts.extend(entities.links.values().copied());
+                instance.models.push(entities);
+            }
+            instance.parts.extend(spawn_lights(&mut commands, &parsed.lights, slot));
+            info!("Registered {} in slot {}", instance.name, slot);
+            registry.insert(instance);
+            progress.slots.insert(slot, LoadStage::Meshes { loaded: 0, total: 0 });
+        }
+
+        finished.issues = parsed.issues;
+        finished.dependencies = parsed.dependencies;
+        finished_events.send(finished);
+    }
+}
+
+fn keep_previous(registry: &RobotRegistry, slot: usize) {
+    if let Some(previous) = registry.get(slot) {
+        warn!("Keeping {} in slot {} from before the failed load", previous.name, slot);
+    }
+}
+
+// バックグラウンドで実行される: 展開・パース・検証・設定読み込みまで
+fn parse_robot(path: &Path, search_paths: &ModelSearchPaths) -> Result<ParsedDescription, RobotLoadError> {
+    let description_path = if path.is_dir() {
+        find_main_description(path).ok_or_else(|| RobotLoadError::NoDescription(path.to_path_buf()))?
+    } else {
+        path.to_path_buf()
     };
 
-    info!("Executing deferred load for: {} in slot {}", request.model_name, request.slot);
+    if !description_path.is_file() {
+        return Err(RobotLoadError::NotFound(description_path));
+    }
+
+    info!("Processing robot description: {:?}", description_path);
+    let config = ModelConfig::load_for(&description_path);
 
-    let model_dir = Path::new("assets/models").join(&request.model_name);
-    
-    if !model_dir.exists() {
-        error!("Model directory not found: {:?}", model_dir);
-        commands.remove_resource::<DeferredLoadRequest>();
-        return;
+    let mut parsed = ParsedDescription {
+        description_path,
+        name: String::new(),
+        is_world: false,
+        models: Vec::new(),
+        lights: Vec::new(),
+        issues: Vec::new(),
+        dependencies: Vec::new(),
+        mesh_hashes: HashMap::new(),
+    };
+
+    if sdf::is_sdf(&parsed.description_path) {
+        let document = sdf::read_file(&parsed.description_path, &search_paths.roots)?;
+        parsed.name = document.name;
+        parsed.is_world = document.is_world;
+        parsed.lights = document.lights;
+        add_imported_models(&mut parsed, document.models, &config);
+    } else if mjcf::is_mjcf(&parsed.description_path) {
+        let document = mjcf::read_file(&parsed.description_path)?;
+        parsed.name = document.name;
+        parsed.lights = document.lights;
+        add_imported_models(&mut parsed, document.models, &config);
+    } else {
+        let (urdf_content, inputs) = read_robot_description(&parsed.description_path, &search_paths.packages)?;
+        parsed.dependencies.extend(inputs);
+        let robot = match urdf_rs::read_from_string(&urdf_content) {
+            Ok(robot) => robot,
+            Err(e) => return Err(RobotLoadError::Parse { path: parsed.description_path, message: e.to_string() }),
+        };
+        parsed.name = robot.name.clone();
+        let transmissions = read_transmissions(
+            &parsed.description_path,
+            &urdf_content,
+            &search_paths.packages,
+            &mut parsed.issues,
+            &mut parsed.dependencies,
+        );
+        parsed.models.push(ParsedModel { robot, config, pose: Transform::IDENTITY, transmissions });
     }
 
-    if let Some(xacro_path) = find_main_xacro(&model_dir) {
-        info!("Processing main xacro file: {:?}", xacro_path);
-        let urdf_content = convert_xacro_to_urdf_string(&xacro_path);
-        
-        let urdf_path = xacro_path.with_extension("urdf");
-        if let Ok(mut file) = fs::File::create(&urdf_path) {
-            let _ = file.write_all(urdf_content.as_bytes());
+    let prefix_names = parsed.models.len() > 1;
+    for model in &mut parsed.models {
+        let unit_issue = apply_mesh_units(model, &parsed.description_path, search_paths);
+        let collision_issues = apply_self_collision(model, &parsed.description_path, &search_paths.packages);
+        let issues = validation::validate_robot(&model.robot, |filename| {
+            resource_file(filename, &parsed.description_path, search_paths).is_file()
+        });
+        let issues = unit_issue.into_iter()
+            .chain(collision_issues)
+            .chain(issues)
+            .chain(validation::validate_transmissions(&model.robot, &model.transmissions));
+        parsed.issues.extend(issues.map(|mut issue| {
+            if prefix_names {
+                issue.message = format!("{}: {}", model.robot.name, issue.message);
+            }
+            issue
+        }));
+        parsed.dependencies.extend(resource_files(&model.robot, &parsed.description_path, search_paths));
+        parsed.dependencies.extend(model.config.srdf_path(&parsed.description_path).filter(|path| path.is_file()));
+    }
+    // 設定ファイルはまだ無くても監視し、作られたら読み直す
+    parsed.dependencies.push(parsed.description_path.clone());
+    parsed.dependencies.push(ModelConfig::path_for(&parsed.description_path));
+    parsed.dependencies.sort();
+    parsed.dependencies.dedup();
+    parsed.mesh_hashes = parsed.dependencies.iter()
+        .filter(|path| mesh_format::is_supported_mesh(&path.to_string_lossy()))
+        .filter_map(|path| Some((path.clone(), cache::file_hash(path)?)))
+        .collect();
+
+    Ok(parsed)
+}
+
+fn add_imported_models(parsed: &mut ParsedDescription, models: Vec<ImportedModel>, config: &ModelConfig) {
+    for model in models {
+        parsed.issues.extend(model.warnings.into_iter().map(|message| ValidationIssue {
+            severity: IssueSeverity::Warning,
+            message: format!("{}: {}", model.robot.name, message),
+        }));
+
+        let mut model_config = config.clone();
+        model_config.fixed_links.extend(model.fixed_links);
+        parsed.models.push(ParsedModel {
+            robot: model.robot,
+            config: model_config,
+            pose: model.pose,
+            transmissions: model.transmissions,
+        });
+    }
+}
+
+// CAD から書き出したままのミリメートル単位などのメッシュを見つけて報告し、設定によっては補正する
+fn apply_mesh_units(model: &mut ParsedModel, description_path: &Path, search_paths: &ModelSearchPaths) -> Option<ValidationIssue> {
+    let units = model.config.mesh_units;
+    if let Some(scale) = units.scale() {
+        if scale != 1.0 {
+            info!("Scaling meshes of {} by {} ({:?})", model.robot.name, scale, units);
+            mesh_units::scale_meshes(&mut model.robot, scale);
         }
+        return None;
+    }
 
-        match urdf_rs::read_from_string(&urdf_content) {
-            Ok(robot) => {
-                info!("URDF parsed successfully. Robot name: {}", robot.name);
-                spawn_robot_recursive_root(&mut commands, &asset_server, &mut materials, &robot, request.slot);
-                loaded_robots.robots.insert(request.slot, request.model_name.clone());
-            },
-            Err(e) => error!("Failed to parse generated URDF: {:?}", e),
+    let correction = mesh_units::detect_mesh_units(&model.robot, |filename| {
+        mesh_format::mesh_bounds(&resource_file(filename, description_path, search_paths))
+    })?;
+    let finding = format!(
+        "meshes look like they are in {:?} (median mesh size {:.2} m, robot size {:.3} m)",
+        correction.units,
+        correction.mesh_size,
+        correction.robot_size,
+    );
+
+    let message = if units == MeshUnits::AutoCorrect {
+        mesh_units::scale_meshes(&mut model.robot, correction.scale);
+        format!("{}; scaled them by {}", finding, correction.scale)
+    } else {
+        let config_name = ModelConfig::path_for(description_path)
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .into_owned();
+        format!(
+            "{}; set mesh_units: AutoCorrect or {:?} in {} to scale them by {}",
+            finding,
+            correction.units,
+            config_name,
+            correction.scale,
+        )
+    };
+    Some(ValidationIssue { severity: IssueSeverity::Warning, message })
+}
+
+// SRDF の disable_collisions を設定に足し、どのリンクにも当たらない名前を報告する
+fn apply_self_collision(model: &mut ParsedModel, description_path: &Path, packages: &PackageResolver) -> Vec<ValidationIssue> {
+    let mut issues = Vec::new();
+
+    if let Some(path) = model.config.srdf_path(description_path) {
+        let content = if is_xacro(&path) {
+            xacro::expand_file_with_inputs(&path, packages)
+                .map(|expansion| expansion.urdf)
+                .map_err(|e| e.to_string())
+        } else {
+            read_file_to_string_smart(&path).ok_or_else(|| "the file could not be read".to_string())
+        };
+        match content.and_then(|content| srdf::parse_disabled_collisions(&content)) {
+            Ok(pairs) => {
+                info!("Read {} disabled collision pairs from {:?}", pairs.len(), path);
+                model.config.self_collision.disable.extend(pairs);
+            }
+            Err(e) => issues.push(ValidationIssue {
+                severity: IssueSeverity::Warning,
+                message: format!("could not read collision rules from {}: {}", path.display(), e),
+            }),
+        }
+    }
+
+    let rules = &model.config.self_collision;
+    let mut unknown: Vec<&str> = rules.disable.iter()
+        .chain(&rules.allow)
+        .flat_map(|(a, b)| [a.as_str(), b.as_str()])
+        .filter(|name| !model.robot.links.iter().any(|link| link.name == *name))
+        .collect();
+    unknown.sort();
+    unknown.dedup();
+    issues.extend(unknown.into_iter().map(|name| ValidationIssue {
+        severity: IssueSeverity::Warning,
+        message: format!("self-collision rule refers to unknown link '{}'", name),
+    }));
+    issues
+}
+
+// 展開後の URDF に transmission が無ければ、同じフォルダにある同じ名前の .trans を読む
+fn read_transmissions(
+    description_path: &Path,
+    urdf_content: &str,
+    packages: &PackageResolver,
+    issues: &mut Vec<ValidationIssue>,
+    dependencies: &mut Vec<PathBuf>,
+) -> Vec<Transmission> {
+    let mut sources = vec![(description_path.to_path_buf(), Ok(urdf_content.to_string()))];
+    if !urdf_content.contains("<transmission") {
+        for path in transmission::sibling_transmission_files(description_path) {
+            dependencies.push(path.clone());
+            let content = xacro::expand_file_with_inputs(&path, packages)
+                .map(|expansion| {
+                    dependencies.extend(expansion.inputs);
+                    expansion.urdf
+                })
+                .map_err(|e| e.to_string());
+            sources.push((path, content));
         }
+    }
+
+    let mut transmissions = Vec::new();
+    for (path, content) in sources {
+        let parsed = content.and_then(|content| transmission::parse_transmissions(&content));
+        match parsed {
+            Ok((found, warnings)) => {
+                transmissions.extend(found);
+                issues.extend(warnings.into_iter().map(|message| ValidationIssue {
+                    severity: IssueSeverity::Warning,
+                    message,
+                }));
+            }
+            Err(e) => issues.push(ValidationIssue {
+                severity: IssueSeverity::Warning,
+                message: format!("could not read transmissions from {}: {}", path.display(), e),
+            }),
+        }
+    }
+    transmissions
+}
+
+// xacro の展開結果はメモリ上にだけ持ち、モデルフォルダには書き出さない。
+// 展開結果と、展開に使ったファイル (自身と include したもの) を返す
+fn read_robot_description(path: &Path, packages: &PackageResolver) -> Result<(String, Vec<PathBuf>), RobotLoadError> {
+    if is_xacro(path) {
+        if let Some(cached) = cache::read_description(path, packages) {
+            info!("Using cached expansion of {:?}", path);
+            return Ok(cached);
+        }
+        Ok(convert_xacro_to_urdf_string(path, packages)?)
     } else {
-        error!("No valid .xacro file found in {:?}", model_dir);
+        let content = read_file_to_string_smart(path).ok_or_else(|| RobotLoadError::Unreadable(path.to_path_buf()))?;
+        Ok((content, vec![path.to_path_buf()]))
+    }
+}
+
+fn is_xacro(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "xacro")
+}
+
+fn is_robot_description(path: &Path) -> bool {
+    // include される断片 (アセットだけのファイル) は worldbody を持たない
+    if mjcf::is_mjcf(path) {
+        return read_file_to_string_smart(path).is_some_and(|content| content.contains("<worldbody"));
     }
 
-    commands.remove_resource::<DeferredLoadRequest>();
+    // ワールドは環境として別に並べる
+    if sdf::is_sdf(path) {
+        return read_file_to_string_smart(path)
+            .and_then(|content| xml::parse_str(&content).ok())
+            .is_some_and(|root| root.name == "sdf" && root.child("model").is_some());
+    }
+
+    let is_urdf_file = path.extension().is_some_and(|ext| ext == "urdf" || ext == "xacro") && !srdf::is_srdf(path);
+    is_urdf_file && read_file_to_string_smart(path).is_some_and(|content| builds_robot(&content))
 }
 
-fn find_main_xacro(root: &Path) -> Option<PathBuf> {
-    for entry in WalkDir::new(root).into_iter().filter_map(|e| e.ok()) {
-        let path = entry.path();
-        if let Some(ext) = path.extension() {
-            if ext == "xacro" {
-                let file_name = path.file_stem().unwrap_or_default().to_string_lossy();
-                if !file_name.contains("materials") && !file_name.contains("trans") {
-                    return Some(path.to_path_buf());
-                }
+// materials.xacro や *.trans のような断片ファイルも <robot> を根に持つが、定義だけで
+// リンクを作らない。link を直接書くか、マクロを呼び出していれば本体とみなす
+fn builds_robot(content: &str) -> bool {
+    let mut reader = Reader::from_str(content);
+    // 開いている要素ごとに、根から条件分岐だけを通ってたどり着けるか
+    let mut open: Vec<bool> = Vec::new();
+
+    loop {
+        let (start, has_children) = match reader.read_event() {
+            Ok(Event::Start(start)) => (start, true),
+            Ok(Event::Empty(start)) => (start, false),
+            Ok(Event::End(_)) => {
+                open.pop();
+                continue;
             }
+            Ok(Event::Eof) | Err(_) => return false,
+            Ok(_) => continue,
+        };
+        let name = String::from_utf8_lossy(start.name().as_ref()).into_owned();
+
+        let reachable = match open.last() {
+            None if matches!(name.as_str(), "robot" | "xacro:robot") => true,
+            None => return false,
+            Some(false) => false,
+            Some(true) if creates_links(&name) => return true,
+            Some(true) => matches!(name.as_str(), "xacro:if" | "xacro:unless"),
+        };
+        if has_children {
+            open.push(reachable);
         }
     }
-    None
 }
 
-fn convert_xacro_to_urdf_string(path: &Path) -> String {
-    let content = match read_file_to_string_smart(path) {
-        Some(c) => c,
-        None => return String::new(),
+fn creates_links(name: &str) -> bool {
+    match name.strip_prefix("xacro:") {
+        None => name == "link",
+        Some(directive) => !matches!(
+            directive,
+            "macro" | "property" | "arg" | "include" | "insert_block" | "attribute" | "element" | "if" | "unless"
+        ),
+    }
+}
+
+fn is_world_description(path: &Path) -> bool {
+    sdf::is_sdf(path) && read_file_to_string_smart(path).is_some_and(|content| content.contains("<world"))
+}
+
+fn is_generated_urdf(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "urdf")
+        && (path.with_extension("xacro").exists() || path.with_extension("urdf.xacro").exists())
+}
+
+fn find_main_description(root: &Path) -> Option<PathBuf> {
+    let candidates: Vec<PathBuf> = WalkDir::new(root)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .map(|entry| entry.into_path())
+        .filter(|path| is_robot_description(path))
+        .collect();
+
+    candidates.iter()
+        .find(|path| is_xacro(path))
+        .or_else(|| candidates.first())
+        .cloned()
+}
+
+pub fn discover_robot_descriptions(roots: &[PathBuf]) -> Vec<PathBuf> {
+    discover_files(roots, |path| !is_generated_urdf(path) && is_robot_description(path))
+}
+
+pub fn discover_world_descriptions(roots: &[PathBuf]) -> Vec<PathBuf> {
+    discover_files(roots, is_world_description)
+}
+
+fn discover_files(roots: &[PathBuf], filter: impl Fn(&Path) -> bool) -> Vec<PathBuf> {
+    let mut found: Vec<PathBuf> = roots.iter()
+        .filter(|root| root.is_dir())
+        .flat_map(|root| WalkDir::new(root).into_iter().filter_map(|e| e.ok()))
+        .map(|entry| entry.into_path())
+        .filter(|path| filter(path))
+        .collect();
+
+    found.sort();
+    found.dedup();
+    found
+}
+
+fn convert_xacro_to_urdf_string(path: &Path, packages: &PackageResolver) -> Result<(String, Vec<PathBuf>), XacroError> {
+    let expansion = xacro::expand_file_with_inputs(path, packages)?;
+    cache::write_description(path, &expansion);
+    Ok((expansion.urdf, expansion.inputs))
+}
+
+fn resolve_resource_path(filename: &str, description_path: &Path, search_paths: &ModelSearchPaths) -> String {
+    let filename = filename.replace('\\', "/");
+
+    let resolved = if let Some(rest) = filename.strip_prefix("package://") {
+        let (package, relative) = rest.split_once('/').unwrap_or((rest, ""));
+        match search_paths.packages.find(package, description_path) {
+            Some(dir) => dir.join(relative),
+            None => {
+                // 見つからないときは最初のルート直下にあるものとして扱い、検証で欠落として報告させる
+                warn!("Package '{}' not found for {}", package, filename);
+                search_paths.roots.first().map(PathBuf::as_path).unwrap_or(Path::new(".")).join(rest)
+            }
+        }
+    } else if let Some(rest) = filename.strip_prefix("file://") {
+        PathBuf::from(rest)
+    } else {
+        description_path.parent().unwrap_or(Path::new(".")).join(&filename)
     };
-    
-    let re_find = Regex::new(r"\$\(find\s+([^\)]+)\)").unwrap();
-    let resolved_find = re_find.replace_all(&content, |caps: &regex::Captures| {
-        let pkg_name = &caps[1];
-        format!("assets/models/{}", pkg_name)
-    });
 
-    let re_include = Regex::new(r#"<xacro:include\s+filename="([^"]+)"\s*/>"#).unwrap();
-    let final_urdf = re_include.replace_all(&resolved_find, |caps: &regex::Captures| {
-        let include_path_str = &caps[1];
-        let include_path = Path::new(include_path_str);
-        
-        if let Some(raw) = read_file_to_string_smart(include_path) {
-            let resolved = re_find.replace_all(&raw, |caps: &regex::Captures| {
-                let pkg_name = &caps[1];
-                format!("assets/models/{}", pkg_name)
-            });
-            let re_xml = Regex::new(r"<\?xml[^>]*\?>").unwrap();
-            let no_decl = re_xml.replace(&resolved, "");
-            let re_robot_s = Regex::new(r"<\s*robot[^>]*>").unwrap();
-            let no_s = re_robot_s.replace(&no_decl, "");
-            let re_robot_e = Regex::new(r"<\s*/\s*robot\s*>").unwrap();
-            re_robot_e.replace(&no_s, "").to_string()
-        } else {
-            String::new()
+    xacro::absolute_path(&resolved).to_string_lossy().replace('\\', "/")
+}
+
+// アセットとしてではなく、ファイルシステム上の場所として解決する
+pub(super) fn resource_file(filename: &str, description_path: &Path, search_paths: &ModelSearchPaths) -> PathBuf {
+    PathBuf::from(resolve_resource_path(filename, description_path, search_paths))
+}
+
+fn resource_files(robot: &urdf_rs::Robot, description_path: &Path, search_paths: &ModelSearchPaths) -> Vec<PathBuf> {
+    let meshes = robot.links.iter()
+        .flat_map(|link| {
+            link.visual.iter().map(|v| &v.geometry)
+                .chain(link.collision.iter().map(|c| &c.geometry))
+        })
+        .filter_map(|geometry| match geometry {
+            urdf_rs::Geometry::Mesh { filename, .. } => Some(filename),
+            _ => None,
+        });
+
+    let textures = robot.materials.iter()
+        .chain(robot.links.iter().flat_map(|link| link.visual.iter().filter_map(|v| v.material.as_ref())))
+        .filter_map(|material| material.texture.as_ref())
+        .map(|texture| &texture.filename)
+        .filter(|filename| !filename.is_empty());
+
+    let mut files: Vec<PathBuf> = meshes.chain(textures)
+        .map(|filename| resource_file(filename, description_path, search_paths))
+        .filter(|path| path.is_file())
+        .collect();
+    files.sort();
+    files.dedup();
+    files
+}
+
+fn spawn_lights(commands: &mut Commands, lights: &[SdfLight], slot: usize) -> Vec<Entity> {
+    lights.iter().map(|light| {
+        let direction = light.pose.rotation * light.direction;
+        let up = if direction.cross(Vec3::Y).length_squared() < 1.0e-6 { Vec3::Z } else { Vec3::Y };
+        let transform = Transform::from_translation(light.pose.translation).looking_to(direction, up);
+        let part = RobotPart { slot };
+
+        match light.kind {
+            SdfLightKind::Directional => {
+                commands.spawn((DirectionalLightBundle {
+                    directional_light: DirectionalLight {
+                        color: light.color,
+                        shadows_enabled: light.cast_shadows,
+                        ..default()
+                    },
+                    transform,
+                    ..default()
+                }, part)).id()
+            }
+            SdfLightKind::Point => {
+                commands.spawn((PointLightBundle {
+                    point_light: PointLight {
+                        color: light.color,
+                        range: light.range,
+                        shadows_enabled: light.cast_shadows,
+                        ..default()
+                    },
+                    transform,
+                    ..default()
+                }, part)).id()
+            }
+            SdfLightKind::Spot { outer_angle } => {
+                commands.spawn((SpotLightBundle {
+                    spot_light: SpotLight {
+                        color: light.color,
+                        range: light.range,
+                        outer_angle,
+                        inner_angle: outer_angle * 0.8,
+                        shadows_enabled: light.cast_shadows,
+                        ..default()
+                    },
+                    transform,
+                    ..default()
+                }, part)).id()
+            }
         }
-    });
-    final_urdf.to_string()
+    }).collect()
 }
 
+#[allow(clippy::too_many_arguments)]
 fn spawn_robot_recursive_root(
     commands: &mut Commands,
     asset_server: &Res<AssetServer>,
     materials: &mut ResMut<Assets<StandardMaterial>>,
+    meshes: &mut ResMut<Assets<Mesh>>,
     robot: &urdf_rs::Robot,
+    transmissions: &[Transmission],
+    description_path: &Path,
+    search_paths: &ModelSearchPaths,
+    mesh_hashes: &HashMap<PathBuf, u64>,
+    config: &ModelConfig,
+    initial_transform: Transform,
     slot: usize,
-) {
+) -> Option<RobotModelEntities> {
     let link_map: HashMap<String, &urdf_rs::Link> = robot.links.iter()
         .map(|l| (l.name.clone(), l))
         .collect();
@@ -224,183 +1035,716 @@ fn spawn_robot_recursive_root(
 
     if let Some(root_name) = root_link_name {
         info!("Found root link: {}", root_name);
-        // スロットに応じて位置をずらす (例: X軸方向に2m間隔)
-        let offset_x = (slot as f32 - 1.0) * 2.0;
-        let initial_transform = Transform::from_xyz(offset_x, 2.0, 0.0);
-        
-        spawn_link_recursive(
+
+        let mut context = SpawnContext {
+            link_map,
+            child_map,
+            description_path,
+            search_paths,
+            mesh_hashes,
+            config,
+            material_handles: HashMap::new(),
+            default_material: materials.add(Color::rgb(0.8, 0.8, 0.8)),
+            slot,
+        };
+
+        // robot 直下で定義された名前付きマテリアルは visual から名前だけで参照される
+        for material in &robot.materials {
+            let handle = standard_material(material, &context, asset_server, materials);
+            context.material_handles.insert(material.name.clone(), handle);
+        }
+
+        let mut spawned = SpawnedEntities::default();
+        let root = spawn_link_recursive(
             commands,
             asset_server,
             materials,
-            &link_map,
-            &child_map,
+            meshes,
+            &context,
             root_name,
             initial_transform,
-            slot,
+            &mut spawned,
         );
+
+        if let Some(root) = root {
+            commands.entity(root).insert(RobotSource {
+                robot: robot.clone(),
+                description_path: description_path.to_path_buf(),
+                config: config.clone(),
+            });
+        }
+
+        for joint in &robot.joints {
+            let Some(mimic) = &joint.mimic else {
+                continue;
+            };
+            match (spawned.joints.get(&joint.name), spawned.joints.get(&mimic.joint)) {
+                (Some(&follower), Some(&leader)) => {
+                    commands.entity(follower).insert(JointMimic {
+                        leader,
+                        multiplier: mimic.multiplier.unwrap_or(1.0) as f32,
+                        offset: mimic.offset.unwrap_or(0.0) as f32,
+                    });
+                }
+                _ => warn!("Joint {} mimics {}, but one of them is not a movable joint", joint.name, mimic.joint),
+            }
+        }
+
+        for transmission in transmissions {
+            let Some(&entity) = spawned.joints.get(&transmission.joint) else {
+                continue;
+            };
+            commands.entity(entity).insert(JointActuator {
+                name: transmission.actuator.clone(),
+                interface: transmission.interface,
+                reduction: transmission.reduction as f32,
+                command: None,
+            });
+        }
+
+        let base_name = match &config.base_link {
+            Some(name) if spawned.links.contains_key(name) => name,
+            Some(name) => {
+                warn!("Base link {} from the model config is not part of {}; using the root link", name, robot.name);
+                root_name
+            }
+            None => root_name,
+        };
+        let base = spawned.links.get(base_name).copied();
+        if let Some(base) = base {
+            info!("Using {} as the base link of {}", base_name, robot.name);
+            commands.entity(base).insert((
+                RobotBase,
+                DriveInput::default(),
+                ExternalForce::default(),
+                AirDrag::new(1.0, 0.1, Vec3::splat(0.5)),
+            ));
+            for &entity in spawned.links.values() {
+                commands.entity(entity).insert(RobotRoot { base });
+            }
+            commands.entity(base).insert(self_collision_rules(robot, &config.self_collision, &spawned.links));
+        }
+
+        Some(RobotModelEntities {
+            name: robot.name.clone(),
+            base,
+            links: spawned.links,
+            joints: spawned.joints,
+        })
     } else {
         error!("No root link found!");
+        None
+    }
+}
+
+// 既定では関節でつながったリンク同士を外し、disable の組を外したあと allow の組を戻す
+fn self_collision_rules(
+    robot: &urdf_rs::Robot,
+    config: &SelfCollisionConfig,
+    links: &HashMap<String, Entity>,
+) -> SelfCollisionRules {
+    let mut rules = SelfCollisionRules::new(config.enabled);
+    let pair = |a: &String, b: &String| Some((*links.get(a)?, *links.get(b)?));
+
+    if !config.adjacent {
+        for (a, b) in robot.joints.iter().filter_map(|joint| pair(&joint.parent.link, &joint.child.link)) {
+            rules.disable(a, b);
+        }
+    }
+    for (a, b) in config.disable.iter().filter_map(|(a, b)| pair(a, b)) {
+        rules.disable(a, b);
+    }
+    for (a, b) in config.allow.iter().filter_map(|(a, b)| pair(a, b)) {
+        rules.allow(a, b);
+    }
+    rules
+}
+
+fn standard_material(
+    material: &urdf_rs::Material,
+    context: &SpawnContext,
+    asset_server: &Res<AssetServer>,
+    materials: &mut ResMut<Assets<StandardMaterial>>,
+) -> Handle<StandardMaterial> {
+    let [r, g, b, a] = material.color.as_ref()
+        .map(|color| color.rgba.map(|v| v as f32))
+        .unwrap_or([1.0; 4]);
+
+    let texture = material.texture.as_ref()
+        .filter(|texture| !texture.filename.is_empty())
+        .map(|texture| asset_server.load(resolve_resource_path(&texture.filename, context.description_path, context.search_paths)));
+
+    materials.add(StandardMaterial {
+        base_color: Color::rgba(r, g, b, a),
+        base_color_texture: texture,
+        alpha_mode: if a < 1.0 { AlphaMode::Blend } else { AlphaMode::Opaque },
+        ..default()
+    })
+}
+
+fn visual_material(
+    visual: &urdf_rs::Visual,
+    context: &SpawnContext,
+    asset_server: &Res<AssetServer>,
+    materials: &mut ResMut<Assets<StandardMaterial>>,
+) -> Handle<StandardMaterial> {
+    match &visual.material {
+        Some(material) if material.color.is_some() || material.texture.is_some() => {
+            standard_material(material, context, asset_server, materials)
+        }
+        Some(material) => match context.material_handles.get(&material.name) {
+            Some(handle) => handle.clone(),
+            None => {
+                warn!("Material {} is not defined; using the default material", material.name);
+                context.default_material.clone()
+            }
+        },
+        None => context.default_material.clone(),
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 fn spawn_link_recursive(
     commands: &mut Commands,
     asset_server: &Res<AssetServer>,
     materials: &mut ResMut<Assets<StandardMaterial>>,
-    link_map: &HashMap<String, &urdf_rs::Link>,
-    child_map: &HashMap<String, Vec<(&String, &urdf_rs::Joint)>>,
+    meshes: &mut ResMut<Assets<Mesh>>,
+    context: &SpawnContext,
     link_name: &str,
     transform: Transform,
-    slot: usize,
-) -> Entity {
-    let link = link_map.get(link_name).expect("Link not found in map");
+    spawned: &mut SpawnedEntities,
+) -> Option<Entity> {
+    let Some(link) = context.link_map.get(link_name) else {
+        error!("Link {} is referenced by a joint but not defined", link_name);
+        return None;
+    };
 
     let mut entity_cmd = commands.spawn((
         RigidBody::Fixed,
         TransformBundle::from(transform),
         VisibilityBundle::default(),
         Name::new(link.name.clone()),
-        RobotPart { slot },
+        RobotPart { slot: context.slot },
+        SpawnTransform(transform),
+        Velocity::default(),
+        ExternalImpulse::default(),
     ));
 
-    if link.inertial.mass.value > 0.0 {
-        entity_cmd.insert(AdditionalMassProperties::Mass(link.inertial.mass.value as f32));
-    } else {
-        entity_cmd.insert(AdditionalMassProperties::Mass(5.0));
-    }
-
-    if transform.translation.y >= 1.9 && transform.translation.x == 0.0 && transform.translation.z == 0.0 {
-         entity_cmd.insert((
-            DriveInput::default(),
-            Velocity::default(),
-            ExternalForce::default(),
-            AirDrag::new(1.0, 0.1, Vec3::splat(0.5)),
-        ));
-    }
-
-    for visual in &link.visual {
-        if let urdf_rs::Geometry::Mesh { filename, scale } = &visual.geometry {
-            let mesh_path = filename.replace("package://", "models/").replace("\\", "/");
-            let mesh_scale = scale.map_or(Vec3::ONE, |s| Vec3::new(s[0] as f32, s[1] as f32, s[2] as f32));
-            let mesh_handle = asset_server.load(&mesh_path);
-            let material_handle = materials.add(Color::rgb(0.8, 0.8, 0.8));
-
-            let visual_transform = Transform {
-                translation: Vec3::from_array(visual.origin.xyz.map(|v| v as f32)),
-                rotation: Quat::from_euler(
-                    EulerRot::XYZ,
-                    visual.origin.rpy[0] as f32,
-                    visual.origin.rpy[1] as f32,
-                    visual.origin.rpy[2] as f32,
-                ),
-                scale: mesh_scale,
-            };
+    entity_cmd.insert(AdditionalMassProperties::MassProperties(inertial_mass_properties(&link.inertial)));
+    spawned.links.insert(link.name.clone(), entity_cmd.id());
+
+    for (index, visual) in link.visual.iter().enumerate() {
+        let origin = pose_to_transform(&visual.origin);
+        let material_handle = visual_material(visual, context, asset_server, materials);
+
+        let (mesh_handle, visual_transform) = match &visual.geometry {
+            urdf_rs::Geometry::Mesh { filename, scale } => {
+                let mesh_handle: Handle<Mesh> = asset_server.load(resolve_resource_path(filename, context.description_path, context.search_paths));
+                (mesh_handle, origin.with_scale(mesh_scale(scale)))
+            }
+            geometry => match primitive_mesh(geometry) {
+                Some(mesh) => (meshes.add(mesh), origin * Transform::from_rotation(geometry_alignment(geometry))),
+                None => continue,
+            },
+        };
 
-            entity_cmd.with_children(|parent| {
-                parent.spawn(PbrBundle {
-                    mesh: mesh_handle.clone(),
+        entity_cmd.with_children(|parent| {
+            parent.spawn((
+                PbrBundle {
+                    mesh: mesh_handle,
                     material: material_handle,
                     transform: visual_transform,
                     ..default()
-                });
-            });
+                },
+                LinkVisual { index },
+            ));
+        });
+    }
 
-            entity_cmd.insert(PendingCollider { transform: visual_transform });
-            entity_cmd.insert(mesh_handle);
-        }
+    // collision 要素が無いリンクだけ visual の形状で代用する
+    let collision_geometry: Vec<(&urdf_rs::Pose, &urdf_rs::Geometry)> = if link.collision.is_empty() {
+        link.visual.iter().map(|v| (&v.origin, &v.geometry)).collect()
+    } else {
+        link.collision.iter().map(|c| (&c.origin, &c.geometry)).collect()
+    };
+
+    let fixed = context.config.is_fixed(&link.name);
+    let mut mode = context.config.collider_mode(&link.name);
+    if mode == ColliderMode::TriMesh && !fixed {
+        warn!("Triangle mesh colliders need a fixed body; using convex decomposition for {}", link.name);
+        mode = ColliderMode::ConvexDecomposition;
     }
 
+    let shapes: Vec<PendingShape> = collision_geometry.into_iter()
+        .filter_map(|(origin, geometry)| geometry_shape(geometry, pose_to_transform(origin), mode, asset_server, context))
+        .collect();
+
+    entity_cmd.insert(PendingCollider {
+        shapes,
+        mode,
+        fixed,
+        decomposition: context.config.decomposition,
+    });
+
     let parent_entity = entity_cmd.id();
 
-    if let Some(children) = child_map.get(link_name) {
+    if let Some(children) = context.child_map.get(link_name) {
         for (child_name, joint) in children {
-            let joint_offset = Vec3::from_array(joint.origin.xyz.map(|v| v as f32));
-            let joint_rotation = Quat::from_euler(
-                EulerRot::XYZ,
-                joint.origin.rpy[0] as f32,
-                joint.origin.rpy[1] as f32,
-                joint.origin.rpy[2] as f32,
-            );
-
-            let child_transform = transform.mul_transform(Transform {
-                translation: joint_offset,
-                rotation: joint_rotation,
-                ..default()
-            });
+            let joint_origin = pose_to_transform(&joint.origin);
+            let child_transform = transform.mul_transform(joint_origin);
 
-            let child_entity = spawn_link_recursive(
+            let Some(child_entity) = spawn_link_recursive(
                 commands,
                 asset_server,
                 materials,
-                link_map,
-                child_map,
+                meshes,
+                context,
                 child_name,
                 child_transform,
-                slot,
-            );
-
-            let axis = Vec3::from_array(joint.axis.xyz.map(|v| v as f32));
-            let joint_data = match joint.joint_type {
-                urdf_rs::JointType::Revolute | urdf_rs::JointType::Continuous => {
-                    let j = RevoluteJointBuilder::new(axis)
-                        .local_anchor1(joint_offset)
-                        .local_anchor2(Vec3::ZERO)
-                        .build();
-                    j.into()
-                },
-                _ => {
-                    let j = FixedJointBuilder::new()
-                        .local_anchor1(joint_offset)
-                        .local_anchor2(Vec3::ZERO)
-                        .build();
-                    j.into()
-                }
+                spawned,
+            ) else {
+                continue;
             };
 
-            commands.entity(child_entity).insert(PendingJoint {
-                parent: parent_entity,
-                data: joint_data,
-                name: joint.name.clone(),
-            });
+            let (joint_data, robot_joint) = joint_data(joint, &joint_origin, parent_entity);
+            if let Some(robot_joint) = robot_joint {
+                commands.entity(child_entity).insert(robot_joint);
+
+                let dynamics = joint.dynamics.as_ref()
+                    .map(|dynamics| JointDynamics { damping: dynamics.damping as f32, friction: dynamics.friction as f32 })
+                    .filter(|dynamics| dynamics.damping > 0.0 || dynamics.friction > 0.0);
+                if let Some(dynamics) = dynamics {
+                    commands.entity(child_entity).insert(dynamics);
+                }
+                spawned.joints.insert(joint.name.clone(), child_entity);
+            }
+
+            // floating 関節は拘束を作らず、子リンクを自由剛体にする
+            if let Some(joint_data) = joint_data {
+                commands.entity(child_entity).insert(PendingJoint {
+                    parent: parent_entity,
+                    data: joint_data,
+                    name: joint.name.clone(),
+                });
+            }
+        }
+    }
+
+    Some(parent_entity)
+}
+
+fn joint_data(joint: &urdf_rs::Joint, joint_origin: &Transform, parent: Entity) -> (Option<GenericJoint>, Option<RobotJoint>) {
+    let axis = Vec3::from_array(joint.axis.xyz.map(|v| v as f32)).try_normalize().unwrap_or(Vec3::X);
+    // 関節座標系の X 軸を URDF の軸に合わせ、初期姿勢を関節変位 0 にする
+    let basis = Quat::from_rotation_arc(Vec3::X, axis);
+    let joint_builder = |locked_axes: JointAxesMask| {
+        GenericJointBuilder::new(locked_axes)
+            .local_anchor1(joint_origin.translation)
+            .local_anchor2(Vec3::ZERO)
+            .local_basis1(joint_origin.rotation * basis)
+            .local_basis2(basis)
+    };
+
+    let (mut builder, kind) = match joint.joint_type {
+        urdf_rs::JointType::Revolute | urdf_rs::JointType::Continuous => {
+            (joint_builder(JointAxesMask::LOCKED_REVOLUTE_AXES), JointKind::Revolute)
+        },
+        urdf_rs::JointType::Prismatic => {
+            (joint_builder(JointAxesMask::LOCKED_PRISMATIC_AXES), JointKind::Prismatic)
+        },
+        // 軸に垂直な平面内の並進と、軸まわりの回転を許す
+        urdf_rs::JointType::Planar => {
+            let locked_axes = JointAxesMask::X | JointAxesMask::ANG_Y | JointAxesMask::ANG_Z;
+            return (Some(joint_builder(locked_axes).build()), None);
+        },
+        urdf_rs::JointType::Floating => return (None, None),
+        _ => {
+            let j = FixedJointBuilder::new()
+                .local_anchor1(joint_origin.translation)
+                .local_anchor2(Vec3::ZERO)
+                .local_basis1(joint_origin.rotation)
+                .build();
+            return (Some(j.into()), None);
+        }
+    };
+
+    let free_axis = kind.free_axis();
+    let mut robot_joint = RobotJoint::new(joint.name.clone(), parent, kind, axis, joint_origin);
+    robot_joint.effort = joint.limit.effort as f32;
+    robot_joint.velocity = joint.limit.velocity as f32;
+
+    if !matches!(joint.joint_type, urdf_rs::JointType::Continuous) {
+        let (lower, upper) = (joint.limit.lower as f32, joint.limit.upper as f32);
+        if lower < upper {
+            builder = builder.limits(free_axis, [lower, upper]);
+            robot_joint.limits = Some([lower, upper]);
+        } else {
+            warn!("Joint {} has an empty range [{}, {}]; leaving it unlimited", joint.name, lower, upper);
+        }
+    }
+
+    // あとからモーターで目標を与えても effort を超えないようにしておく
+    if robot_joint.effort > 0.0 {
+        builder = builder.motor_max_force(free_axis, robot_joint.effort);
+    }
+
+    (Some(builder.build()), Some(robot_joint))
+}
+
+pub(super) fn inertial_mass_properties(inertial: &urdf_rs::Inertial) -> MassProperties {
+    let origin = pose_to_transform(&inertial.origin);
+    let i = &inertial.inertia;
+    let tensor = Mat3::from_cols_array(&[
+        i.ixx, i.ixy, i.ixz,
+        i.ixy, i.iyy, i.iyz,
+        i.ixz, i.iyz, i.izz,
+    ].map(|v| v as f32));
+
+    // inertial の origin で回した慣性テンソルをリンク座標系で主軸分解する
+    let rotation = Mat3::from_quat(origin.rotation);
+    let link_tensor = rotation * tensor * rotation.transpose();
+    let mass = (inertial.mass.value as f32).max(MIN_LINK_MASS);
+
+    let principal = ParryMassProperties::with_inertia_matrix(
+        Point::origin(),
+        mass,
+        Matrix3::from_column_slice(&link_tensor.to_cols_array()),
+    );
+    let frame = principal.principal_inertia_local_frame;
+    let inertia = principal.principal_inertia();
+
+    MassProperties {
+        local_center_of_mass: origin.translation,
+        mass,
+        principal_inertia_local_frame: Quat::from_xyzw(frame.i, frame.j, frame.k, frame.w),
+        principal_inertia: Vec3::new(inertia.x, inertia.y, inertia.z).max(Vec3::splat(MIN_PRINCIPAL_INERTIA)),
+    }
+}
+
+pub(super) fn transform_to_pose(transform: &Transform) -> urdf_rs::Pose {
+    let (roll, pitch, yaw) = transform.rotation.to_euler(EulerRot::XYZ);
+    urdf_rs::Pose {
+        xyz: urdf_rs::Vec3(transform.translation.to_array().map(f64::from)),
+        rpy: urdf_rs::Vec3([roll as f64, pitch as f64, yaw as f64]),
+    }
+}
+
+pub(super) fn pose_to_transform(pose: &urdf_rs::Pose) -> Transform {
+    Transform {
+        translation: Vec3::from_array(pose.xyz.map(|v| v as f32)),
+        rotation: Quat::from_euler(
+            EulerRot::XYZ,
+            pose.rpy[0] as f32,
+            pose.rpy[1] as f32,
+            pose.rpy[2] as f32,
+        ),
+        ..default()
+    }
+}
+
+fn mesh_scale(scale: &Option<urdf_rs::Vec3>) -> Vec3 {
+    scale.map_or(Vec3::ONE, |s| Vec3::new(s[0] as f32, s[1] as f32, s[2] as f32))
+}
+
+// URDF の円柱・カプセルは Z 軸方向だが、Bevy / Rapier のものは Y 軸方向
+pub(super) fn geometry_alignment(geometry: &urdf_rs::Geometry) -> Quat {
+    match geometry {
+        urdf_rs::Geometry::Cylinder { .. } | urdf_rs::Geometry::Capsule { .. } => {
+            Quat::from_rotation_x(std::f32::consts::FRAC_PI_2)
+        }
+        _ => Quat::IDENTITY,
+    }
+}
+
+fn primitive_mesh(geometry: &urdf_rs::Geometry) -> Option<Mesh> {
+    match *geometry {
+        urdf_rs::Geometry::Box { ref size } => {
+            let [x, y, z] = size.map(|v| v as f32);
+            Some(Cuboid::new(x, y, z).into())
+        }
+        urdf_rs::Geometry::Cylinder { radius, length } => Some(Cylinder::new(radius as f32, length as f32).into()),
+        urdf_rs::Geometry::Capsule { radius, length } => Some(Capsule3d::new(radius as f32, length as f32).into()),
+        urdf_rs::Geometry::Sphere { radius } => Some(Sphere::new(radius as f32).into()),
+        urdf_rs::Geometry::Mesh { .. } => None,
+    }
+}
+
+fn primitive_collider(geometry: &urdf_rs::Geometry) -> Option<Collider> {
+    match *geometry {
+        urdf_rs::Geometry::Box { ref size } => {
+            let [x, y, z] = size.map(|v| v as f32 / 2.0);
+            Some(Collider::cuboid(x, y, z))
+        }
+        urdf_rs::Geometry::Cylinder { radius, length } => Some(Collider::cylinder(length as f32 / 2.0, radius as f32)),
+        urdf_rs::Geometry::Capsule { radius, length } => Some(Collider::capsule_y(length as f32 / 2.0, radius as f32)),
+        urdf_rs::Geometry::Sphere { radius } => Some(Collider::ball(radius as f32)),
+        urdf_rs::Geometry::Mesh { .. } => None,
+    }
+}
+
+fn geometry_shape(
+    geometry: &urdf_rs::Geometry,
+    origin: Transform,
+    mode: ColliderMode,
+    asset_server: &AssetServer,
+    context: &SpawnContext,
+) -> Option<PendingShape> {
+    if let urdf_rs::Geometry::Mesh { filename, scale } = geometry {
+        let path = resolve_resource_path(filename, context.description_path, context.search_paths);
+        let transform = origin.with_scale(mesh_scale(scale));
+
+        // 三角形メッシュは作り直しても安いのでキャッシュしない
+        let cache_key = context.mesh_hashes.get(Path::new(&path))
+            .filter(|_| mode != ColliderMode::TriMesh)
+            .map(|&hash| cache::collider_key(hash, mode, context.config.decomposition, &transform));
+        if let Some(parts) = cache_key.and_then(cache::read_colliders) {
+            return Some(PendingShape::Cached { parts });
+        }
+
+        return Some(PendingShape::Mesh {
+            handle: asset_server.load(path),
+            transform,
+            cache_key,
+        });
+    }
+
+    primitive_collider(geometry).map(|collider| PendingShape::Primitive {
+        collider,
+        transform: origin * Transform::from_rotation(geometry_alignment(geometry)),
+    })
+}
+
+fn update_load_progress(
+    mut progress: ResMut<RobotLoadProgress>,
+    pending_query: Query<(&PendingCollider, &RobotPart)>,
+    meshes: Res<Assets<Mesh>>,
+) {
+    for (&slot, stage) in progress.slots.iter_mut() {
+        if *stage == LoadStage::Parsing {
+            continue;
+        }
+
+        let mut loaded = 0;
+        let mut total = 0;
+        let mut remaining = 0;
+        for (pending, _) in pending_query.iter().filter(|(_, part)| part.slot == slot) {
+            remaining += 1;
+            for shape in &pending.shapes {
+                if let PendingShape::Mesh { handle, .. } = shape {
+                    total += 1;
+                    if meshes.contains(handle) {
+                        loaded += 1;
+                    }
+                }
+            }
         }
+
+        *stage = if loaded < total {
+            LoadStage::Meshes { loaded, total }
+        } else {
+            LoadStage::Colliders { remaining }
+        };
     }
 
-    parent_entity
+    progress.slots.retain(|_, stage| *stage != LoadStage::Colliders { remaining: 0 });
 }
 
 fn apply_mesh_colliders(
     mut commands: Commands,
-    mut query: Query<(Entity, &Handle<Mesh>, &PendingCollider, Option<&PendingJoint>), With<PendingCollider>>,
+    query: Query<(Entity, &PendingCollider), Without<ColliderTask>>,
     meshes: Res<Assets<Mesh>>,
+    asset_server: Res<AssetServer>,
 ) {
-    for (entity, mesh_handle, pending, pending_joint) in query.iter_mut() {
-        if let Some(mesh) = meshes.get(mesh_handle) {
-            if let Some(VertexAttributeValues::Float32x3(positions)) = mesh.attribute(Mesh::ATTRIBUTE_POSITION) {
-                
-                let transformed_positions: Vec<Vec3> = positions.iter()
-                    .map(|p| pending.transform.transform_point(Vec3::new(p[0], p[1], p[2])))
-                    .collect();
-                
-                if let Some(collider) = Collider::convex_hull(&transformed_positions) {
-                    let robot_collision_group = CollisionGroups::new(Group::GROUP_2, Group::GROUP_1);
-                    
-                    let mut cmd = commands.entity(entity);
-                    cmd
-                        .insert(collider)
-                        .insert(RigidBody::Dynamic)
-                        .insert(robot_collision_group)
-                        .remove::<PendingCollider>();
-
-                    if let Some(pj) = pending_joint {
-                        info!("Enabling joint: {}", pj.name);
-                        cmd.insert(ImpulseJoint::new(pj.parent, pj.data));
-                        cmd.insert(Name::new(format!("Joint: {}", pj.name)));
-                        cmd.remove::<PendingJoint>();
+    for (entity, pending) in query.iter() {
+        let still_loading = pending.shapes.iter().any(|shape| match shape {
+            PendingShape::Mesh { handle, .. } => {
+                meshes.get(handle).is_none() && asset_server.load_state(handle) != LoadState::Failed
+            }
+            PendingShape::Cached { .. } | PendingShape::Primitive { .. } => false,
+        });
+
+        if still_loading {
+            continue;
+        }
+
+        // メッシュの頂点だけをここで取り出し、形状の計算はタスクに渡す
+        let jobs: Vec<ColliderJob> = pending.shapes.iter()
+            .filter_map(|shape| match shape {
+                PendingShape::Mesh { handle, transform, cache_key } => {
+                    match meshes.get(handle).and_then(|mesh| MeshGeometry::from_mesh(mesh, transform)) {
+                        Some(geometry) => Some(ColliderJob::Mesh { geometry, cache_key: *cache_key }),
+                        None => {
+                            warn!("Could not build a collider from mesh {:?} on entity {:?}", handle.path(), entity);
+                            None
+                        }
                     }
-                    
-                    info!("Generated scaled convex hull for entity {:?}", entity);
                 }
+                PendingShape::Cached { parts } => Some(ColliderJob::Ready(parts.clone())),
+                PendingShape::Primitive { collider, transform } => {
+                    Some(ColliderJob::Ready(vec![(transform.translation, transform.rotation, collider.clone())]))
+                }
+            })
+            .collect();
+
+        let (mode, decomposition) = (pending.mode, pending.decomposition);
+        let task = AsyncComputeTaskPool::get().spawn(async move { build_colliders(jobs, mode, decomposition) });
+        commands.entity(entity).insert(ColliderTask { task });
+    }
+}
+
+// バックグラウンドで実行される
+fn build_colliders(jobs: Vec<ColliderJob>, mode: ColliderMode, decomposition: DecompositionSettings) -> BuiltColliders {
+    let mut built = BuiltColliders::default();
+    for job in jobs {
+        let (geometry, cache_key) = match job {
+            ColliderJob::Mesh { geometry, cache_key } => (geometry, cache_key),
+            ColliderJob::Ready(parts) => {
+                built.parts.extend(parts);
+                continue;
+            }
+        };
+
+        // ファイルのハッシュが無いメッシュは、読み込んだ形状そのものをキーにして引く
+        let cache_key = match cache_key {
+            None if mode != ColliderMode::TriMesh => {
+                let key = cache::geometry_key(&geometry, mode, decomposition);
+                if let Some(parts) = cache::read_colliders(key) {
+                    built.parts.extend(parts);
+                    continue;
+                }
+                Some(key)
             }
+            cache_key => cache_key,
+        };
+
+        let shape_parts: Vec<(Vect, Rot, Collider)> = match mode {
+            ColliderMode::Aabb => collider::aabb_collider(&geometry).into_iter().collect(),
+            ColliderMode::ConvexHull => collider::convex_hull_collider(&geometry).into_iter().collect(),
+            ColliderMode::ConvexDecomposition => collider::convex_decomposition_colliders(&geometry, decomposition),
+            ColliderMode::TriMesh => {
+                built.trimeshes.extend(collider::trimesh_collider(&geometry));
+                Vec::new()
+            }
+        };
+
+        if let Some(key) = cache_key {
+            cache::write_colliders(key, &shape_parts);
+        }
+        built.parts.extend(shape_parts);
+    }
+    built
+}
+
+type ColliderTaskState = (
+    Entity,
+    &'static PendingCollider,
+    &'static mut ColliderTask,
+    Option<&'static PendingJoint>,
+    Option<&'static RobotRoot>,
+);
+
+fn finish_mesh_colliders(
+    mut commands: Commands,
+    mut query: Query<ColliderTaskState>,
+) {
+    for (entity, pending, mut collider_task, pending_joint, root) in query.iter_mut() {
+        let Some(BuiltColliders { parts, trimeshes }) = block_on(poll_once(&mut collider_task.task)) else {
+            continue;
+        };
+
+        let robot_collision_group = contact::robot_collision_groups();
+        // 同じロボットのリンク同士の接触は RobotContactFilter が SelfCollisionRules で選ぶ
+        let owner = root.map(|root| CollisionOwner { base: root.base, link: entity });
+        let body = if pending.fixed { RigidBody::Fixed } else { RigidBody::Dynamic };
+
+        // 質量は URDF の inertial だけで決め、コライダー形状からは加算しない
+        let mut cmd = commands.entity(entity);
+        cmd
+            .insert(body)
+            .insert(robot_collision_group)
+            .insert(ColliderMassProperties::Density(0.0))
+            .remove::<(PendingCollider, ColliderTask)>();
+        if let Some(owner) = owner {
+            cmd.insert((owner, ActiveHooks::FILTER_CONTACT_PAIRS));
+        }
+
+        match parts.len() {
+            0 => {}
+            1 if parts[0].0 == Vec3::ZERO && parts[0].1 == Quat::IDENTITY => {
+                cmd.insert(parts[0].2.clone());
+            }
+            _ => {
+                cmd.insert(Collider::compound(parts));
+            }
+        }
+
+        // 三角形メッシュは複合形状に入れられないので子エンティティのコライダーにする
+        if !trimeshes.is_empty() {
+            cmd.with_children(|parent| {
+                for trimesh in trimeshes {
+                    let mut child = parent.spawn((
+                        trimesh,
+                        TransformBundle::default(),
+                        robot_collision_group,
+                        ColliderMassProperties::Density(0.0),
+                    ));
+                    if let Some(owner) = owner {
+                        child.insert((owner, ActiveHooks::FILTER_CONTACT_PAIRS));
+                    }
+                }
+            });
+        }
+
+        if let Some(pj) = pending_joint {
+            info!("Enabling joint: {}", pj.name);
+            cmd.insert(ImpulseJoint::new(pj.parent, pj.data));
+            cmd.remove::<PendingJoint>();
+        }
+
+        info!("Generated {:?} colliders for entity {:?}", pending.mode, entity);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn descriptions_are_told_apart_from_fragments() {
+        let dir = std::env::temp_dir().join(format!("udon_loader_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let files = [
+            ("plain.urdf", r#"<robot name="a"><link name="base"/></robot>"#, true),
+            ("macro_only.urdf.xacro", r#"<?xml version="1.0"?>
+                <robot name="b" xmlns:xacro="http://www.ros.org/wiki/xacro">
+                  <xacro:include filename="arm.xacro"/>
+                  <xacro:arm prefix="left"/>
+                </robot>"#, true),
+            ("conditional.xacro", r#"<robot xmlns:xacro="http://www.ros.org/wiki/xacro">
+                  <xacro:if value="true"><xacro:arm/></xacro:if>
+                </robot>"#, true),
+            ("materials.xacro", r#"<robot xmlns:xacro="http://www.ros.org/wiki/xacro">
+                  <xacro:property name="grey" value="0.5"/>
+                  <material name="grey"><color rgba="0.5 0.5 0.5 1"/></material>
+                </robot>"#, false),
+            ("arm.xacro", r#"<robot xmlns:xacro="http://www.ros.org/wiki/xacro">
+                  <xacro:macro name="arm" params="prefix"><link name="${prefix}_arm"/></xacro:macro>
+                </robot>"#, false),
+            ("not_robot.xacro", r#"<launch><link/></launch>"#, false),
+            ("model.sdf", r#"<sdf version="1.6"><model name="m"><link name="base"/></model></sdf>"#, true),
+            ("world.sdf", r#"<sdf version="1.6"><world name="w"><model name="m"><link name="base"/></model></world></sdf>"#, false),
+        ];
+
+        for (name, content, _) in &files {
+            fs::write(dir.join(name), content).unwrap();
+        }
+        let results: Vec<(&str, bool)> = files.iter()
+            .map(|(name, _, _)| (*name, is_robot_description(&dir.join(name))))
+            .collect();
+        let _ = fs::remove_dir_all(&dir);
+
+        for ((name, _, expected), (_, actual)) in files.iter().zip(results) {
+            assert_eq!(actual, *expected, "{}", name);
         }
     }
 }
@@ -0,0 +1,797 @@
+use bevy::asset::io::Reader;
+use bevy::asset::{AssetLoader, AsyncReadExt, LoadContext};
+use bevy::prelude::*;
+use bevy::render::mesh::{Indices, PrimitiveTopology};
+use bevy::render::render_asset::RenderAssetUsages;
+use bevy::utils::BoxedFuture;
+use std::collections::HashMap;
+use std::f32::consts::FRAC_PI_2;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use super::xml::{self, XmlElement};
+
+// StlPlugin と RobotMeshLoader が読める拡張子
+const MESH_EXTENSIONS: [&str; 5] = ["stl", "obj", "dae", "gltf", "glb"];
+
+// instance_node の循環参照で止まらないようにするための上限
+const MAX_NODE_DEPTH: usize = 64;
+
+#[derive(Debug)]
+pub struct MeshFormatError {
+    pub message: String,
+}
+
+impl MeshFormatError {
+    fn new(message: impl Into<String>) -> Self {
+        Self { message: message.into() }
+    }
+}
+
+impl fmt::Display for MeshFormatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for MeshFormatError {}
+
+impl From<std::io::Error> for MeshFormatError {
+    fn from(e: std::io::Error) -> Self {
+        Self::new(e.to_string())
+    }
+}
+
+pub fn is_supported_mesh(filename: &str) -> bool {
+    MESH_EXTENSIONS.contains(&extension(Path::new(filename)).as_str())
+}
+
+// URDF の <mesh> から参照される OBJ / Collada / glTF を 1 つの Mesh として読む。
+// 複数のプリミティブやノードはノードの変換を焼き込んで結合する (マテリアルは URDF 側のものを使う)
+#[derive(Default)]
+pub struct RobotMeshLoader;
+
+impl AssetLoader for RobotMeshLoader {
+    type Asset = Mesh;
+    type Settings = ();
+    type Error = MeshFormatError;
+
+    fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader,
+        _settings: &'a (),
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<Mesh, MeshFormatError>> {
+        Box::pin(async move {
+            let mut bytes = Vec::new();
+            reader.read_to_end(&mut bytes).await?;
+
+            parse_mesh(&bytes, load_context.path())?.build()
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["obj", "dae", "gltf", "glb"]
+    }
+}
+
+fn extension(path: &Path) -> String {
+    path.extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .unwrap_or_default()
+}
+
+fn parse_mesh(bytes: &[u8], path: &Path) -> Result<MeshBuilder, MeshFormatError> {
+    match extension(path).as_str() {
+        "obj" => parse_obj(&String::from_utf8_lossy(bytes)),
+        "dae" => parse_collada(&String::from_utf8_lossy(bytes)),
+        "gltf" | "glb" => parse_gltf(bytes, path.parent()),
+        _ => Err(MeshFormatError::new(format!("unsupported mesh format: {}", path.display()))),
+    }
+}
+
+// 読み込み前の単位推定用に、メッシュファイルの外接箱 (ファイル上の座標) を求める
+pub fn mesh_bounds(path: &Path) -> Option<(Vec3, Vec3)> {
+    let bytes = fs::read(path).ok()?;
+    let positions = if extension(path) == "stl" {
+        stl_positions(&bytes)
+    } else {
+        parse_mesh(&bytes, path).ok()?.positions.into_iter().map(Vec3::from_array).collect()
+    };
+
+    let first = *positions.first()?;
+    Some(positions.iter().fold((first, first), |(min, max), p| (min.min(*p), max.max(*p))))
+}
+
+// バイナリ STL は 80 バイトのヘッダ、三角形数、三角形ごとに 50 バイト
+fn stl_positions(bytes: &[u8]) -> Vec<Vec3> {
+    let binary_count = bytes.get(80..84)
+        .map(|count| u32::from_le_bytes([count[0], count[1], count[2], count[3]]) as usize)
+        .filter(|count| bytes.len() == 84 + count * 50);
+
+    match binary_count {
+        Some(count) => (0..count)
+            .flat_map(|triangle| (0..3).map(move |vertex| 84 + triangle * 50 + 12 + vertex * 12))
+            .map(|offset| {
+                let float = |i: usize| {
+                    let start = offset + i * 4;
+                    f32::from_le_bytes([bytes[start], bytes[start + 1], bytes[start + 2], bytes[start + 3]])
+                };
+                Vec3::new(float(0), float(1), float(2))
+            })
+            .collect(),
+        None => String::from_utf8_lossy(bytes)
+            .lines()
+            .filter_map(|line| xml::vec3(line.trim().strip_prefix("vertex")?))
+            .collect(),
+    }
+}
+
+struct Primitive {
+    positions: Vec<Vec3>,
+    normals: Option<Vec<Vec3>>,
+    uvs: Option<Vec<[f32; 2]>>,
+    indices: Vec<u32>,
+}
+
+#[derive(Default)]
+struct MeshBuilder {
+    positions: Vec<[f32; 3]>,
+    normals: Vec<[f32; 3]>,
+    uvs: Vec<[f32; 2]>,
+    indices: Vec<u32>,
+}
+
+impl MeshBuilder {
+    fn add(&mut self, primitive: Primitive, transform: Mat4) -> Result<(), MeshFormatError> {
+        let vertex_count = primitive.positions.len();
+        if primitive.indices.iter().any(|&i| i as usize >= vertex_count) {
+            return Err(MeshFormatError::new("triangle index out of range"));
+        }
+
+        let normals = primitive.normals
+            .filter(|normals| normals.len() == vertex_count)
+            .unwrap_or_else(|| vertex_normals(&primitive.positions, &primitive.indices));
+        let uvs = primitive.uvs
+            .filter(|uvs| uvs.len() == vertex_count)
+            .unwrap_or_else(|| vec![[0.0, 0.0]; vertex_count]);
+
+        let normal_matrix = Mat3::from_mat4(transform).inverse().transpose();
+        // 鏡映を含む変換では三角形の向きが裏返る
+        let mirrored = transform.determinant() < 0.0;
+
+        let base = self.positions.len() as u32;
+        self.positions.extend(primitive.positions.iter().map(|p| transform.transform_point3(*p).to_array()));
+        self.normals.extend(normals.iter().map(|n| (normal_matrix * *n).normalize_or_zero().to_array()));
+        self.uvs.extend(uvs);
+
+        for triangle in primitive.indices.chunks_exact(3) {
+            let [a, b, c] = [triangle[0] + base, triangle[1] + base, triangle[2] + base];
+            if mirrored {
+                self.indices.extend([a, c, b]);
+            } else {
+                self.indices.extend([a, b, c]);
+            }
+        }
+        Ok(())
+    }
+
+    fn build(self) -> Result<Mesh, MeshFormatError> {
+        if self.indices.is_empty() {
+            return Err(MeshFormatError::new("mesh has no triangles"));
+        }
+
+        Ok(Mesh::new(PrimitiveTopology::TriangleList, RenderAssetUsages::default())
+            .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, self.positions)
+            .with_inserted_attribute(Mesh::ATTRIBUTE_NORMAL, self.normals)
+            .with_inserted_attribute(Mesh::ATTRIBUTE_UV_0, self.uvs)
+            .with_inserted_indices(Indices::U32(self.indices)))
+    }
+}
+
+// 法線が無いファイル向けに、面法線を頂点ごとに平均する
+fn vertex_normals(positions: &[Vec3], indices: &[u32]) -> Vec<Vec3> {
+    let mut normals = vec![Vec3::ZERO; positions.len()];
+    for triangle in indices.chunks_exact(3) {
+        let [a, b, c] = [triangle[0] as usize, triangle[1] as usize, triangle[2] as usize];
+        let normal = (positions[b] - positions[a]).cross(positions[c] - positions[a]);
+        normals[a] += normal;
+        normals[b] += normal;
+        normals[c] += normal;
+    }
+    normals.into_iter().map(|n| n.normalize_or_zero()).collect()
+}
+
+type Corner = (usize, Option<usize>, Option<usize>);
+
+// (位置, 法線, UV) の添字の組を 1 頂点にまとめて添字付きのプリミティブにする
+fn indexed_primitive(
+    corners: &[Corner],
+    positions: &[Vec3],
+    normals: &[Vec3],
+    uvs: &[[f32; 2]],
+) -> Result<Primitive, MeshFormatError> {
+    let has_normals = corners.iter().all(|(_, normal, _)| normal.is_some());
+    let has_uvs = corners.iter().all(|(_, _, uv)| uv.is_some());
+
+    let mut vertices: HashMap<Corner, u32> = HashMap::new();
+    let mut primitive = Primitive {
+        positions: Vec::new(),
+        normals: has_normals.then(Vec::new),
+        uvs: has_uvs.then(Vec::new),
+        indices: Vec::with_capacity(corners.len()),
+    };
+
+    for corner in corners {
+        if let Some(&index) = vertices.get(corner) {
+            primitive.indices.push(index);
+            continue;
+        }
+
+        let (position, normal, uv) = *corner;
+        let out_of_range = || MeshFormatError::new("vertex index out of range");
+        primitive.positions.push(*positions.get(position).ok_or_else(out_of_range)?);
+        if let (Some(out), Some(normal)) = (primitive.normals.as_mut(), normal) {
+            out.push(*normals.get(normal).ok_or_else(out_of_range)?);
+        }
+        if let (Some(out), Some(uv)) = (primitive.uvs.as_mut(), uv) {
+            out.push(*uvs.get(uv).ok_or_else(out_of_range)?);
+        }
+
+        let index = vertices.len() as u32;
+        vertices.insert(*corner, index);
+        primitive.indices.push(index);
+    }
+
+    Ok(primitive)
+}
+
+// 多角形を扇形に三角形分割する
+fn push_polygon(corners: &mut Vec<Corner>, polygon: &[Corner]) {
+    for i in 1..polygon.len().saturating_sub(1) {
+        corners.extend([polygon[0], polygon[i], polygon[i + 1]]);
+    }
+}
+
+fn parse_obj(content: &str) -> Result<MeshBuilder, MeshFormatError> {
+    let mut positions = Vec::new();
+    let mut normals = Vec::new();
+    let mut uvs = Vec::new();
+    let mut corners = Vec::new();
+
+    for (line_number, line) in content.lines().enumerate() {
+        let mut tokens = line.split_whitespace();
+        let keyword = tokens.next();
+        let values: Vec<&str> = tokens.collect();
+        let error = |message: &str| MeshFormatError::new(format!("line {}: {}", line_number + 1, message));
+        let floats = |count: usize| -> Result<Vec<f32>, MeshFormatError> {
+            values.iter()
+                .take(count)
+                .map(|value| value.parse().map_err(|_| error("invalid number")))
+                .collect()
+        };
+
+        match keyword {
+            Some("v") => match floats(3)?.as_slice() {
+                [x, y, z] => positions.push(Vec3::new(*x, *y, *z)),
+                _ => return Err(error("vertex needs three coordinates")),
+            },
+            Some("vn") => match floats(3)?.as_slice() {
+                [x, y, z] => normals.push(Vec3::new(*x, *y, *z)),
+                _ => return Err(error("normal needs three coordinates")),
+            },
+            Some("vt") => match floats(2)?.as_slice() {
+                [u, v] => uvs.push([*u, 1.0 - *v]),
+                [u] => uvs.push([*u, 1.0]),
+                _ => return Err(error("texture coordinate needs a value")),
+            },
+            Some("f") => {
+                let polygon = values.iter()
+                    .map(|vertex| {
+                        let mut parts = vertex.split('/');
+                        let position = obj_index(parts.next(), positions.len())
+                            .ok_or_else(|| error("face vertex without a position"))?;
+                        let uv = obj_index(parts.next(), uvs.len());
+                        let normal = obj_index(parts.next(), normals.len());
+                        Ok((position, normal, uv))
+                    })
+                    .collect::<Result<Vec<Corner>, MeshFormatError>>()?;
+                push_polygon(&mut corners, &polygon);
+            }
+            _ => {}
+        }
+    }
+
+    let mut builder = MeshBuilder::default();
+    builder.add(indexed_primitive(&corners, &positions, &normals, &uvs)?, Mat4::IDENTITY)?;
+    Ok(builder)
+}
+
+// OBJ の添字は 1 始まりで、負の値は末尾からの相対位置
+fn obj_index(token: Option<&str>, count: usize) -> Option<usize> {
+    let index: i64 = token.filter(|t| !t.is_empty())?.parse().ok()?;
+    match index {
+        i if i > 0 => Some(i as usize - 1),
+        i if i < 0 => count.checked_sub(i.unsigned_abs() as usize),
+        _ => None,
+    }
+}
+
+struct ColladaSource {
+    values: Vec<f32>,
+    stride: usize,
+}
+
+impl ColladaSource {
+    fn vec3s(&self) -> Vec<Vec3> {
+        if self.stride < 3 {
+            return Vec::new();
+        }
+        self.values.chunks_exact(self.stride)
+            .map(|v| Vec3::new(v[0], v[1], v[2]))
+            .collect()
+    }
+
+    fn uvs(&self) -> Vec<[f32; 2]> {
+        if self.stride < 2 {
+            return Vec::new();
+        }
+        self.values.chunks_exact(self.stride)
+            .map(|v| [v[0], 1.0 - v[1]])
+            .collect()
+    }
+}
+
+struct ColladaDocument<'a> {
+    geometries: HashMap<&'a str, &'a XmlElement>,
+    nodes: HashMap<&'a str, &'a XmlElement>,
+}
+
+fn parse_collada(content: &str) -> Result<MeshBuilder, MeshFormatError> {
+    let root = xml::parse_str(content).map_err(MeshFormatError::new)?;
+    if root.name != "COLLADA" {
+        return Err(MeshFormatError::new("not a COLLADA document"));
+    }
+
+    let document = ColladaDocument {
+        geometries: elements_by_id(&root, "library_geometries", "geometry"),
+        nodes: elements_by_id(&root, "library_nodes", "node"),
+    };
+
+    // URDF のリンク座標系は Z 上向きでメートル単位
+    let asset = root.child("asset");
+    let up = match asset.and_then(|asset| asset.child_text("up_axis")) {
+        Some("Y_UP") | None => Mat4::from_rotation_x(FRAC_PI_2),
+        Some("X_UP") => Mat4::from_rotation_y(-FRAC_PI_2),
+        Some(_) => Mat4::IDENTITY,
+    };
+    let meter = asset.and_then(|asset| asset.child("unit"))
+        .and_then(|unit| unit.attribute("meter"))
+        .and_then(|meter| meter.trim().parse::<f32>().ok())
+        .filter(|meter| *meter > 0.0)
+        .unwrap_or(1.0);
+    let up = up * Mat4::from_scale(Vec3::splat(meter));
+
+    let scene_url = root.child("scene")
+        .and_then(|scene| scene.child("instance_visual_scene"))
+        .and_then(|instance| instance.attribute("url"));
+    let scenes: Vec<&XmlElement> = root.children("library_visual_scenes")
+        .flat_map(|library| library.children("visual_scene"))
+        .collect();
+    let scene = scenes.iter()
+        .find(|scene| scene_url.is_some_and(|url| scene.attribute("id") == url.strip_prefix('#')))
+        .or_else(|| scenes.first());
+
+    let mut builder = MeshBuilder::default();
+    match scene {
+        Some(scene) => {
+            for node in scene.children("node") {
+                add_collada_node(&mut builder, &document, node, up, 0)?;
+            }
+        }
+        None => {
+            for geometry in document.geometries.values() {
+                add_collada_geometry(&mut builder, geometry, up)?;
+            }
+        }
+    }
+    Ok(builder)
+}
+
+fn elements_by_id<'a>(root: &'a XmlElement, library: &'a str, element: &'a str) -> HashMap<&'a str, &'a XmlElement> {
+    root.children(library)
+        .flat_map(|library| library.children(element))
+        .filter_map(|item| Some((item.attribute("id")?, item)))
+        .collect()
+}
+
+fn add_collada_node(
+    builder: &mut MeshBuilder,
+    document: &ColladaDocument,
+    node: &XmlElement,
+    parent: Mat4,
+    depth: usize,
+) -> Result<(), MeshFormatError> {
+    if depth > MAX_NODE_DEPTH {
+        return Err(MeshFormatError::new("COLLADA node hierarchy is too deep"));
+    }
+
+    let transform = parent * collada_node_transform(node);
+
+    for instance in node.children("instance_geometry") {
+        let geometry = instance.attribute("url")
+            .and_then(|url| document.geometries.get(url.trim_start_matches('#')));
+        if let Some(geometry) = geometry {
+            add_collada_geometry(builder, geometry, transform)?;
+        }
+    }
+    for instance in node.children("instance_node") {
+        let referenced = instance.attribute("url")
+            .and_then(|url| document.nodes.get(url.trim_start_matches('#')));
+        if let Some(referenced) = referenced {
+            add_collada_node(builder, document, referenced, transform, depth + 1)?;
+        }
+    }
+    for child in node.children("node") {
+        add_collada_node(builder, document, child, transform, depth + 1)?;
+    }
+    Ok(())
+}
+
+fn collada_node_transform(node: &XmlElement) -> Mat4 {
+    node.children.iter().fold(Mat4::IDENTITY, |transform, element| {
+        let values: Vec<f32> = xml::parse_values(&element.text)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|v| v as f32)
+            .collect();
+        let local = match (element.name.as_str(), values.as_slice()) {
+            // COLLADA の行列は行優先
+            ("matrix", v) if v.len() == 16 => Mat4::from_cols_slice(v).transpose(),
+            ("translate", [x, y, z]) => Mat4::from_translation(Vec3::new(*x, *y, *z)),
+            ("rotate", [x, y, z, angle]) => {
+                Mat4::from_axis_angle(Vec3::new(*x, *y, *z).normalize_or_zero(), angle.to_radians())
+            }
+            ("scale", [x, y, z]) => Mat4::from_scale(Vec3::new(*x, *y, *z)),
+            _ => Mat4::IDENTITY,
+        };
+        transform * local
+    })
+}
+
+fn add_collada_geometry(builder: &mut MeshBuilder, geometry: &XmlElement, transform: Mat4) -> Result<(), MeshFormatError> {
+    let Some(mesh) = geometry.child("mesh") else {
+        return Ok(());
+    };
+
+    let sources: HashMap<&str, ColladaSource> = mesh.children("source")
+        .filter_map(|source| {
+            let values = xml::parse_values(&source.child("float_array")?.text)?;
+            let stride = source.child("technique_common")
+                .and_then(|technique| technique.child("accessor"))
+                .and_then(|accessor| accessor.attribute("stride"))
+                .and_then(|stride| stride.parse().ok())
+                .unwrap_or(1);
+            let values = values.into_iter().map(|v| v as f32).collect();
+            Some((source.attribute("id")?, ColladaSource { values, stride }))
+        })
+        .collect();
+    let source = |url: &str| sources.get(url.trim_start_matches('#'));
+
+    // <vertices> は位置 (と場合によっては法線) のソースを束ねる
+    let vertices = mesh.child("vertices");
+    let vertex_input = |semantic: &str| {
+        vertices?.children("input")
+            .find(|input| input.attribute("semantic") == Some(semantic))
+            .and_then(|input| input.attribute("source"))
+    };
+    let Some(positions) = vertex_input("POSITION").and_then(source).map(ColladaSource::vec3s) else {
+        return Ok(());
+    };
+    let shared_normals = vertex_input("NORMAL").and_then(source).map(ColladaSource::vec3s);
+
+    for primitive in &mesh.children {
+        let polygons: Vec<Vec<u32>> = match primitive.name.as_str() {
+            "triangles" | "polylist" | "polygons" => collada_polygons(primitive),
+            _ => continue,
+        };
+
+        let inputs: Vec<(&str, &str, usize)> = primitive.children("input")
+            .filter_map(|input| {
+                let offset = input.attribute("offset")?.parse().ok()?;
+                Some((input.attribute("semantic")?, input.attribute("source")?, offset))
+            })
+            .collect();
+        let stride = inputs.iter().map(|(_, _, offset)| offset + 1).max().unwrap_or(1);
+        let offset_of = |semantic: &str| inputs.iter().find(|(s, _, _)| *s == semantic).map(|(_, _, offset)| *offset);
+        let Some(vertex_offset) = offset_of("VERTEX") else {
+            continue;
+        };
+
+        let (normals, normal_offset) = match inputs.iter().find(|(s, _, _)| *s == "NORMAL") {
+            Some((_, url, offset)) => (source(url).map(ColladaSource::vec3s).unwrap_or_default(), Some(*offset)),
+            // <vertices> 側の法線は位置と同じ添字を使う
+            None => (shared_normals.clone().unwrap_or_default(), shared_normals.as_ref().map(|_| vertex_offset)),
+        };
+        let (uvs, uv_offset) = match inputs.iter().find(|(s, _, _)| *s == "TEXCOORD") {
+            Some((_, url, offset)) => (source(url).map(ColladaSource::uvs).unwrap_or_default(), Some(*offset)),
+            None => (Vec::new(), None),
+        };
+
+        let mut corners = Vec::new();
+        for polygon in polygons {
+            let polygon: Vec<Corner> = polygon.chunks_exact(stride)
+                .map(|indices| {
+                    (
+                        indices[vertex_offset] as usize,
+                        normal_offset.map(|offset| indices[offset] as usize),
+                        uv_offset.map(|offset| indices[offset] as usize),
+                    )
+                })
+                .collect();
+            push_polygon(&mut corners, &polygon);
+        }
+
+        builder.add(indexed_primitive(&corners, &positions, &normals, &uvs)?, transform)?;
+    }
+    Ok(())
+}
+
+// 各多角形の添字列 (頂点数 x 入力数) を返す
+fn collada_polygons(primitive: &XmlElement) -> Vec<Vec<u32>> {
+    let parse = |text: &str| -> Vec<u32> {
+        text.split_whitespace().filter_map(|value| value.parse().ok()).collect()
+    };
+    let stride = primitive.children("input")
+        .filter_map(|input| input.attribute("offset")?.parse::<usize>().ok())
+        .max()
+        .map_or(1, |offset| offset + 1);
+
+    match primitive.name.as_str() {
+        "triangles" => {
+            let indices = primitive.child_text("p").map(parse).unwrap_or_default();
+            indices.chunks_exact(stride * 3).map(<[u32]>::to_vec).collect()
+        }
+        "polylist" => {
+            let counts = primitive.child_text("vcount").map(parse).unwrap_or_default();
+            let indices = primitive.child_text("p").map(parse).unwrap_or_default();
+            let mut polygons = Vec::new();
+            let mut start = 0;
+            for count in counts {
+                let end = start + count as usize * stride;
+                let Some(polygon) = indices.get(start..end) else {
+                    break;
+                };
+                polygons.push(polygon.to_vec());
+                start = end;
+            }
+            polygons
+        }
+        _ => primitive.children("p").map(|p| parse(&p.text)).collect(),
+    }
+}
+
+fn parse_gltf(bytes: &[u8], base: Option<&Path>) -> Result<MeshBuilder, MeshFormatError> {
+    let gltf_error = |e: gltf::Error| MeshFormatError::new(e.to_string());
+    let gltf::Gltf { document, blob } = gltf::Gltf::from_slice(bytes).map_err(gltf_error)?;
+    // URDF のメッシュは解決済みのファイルパスで読むので、外部 .bin もファイルシステムから読む
+    let buffers = gltf::import_buffers(&document, base, blob).map_err(gltf_error)?;
+
+    // glTF は Y 上向き、URDF のリンク座標系は Z 上向き
+    let up = Mat4::from_rotation_x(FRAC_PI_2);
+    let mut builder = MeshBuilder::default();
+    match document.default_scene().or_else(|| document.scenes().next()) {
+        Some(scene) => {
+            for node in scene.nodes() {
+                add_gltf_node(&mut builder, &node, &buffers, up)?;
+            }
+        }
+        None => {
+            for mesh in document.meshes() {
+                add_gltf_mesh(&mut builder, &mesh, &buffers, up)?;
+            }
+        }
+    }
+    Ok(builder)
+}
+
+fn add_gltf_node(
+    builder: &mut MeshBuilder,
+    node: &gltf::Node,
+    buffers: &[gltf::buffer::Data],
+    parent: Mat4,
+) -> Result<(), MeshFormatError> {
+    let transform = parent * Mat4::from_cols_array_2d(&node.transform().matrix());
+    if let Some(mesh) = node.mesh() {
+        add_gltf_mesh(builder, &mesh, buffers, transform)?;
+    }
+    for child in node.children() {
+        add_gltf_node(builder, &child, buffers, transform)?;
+    }
+    Ok(())
+}
+
+fn add_gltf_mesh(
+    builder: &mut MeshBuilder,
+    mesh: &gltf::Mesh,
+    buffers: &[gltf::buffer::Data],
+    transform: Mat4,
+) -> Result<(), MeshFormatError> {
+    for primitive in mesh.primitives() {
+        if primitive.mode() != gltf::mesh::Mode::Triangles {
+            continue;
+        }
+
+        let reader = primitive.reader(|buffer| buffers.get(buffer.index()).map(|data| data.0.as_slice()));
+        let Some(positions) = reader.read_positions() else {
+            continue;
+        };
+        let positions: Vec<Vec3> = positions.map(Vec3::from_array).collect();
+        let indices = match reader.read_indices() {
+            Some(indices) => indices.into_u32().collect(),
+            None => (0..positions.len() as u32).collect(),
+        };
+
+        builder.add(Primitive {
+            normals: reader.read_normals().map(|normals| normals.map(Vec3::from_array).collect()),
+            uvs: reader.read_tex_coords(0).map(|uvs| uvs.into_f32().collect()),
+            positions,
+            indices,
+        }, transform)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 三角形ごとの頂点位置
+    fn triangles(builder: &MeshBuilder) -> Vec<[Vec3; 3]> {
+        builder.indices.chunks_exact(3)
+            .map(|triangle| [triangle[0], triangle[1], triangle[2]].map(|i| Vec3::from_array(builder.positions[i as usize])))
+            .collect()
+    }
+
+    fn assert_points(actual: &[Vec3], expected: &[Vec3]) {
+        assert_eq!(actual.len(), expected.len(), "{:?}", actual);
+        for (a, e) in actual.iter().zip(expected) {
+            assert!(a.abs_diff_eq(*e, 1e-5), "{:?} != {:?}", actual, expected);
+        }
+    }
+
+    #[test]
+    fn obj_quads_are_triangulated_with_relative_indices() {
+        let builder = parse_obj("
+            v 0 0 0
+            v 1 0 0
+            v 1 1 0
+            v 0 1 0
+            vn 0 0 1
+            # 4 頂点の面と、末尾からの相対添字の面
+            f 1//1 2//1 3//1 4//1
+            f -4//-1 -2//-1 -1//-1
+        ").unwrap();
+
+        let expected = [
+            [Vec3::ZERO, Vec3::X, Vec3::new(1.0, 1.0, 0.0)],
+            [Vec3::ZERO, Vec3::new(1.0, 1.0, 0.0), Vec3::Y],
+            [Vec3::ZERO, Vec3::new(1.0, 1.0, 0.0), Vec3::Y],
+        ];
+        let actual = triangles(&builder);
+        assert_eq!(actual.len(), expected.len());
+        for (a, e) in actual.iter().zip(&expected) {
+            assert_points(a, e);
+        }
+        assert!(builder.normals.iter().all(|n| *n == [0.0, 0.0, 1.0]));
+    }
+
+    #[test]
+    fn obj_faces_keep_texture_coordinates_and_normals() {
+        let builder = parse_obj("
+            v 0 0 0
+            v 1 0 0
+            v 0 1 0
+            vt 0 0
+            vt 1 0
+            vt 0 1
+            vn 0 0 -1
+            f 1/1/1 2/2/1 3/3/1
+        ").unwrap();
+
+        // OBJ の v は下から上、bevy の UV は上から下
+        assert_eq!(builder.uvs, [[0.0, 1.0], [1.0, 1.0], [0.0, 0.0]]);
+        // ファイルの法線は面の向きから計算し直さない
+        assert!(builder.normals.iter().all(|n| *n == [0.0, 0.0, -1.0]));
+        assert!(parse_obj("v 0 0 0\nf 1 2 3").is_err());
+    }
+
+    const COLLADA_POSITIONS: &str = r##"
+        <source id="positions">
+          <float_array count="15">0 0 0  1 0 0  1 1 0  0 1 0  0 0 1</float_array>
+          <technique_common><accessor source="#positions-array" count="5" stride="3"/></technique_common>
+        </source>
+        <vertices id="vertices"><input semantic="POSITION" source="#positions"/></vertices>"##;
+
+    fn collada(asset: &str, primitive: &str) -> MeshBuilder {
+        parse_collada(&format!(
+            r##"<COLLADA><asset>{}</asset><library_geometries><geometry id="mesh"><mesh>{}{}</mesh></geometry></library_geometries></COLLADA>"##,
+            asset, COLLADA_POSITIONS, primitive
+        )).unwrap()
+    }
+
+    #[test]
+    fn collada_polylist_uses_vertex_counts() {
+        let builder = collada("<up_axis>Z_UP</up_axis>", r##"
+            <polylist count="2">
+              <input semantic="VERTEX" source="#vertices" offset="0"/>
+              <vcount>4 3</vcount>
+              <p>0 1 2 3  0 1 4</p>
+            </polylist>"##);
+
+        let actual = triangles(&builder);
+        assert_eq!(actual.len(), 3);
+        assert_points(&actual[0], &[Vec3::ZERO, Vec3::X, Vec3::new(1.0, 1.0, 0.0)]);
+        assert_points(&actual[1], &[Vec3::ZERO, Vec3::new(1.0, 1.0, 0.0), Vec3::Y]);
+        assert_points(&actual[2], &[Vec3::ZERO, Vec3::X, Vec3::Z]);
+    }
+
+    #[test]
+    fn collada_triangles_follow_up_axis_and_unit() {
+        let triangle = r##"
+            <triangles count="1">
+              <input semantic="VERTEX" source="#vertices" offset="0"/>
+              <p>0 1 3</p>
+            </triangles>"##;
+
+        // Z_UP はそのまま、Y_UP は Y 軸を Z 軸に向ける。既定は Y_UP
+        let z_up = collada("<up_axis>Z_UP</up_axis>", triangle);
+        assert_points(&triangles(&z_up)[0], &[Vec3::ZERO, Vec3::X, Vec3::Y]);
+        let y_up = collada("<up_axis>Y_UP</up_axis>", triangle);
+        assert_points(&triangles(&y_up)[0], &[Vec3::ZERO, Vec3::X, Vec3::Z]);
+        let default = collada("", triangle);
+        assert_points(&triangles(&default)[0], &[Vec3::ZERO, Vec3::X, Vec3::Z]);
+
+        let millimeters = collada(r#"<unit name="millimeter" meter="0.001"/><up_axis>Z_UP</up_axis>"#, triangle);
+        assert_points(&triangles(&millimeters)[0], &[Vec3::ZERO, Vec3::X * 0.001, Vec3::Y * 0.001]);
+    }
+
+    // 1 つの三角形だけを持つ GLB を組み立てる
+    fn glb_triangle(positions: [[f32; 3]; 3]) -> Vec<u8> {
+        let bin: Vec<u8> = positions.iter().flatten().flat_map(|v| v.to_le_bytes()).collect();
+        let mut json = format!(
+            r#"{{"asset":{{"version":"2.0"}},"buffers":[{{"byteLength":{len}}}],
+                "bufferViews":[{{"buffer":0,"byteLength":{len}}}],
+                "accessors":[{{"bufferView":0,"componentType":5126,"count":3,"type":"VEC3","min":[0,0,0],"max":[1,1,1]}}],
+                "meshes":[{{"primitives":[{{"attributes":{{"POSITION":0}}}}]}}],
+                "nodes":[{{"mesh":0}}],"scenes":[{{"nodes":[0]}}],"scene":0}}"#,
+            len = bin.len()
+        ).into_bytes();
+        while json.len() % 4 != 0 {
+            json.push(b' ');
+        }
+
+        let mut glb = Vec::new();
+        glb.extend_from_slice(b"glTF");
+        glb.extend_from_slice(&2u32.to_le_bytes());
+        glb.extend_from_slice(&((12 + 8 + json.len() + 8 + bin.len()) as u32).to_le_bytes());
+        glb.extend_from_slice(&(json.len() as u32).to_le_bytes());
+        glb.extend_from_slice(b"JSON");
+        glb.extend_from_slice(&json);
+        glb.extend_from_slice(&(bin.len() as u32).to_le_bytes());
+        glb.extend_from_slice(b"BIN\0");
+        glb.extend_from_slice(&bin);
+        glb
+    }
+
+    #[test]
+    fn gltf_y_up_is_turned_to_z_up() {
+        let glb = glb_triangle([[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]]);
+        let builder = parse_gltf(&glb, None).unwrap();
+
+        assert_points(&triangles(&builder)[0], &[Vec3::ZERO, Vec3::X, Vec3::Z]);
+        // 法線が無いので面から計算し、やはり Z 上向きの座標系で表す
+        assert!(builder.normals.iter().all(|n| Vec3::from_array(*n).abs_diff_eq(Vec3::NEG_Y, 1e-5)), "{:?}", builder.normals);
+    }
+}
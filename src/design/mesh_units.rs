@@ -0,0 +1,97 @@
+use bevy::prelude::*;
+use std::collections::HashMap;
+
+use super::model_config::MeshUnits;
+
+// メッシュの大きさがロボットの寸法のこの倍以上なら単位違いを疑う
+const SUSPICIOUS_RATIO: f64 = 30.0;
+const CANDIDATES: [(MeshUnits, f64); 2] = [
+    (MeshUnits::Centimetres, 0.01),
+    (MeshUnits::Millimetres, 0.001),
+];
+
+#[derive(Clone, Copy, Debug)]
+pub struct UnitCorrection {
+    pub units: MeshUnits,
+    pub scale: f64,
+    // 推定に使った代表寸法 (中央値, m)
+    pub mesh_size: f64,
+    pub robot_size: f64,
+}
+
+// 関節の間隔と慣性から求めた寸法に比べてメッシュが桁違いに大きければ、合いそうな単位を返す
+pub fn detect_mesh_units(
+    robot: &urdf_rs::Robot,
+    mesh_bounds: impl Fn(&str) -> Option<(Vec3, Vec3)>,
+) -> Option<UnitCorrection> {
+    let robot_size = median(reference_lengths(robot))?;
+
+    let mut bounds_cache: HashMap<&str, Option<(Vec3, Vec3)>> = HashMap::new();
+    let mesh_sizes = mesh_geometries(robot)
+        .filter_map(|(filename, scale)| {
+            let (min, max) = (*bounds_cache.entry(filename).or_insert_with(|| mesh_bounds(filename)))?;
+            Some((max - min).max_element() as f64 * scale)
+        })
+        .filter(|size| *size > 0.0)
+        .collect();
+    let mesh_size = median(mesh_sizes)?;
+
+    let ratio = mesh_size / robot_size;
+    if ratio < SUSPICIOUS_RATIO {
+        return None;
+    }
+
+    let (units, scale) = CANDIDATES.into_iter()
+        .min_by(|a, b| (ratio * a.1).ln().abs().total_cmp(&(ratio * b.1).ln().abs()))?;
+    Some(UnitCorrection { units, scale, mesh_size, robot_size })
+}
+
+pub fn scale_meshes(robot: &mut urdf_rs::Robot, factor: f64) {
+    for link in &mut robot.links {
+        let geometries = link.visual.iter_mut().map(|v| &mut v.geometry)
+            .chain(link.collision.iter_mut().map(|c| &mut c.geometry));
+        for geometry in geometries {
+            if let urdf_rs::Geometry::Mesh { scale, .. } = geometry {
+                let current = scale.as_ref().map_or([1.0; 3], |s| s.0);
+                *scale = Some(urdf_rs::Vec3(current.map(|v| v * factor)));
+            }
+        }
+    }
+}
+
+fn mesh_geometries(robot: &urdf_rs::Robot) -> impl Iterator<Item = (&str, f64)> {
+    robot.links.iter()
+        .flat_map(|link| {
+            link.visual.iter().map(|v| &v.geometry)
+                .chain(link.collision.iter().map(|c| &c.geometry))
+        })
+        .filter_map(|geometry| match geometry {
+            urdf_rs::Geometry::Mesh { filename, scale } => {
+                let scale = scale.as_ref().map_or(1.0, |s| s.0.iter().fold(0.0, |max: f64, v| max.max(v.abs())));
+                Some((filename.as_str(), scale))
+            }
+            _ => None,
+        })
+}
+
+// 関節原点どうしの距離と、慣性から逆算した大きさ (同じ慣性を持つ立方体の一辺)
+fn reference_lengths(robot: &urdf_rs::Robot) -> Vec<f64> {
+    let joint_offsets = robot.joints.iter()
+        .map(|joint| joint.origin.xyz.0.iter().map(|v| v * v).sum::<f64>().sqrt());
+
+    let inertia_sizes = robot.links.iter()
+        .filter(|link| link.inertial.mass.value > 0.0)
+        .map(|link| {
+            let inertia = &link.inertial.inertia;
+            (2.0 * (inertia.ixx + inertia.iyy + inertia.izz) / link.inertial.mass.value).sqrt()
+        });
+
+    joint_offsets.chain(inertia_sizes)
+        .filter(|length| length.is_finite() && *length > 1.0e-6)
+        .collect()
+}
+
+fn median(mut values: Vec<f64>) -> Option<f64> {
+    values.sort_by(f64::total_cmp);
+    values.get(values.len() / 2).copied()
+}
@@ -0,0 +1,1412 @@
+use bevy::math::DMat3;
+use bevy::prelude::*;
+use std::collections::HashMap;
+use std::f32::consts::PI;
+use std::fmt;
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+
+use quick_xml::escape::escape;
+
+use crate::robot::actuator::ActuatorInterface;
+use super::loader::{pose_to_transform, read_file_to_string_smart, transform_to_pose, ImportedModel};
+use super::sdf::{SdfLight, SdfLightKind};
+use super::transmission::Transmission;
+use super::xml::{self, parse_bool, parse_values, vec3, XmlElement};
+
+const MAX_INCLUDE_DEPTH: usize = 16;
+const DEFAULT_CLASS: &str = "main";
+// MuJoCo の既定値
+const DEFAULT_DENSITY: f32 = 1000.0;
+const DEFAULT_RGBA: [f64; 4] = [0.5, 0.5, 0.5, 1.0];
+// MuJoCo のビューアが既定で表示するのはグループ 0〜2。3 以降は衝突専用として隠すのが慣例
+const HIDDEN_GROUP: i32 = 3;
+// 複数の関節を持つ body を分けたときの中間リンクの質量
+const INTERMEDIATE_LINK_MASS: f64 = 1.0e-3;
+const WORLD_LINK: &str = "world";
+
+#[derive(Debug, Clone)]
+pub struct MjcfError {
+    pub file: PathBuf,
+    pub message: String,
+}
+
+impl MjcfError {
+    fn new(file: &Path, message: impl Into<String>) -> Self {
+        Self {
+            file: file.to_path_buf(),
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for MjcfError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.file.display(), self.message)
+    }
+}
+
+impl std::error::Error for MjcfError {}
+
+pub struct MjcfDocument {
+    pub name: String,
+    pub models: Vec<ImportedModel>,
+    pub lights: Vec<SdfLight>,
+}
+
+pub fn is_mjcf(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "xml" || ext == "mjcf")
+        && read_file_to_string_smart(path).is_some_and(|content| content.contains("<mujoco"))
+}
+
+pub fn read_file(path: &Path) -> Result<MjcfDocument, MjcfError> {
+    let root = parse_document(path)?;
+    if root.name != "mujoco" {
+        return Err(MjcfError::new(path, format!("root element is <{}>, expected <mujoco>", root.name)));
+    }
+
+    // include のパスはメインのファイルからの相対パス
+    let base_dir = path.parent().unwrap_or(Path::new(".")).to_path_buf();
+    let root = expand_includes(root, path, &base_dir, 0)?;
+
+    let mut importer = MjcfImporter::new(&root, &base_dir);
+    let mut document = MjcfDocument {
+        name: root.attribute("model")
+            .map(str::to_string)
+            .unwrap_or_else(|| path.file_stem().unwrap_or_default().to_string_lossy().into_owned()),
+        models: Vec::new(),
+        lights: Vec::new(),
+    };
+
+    for worldbody in root.children("worldbody") {
+        for body in worldbody.children("body") {
+            document.models.push(importer.top_level_body(body));
+        }
+        if let Some(model) = importer.static_geoms(worldbody, &document.name) {
+            document.models.push(model);
+        }
+        document.lights.extend(worldbody.children("light").map(|light| importer.light(light)));
+    }
+
+    if document.models.is_empty() {
+        return Err(MjcfError::new(path, "no <body> or static <geom> in <worldbody>"));
+    }
+
+    importer.apply_equalities(&root, &mut document.models);
+    importer.apply_actuators(&root, &mut document.models);
+
+    if let Some(first) = document.models.first_mut() {
+        first.warnings.append(&mut importer.warnings);
+    }
+    if let [model] = document.models.as_mut_slice() {
+        model.robot.name = document.name.clone();
+    }
+
+    Ok(document)
+}
+
+fn expand_includes(mut element: XmlElement, file: &Path, base_dir: &Path, depth: usize) -> Result<XmlElement, MjcfError> {
+    let mut children = Vec::new();
+    for child in std::mem::take(&mut element.children) {
+        if child.name != "include" {
+            children.push(expand_includes(child, file, base_dir, depth)?);
+            continue;
+        }
+
+        let Some(included) = child.attribute("file") else {
+            return Err(MjcfError::new(file, "<include> without file"));
+        };
+        if depth >= MAX_INCLUDE_DEPTH {
+            return Err(MjcfError::new(file, format!("includes nested too deeply at '{}'", included)));
+        }
+
+        let included_file = base_dir.join(included);
+        let included_root = parse_document(&included_file)?;
+        let included_root = expand_includes(included_root, &included_file, base_dir, depth + 1)?;
+        // include されたファイルの <mujoco> の中身をその場に展開する
+        children.extend(included_root.children);
+    }
+    element.children = children;
+    Ok(element)
+}
+
+struct Compiler {
+    degrees: bool,
+    eulerseq: Vec<char>,
+    meshdir: PathBuf,
+    autolimits: bool,
+}
+
+struct MeshAsset {
+    filename: String,
+    scale: Option<Vec3>,
+}
+
+// クラス名 -> 要素名 -> 属性
+type Defaults = HashMap<String, HashMap<String, Vec<(String, String)>>>;
+
+// 要素そのものの属性を優先し、なければ default クラスの値を使う
+struct Attributes<'a> {
+    element: &'a XmlElement,
+    defaults: Option<&'a Vec<(String, String)>>,
+}
+
+impl<'a> Attributes<'a> {
+    fn get(&self, key: &str) -> Option<&'a str> {
+        self.element.attribute(key).or_else(|| {
+            self.defaults?.iter()
+                .find(|(k, _)| k == key)
+                .map(|(_, v)| v.as_str())
+        })
+    }
+
+    fn values(&self, key: &str) -> Option<Vec<f64>> {
+        parse_values(self.get(key)?)
+    }
+
+    fn f64(&self, key: &str) -> Option<f64> {
+        self.get(key)?.trim().parse().ok()
+    }
+
+    fn i32(&self, key: &str) -> Option<i32> {
+        self.get(key)?.trim().parse().ok()
+    }
+}
+
+struct MjcfImporter {
+    compiler: Compiler,
+    defaults: Defaults,
+    meshes: HashMap<String, MeshAsset>,
+    materials: HashMap<String, [f64; 4]>,
+    warnings: Vec<String>,
+    unnamed_bodies: usize,
+}
+
+// 1 つのトップレベル body から作る URDF のリンク・関節
+#[derive(Default)]
+struct BodyTree {
+    links: Vec<urdf_rs::Link>,
+    joints: Vec<urdf_rs::Joint>,
+}
+
+struct MassSample {
+    mass: f32,
+    // リンク座標系での形状の位置・姿勢
+    transform: Transform,
+    // 形状座標系での主慣性モーメント (単位質量あたり)
+    unit_inertia: Vec3,
+}
+
+impl MjcfImporter {
+    fn new(root: &XmlElement, base_dir: &Path) -> Self {
+        let mut importer = Self {
+            compiler: Compiler {
+                degrees: true,
+                eulerseq: vec!['x', 'y', 'z'],
+                meshdir: base_dir.to_path_buf(),
+                autolimits: true,
+            },
+            defaults: HashMap::new(),
+            meshes: HashMap::new(),
+            materials: HashMap::new(),
+            warnings: Vec::new(),
+            unnamed_bodies: 0,
+        };
+
+        for compiler in root.children("compiler") {
+            if let Some(angle) = compiler.attribute("angle") {
+                importer.compiler.degrees = angle != "radian";
+            }
+            if let Some(eulerseq) = compiler.attribute("eulerseq") {
+                importer.compiler.eulerseq = eulerseq.chars().collect();
+            }
+            if let Some(dir) = compiler.attribute("meshdir").or_else(|| compiler.attribute("assetdir")) {
+                importer.compiler.meshdir = base_dir.join(dir);
+            }
+            if let Some(autolimits) = compiler.attribute("autolimits") {
+                importer.compiler.autolimits = parse_bool(autolimits);
+            }
+        }
+
+        for default in root.children("default") {
+            let class = default.attribute("class").unwrap_or(DEFAULT_CLASS);
+            let inherited = importer.defaults.get(class).cloned().unwrap_or_default();
+            importer.collect_defaults(default, class, &inherited);
+        }
+
+        for asset in root.children("asset") {
+            for mesh in asset.children("mesh") {
+                importer.mesh_asset(mesh);
+            }
+            for material in asset.children("material") {
+                let attributes = resolve_attributes(&importer.defaults, material, DEFAULT_CLASS);
+                if let (Some(name), Some(rgba)) = (material.attribute("name"), rgba(&attributes)) {
+                    importer.materials.insert(name.to_string(), rgba);
+                }
+            }
+        }
+
+        importer
+    }
+
+    fn collect_defaults(&mut self, element: &XmlElement, class: &str, inherited: &HashMap<String, Vec<(String, String)>>) {
+        let mut defaults = inherited.clone();
+        for child in element.children.iter().filter(|child| child.name != "default") {
+            let attributes = defaults.entry(child.name.clone()).or_default();
+            for (key, value) in &child.attributes {
+                match attributes.iter_mut().find(|(k, _)| k == key) {
+                    Some(attribute) => attribute.1 = value.clone(),
+                    None => attributes.push((key.clone(), value.clone())),
+                }
+            }
+        }
+
+        for nested in element.children("default") {
+            let nested_class = nested.attribute("class").unwrap_or(class);
+            self.collect_defaults(nested, nested_class, &defaults);
+        }
+        self.defaults.insert(class.to_string(), defaults);
+    }
+
+    fn mesh_asset(&mut self, element: &XmlElement) {
+        let attributes = resolve_attributes(&self.defaults, element, DEFAULT_CLASS);
+        let Some(file) = attributes.get("file") else {
+            self.warnings.push(format!("mesh asset '{}' has no file", element.attribute("name").unwrap_or_default()));
+            return;
+        };
+
+        // 名前がなければファイル名 (拡張子なし) で参照される
+        let name = element.attribute("name")
+            .map(str::to_string)
+            .unwrap_or_else(|| Path::new(file).file_stem().unwrap_or_default().to_string_lossy().into_owned());
+        let asset = MeshAsset {
+            filename: self.compiler.meshdir.join(file).to_string_lossy().replace('\\', "/"),
+            scale: attributes.get("scale").and_then(vec3),
+        };
+        self.meshes.insert(name, asset);
+    }
+
+    fn angle(&self, value: f64) -> f32 {
+        if self.compiler.degrees {
+            value.to_radians() as f32
+        } else {
+            value as f32
+        }
+    }
+
+    // 小文字は回転する軸 (内因性)、大文字は固定軸 (外因性) まわりの回転
+    fn euler(&self, angles: &[f64]) -> Quat {
+        let mut rotation = Quat::IDENTITY;
+        for (&axis, &angle) in self.compiler.eulerseq.iter().zip(angles) {
+            let axis_vector = match axis.to_ascii_lowercase() {
+                'x' => Vec3::X,
+                'y' => Vec3::Y,
+                _ => Vec3::Z,
+            };
+            let step = Quat::from_axis_angle(axis_vector, self.angle(angle));
+            rotation = if axis.is_ascii_lowercase() { rotation * step } else { step * rotation };
+        }
+        rotation
+    }
+
+    // 位置・姿勢は default クラスでは指定できないので要素の属性だけを見る
+    fn frame(&self, element: &XmlElement) -> Transform {
+        let translation = element.attribute("pos").and_then(vec3).unwrap_or(Vec3::ZERO);
+        let values = |key: &str| element.attribute(key).and_then(parse_values);
+
+        let rotation = if let Some([w, x, y, z]) = values("quat").as_deref() {
+            Quat::from_xyzw(*x as f32, *y as f32, *z as f32, *w as f32).normalize()
+        } else if let Some([x, y, z, angle]) = values("axisangle").as_deref() {
+            let axis = Vec3::new(*x as f32, *y as f32, *z as f32).try_normalize().unwrap_or(Vec3::Z);
+            Quat::from_axis_angle(axis, self.angle(*angle))
+        } else if let Some(angles @ [_, _, _]) = values("euler").as_deref() {
+            self.euler(angles)
+        } else if let Some([x1, x2, x3, y1, y2, y3]) = values("xyaxes").as_deref() {
+            let x = Vec3::new(*x1 as f32, *x2 as f32, *x3 as f32).normalize_or_zero();
+            let y = Vec3::new(*y1 as f32, *y2 as f32, *y3 as f32);
+            let y = (y - x * x.dot(y)).normalize_or_zero();
+            Quat::from_mat3(&Mat3::from_cols(x, y, x.cross(y))).normalize()
+        } else if let Some(z) = element.attribute("zaxis").and_then(vec3) {
+            Quat::from_rotation_arc(Vec3::Z, z.try_normalize().unwrap_or(Vec3::Z))
+        } else {
+            Quat::IDENTITY
+        };
+
+        Transform::from_translation(translation).with_rotation(rotation)
+    }
+
+    fn body_name(&mut self, element: &XmlElement) -> String {
+        match element.attribute("name") {
+            Some(name) => name.to_string(),
+            None => {
+                self.unnamed_bodies += 1;
+                format!("body{}", self.unnamed_bodies)
+            }
+        }
+    }
+
+    // トップレベルの body ごとに 1 つのモデルを作る。
+    // freejoint があれば浮いたロボット、関節がなければ固定、それ以外はワールドに関節でつながる
+    fn top_level_body(&mut self, element: &XmlElement) -> ImportedModel {
+        let class = element.attribute("childclass").unwrap_or(DEFAULT_CLASS);
+        let name = self.body_name(element);
+        let pose = self.frame(element);
+        let joints = movable_joints(element);
+        let is_free = joints.iter().any(|joint| joint_type(&self.defaults, joint, class) == "free");
+
+        let mut tree = BodyTree::default();
+        let mut fixed_links = Vec::new();
+        let mut warnings = Vec::new();
+
+        if is_free {
+            if joints.len() > 1 {
+                warnings.push(format!("body '{}' combines a free joint with other joints; only the free joint is used", name));
+            }
+            self.body_contents(element, class, &name, Vec3::ZERO, &mut tree);
+        } else if joints.is_empty() {
+            fixed_links.push(name.clone());
+            self.body_contents(element, class, &name, Vec3::ZERO, &mut tree);
+        } else {
+            tree.links.push(empty_link(WORLD_LINK));
+            fixed_links.push(WORLD_LINK.to_string());
+            self.body(element, class, name.clone(), (WORLD_LINK, Vec3::ZERO), Transform::IDENTITY, &mut tree);
+        }
+
+        ImportedModel {
+            robot: urdf_rs::Robot {
+                name,
+                links: tree.links,
+                joints: tree.joints,
+                materials: Vec::new(),
+            },
+            pose,
+            fixed_links,
+            warnings,
+            transmissions: Vec::new(),
+        }
+    }
+
+    // worldbody 直下の geom は地面に固定された 1 つのリンクにまとめる
+    fn static_geoms(&mut self, worldbody: &XmlElement, name: &str) -> Option<ImportedModel> {
+        let mut link = empty_link(WORLD_LINK);
+        let mut samples = Vec::new();
+        for geom in worldbody.children("geom") {
+            self.geom(geom, DEFAULT_CLASS, Transform::IDENTITY, &mut link, &mut samples);
+        }
+        if link.visual.is_empty() && link.collision.is_empty() {
+            return None;
+        }
+
+        Some(ImportedModel {
+            robot: urdf_rs::Robot {
+                name: format!("{}_world", name),
+                links: vec![link],
+                joints: Vec::new(),
+                materials: Vec::new(),
+            },
+            pose: Transform::IDENTITY,
+            fixed_links: vec![WORLD_LINK.to_string()],
+            warnings: Vec::new(),
+            transmissions: Vec::new(),
+        })
+    }
+
+    // parent: 親リンク名と、親 body 座標系から見た親リンク座標系の位置。
+    // URDF ではリンクの座標系が関節の座標系と一致するので、関節の pos だけリンクをずらす
+    fn body(
+        &mut self,
+        element: &XmlElement,
+        class: &str,
+        name: String,
+        parent: (&str, Vec3),
+        pose: Transform,
+        tree: &mut BodyTree,
+    ) {
+        let class = element.attribute("childclass").unwrap_or(class);
+        let (parent_link, parent_offset) = parent;
+        let parent_to_body = Transform::from_translation(-parent_offset) * pose;
+
+        let mut joints = movable_joints(element);
+        if joints.iter().any(|joint| joint_type(&self.defaults, joint, class) == "free") {
+            self.warnings.push(format!("nested body '{}' has a free joint; it is welded to its parent instead", name));
+            joints.clear();
+        }
+
+        if joints.is_empty() {
+            tree.joints.push(urdf_joint(
+                format!("{}_fixed", name),
+                urdf_rs::JointType::Fixed,
+                parent_link,
+                &name,
+                parent_to_body,
+                Vec3::Z,
+            ));
+            self.body_contents(element, class, &name, Vec3::ZERO, tree);
+            return;
+        }
+
+        // 1 つの body に複数の関節がある場合は、質量の小さい中間リンクを挟んで直列につなぐ
+        let mut previous_link = parent_link.to_string();
+        let mut previous_offset = None;
+        let count = joints.len();
+        for (index, joint) in joints.into_iter().enumerate() {
+            let attributes = resolve_attributes(&self.defaults, joint, class);
+            let offset = attributes.get("pos").and_then(vec3).unwrap_or(Vec3::ZERO);
+            let joint_name = joint.attribute("name")
+                .map(str::to_string)
+                .unwrap_or_else(|| format!("{}_joint{}", name, index));
+            let link_name = if index + 1 == count {
+                name.clone()
+            } else {
+                let link_name = format!("{}_{}", name, joint_name);
+                tree.links.push(empty_link(&link_name));
+                link_name
+            };
+
+            let origin = match previous_offset {
+                None => parent_to_body * Transform::from_translation(offset),
+                Some(previous) => Transform::from_translation(offset - previous),
+            };
+
+            let urdf_joint = self.joint(joint, class, joint_name, &previous_link, &link_name, origin);
+            tree.joints.push(urdf_joint);
+
+            previous_link = link_name;
+            previous_offset = Some(offset);
+        }
+
+        self.body_contents(element, class, &name, previous_offset.unwrap_or_default(), tree);
+    }
+
+    // offset: body 座標系から見たリンク座標系の位置
+    fn body_contents(&mut self, element: &XmlElement, class: &str, name: &str, offset: Vec3, tree: &mut BodyTree) {
+        let rebase = Transform::from_translation(-offset);
+        let mut link = empty_link(name);
+        let mut samples = Vec::new();
+
+        for geom in element.children("geom") {
+            self.geom(geom, class, rebase, &mut link, &mut samples);
+        }
+
+        link.inertial = match element.child("inertial") {
+            Some(inertial) => self.inertial(inertial, rebase),
+            None => inertial_from_geoms(&samples),
+        };
+        tree.links.push(link);
+
+        for child in element.children("body") {
+            let child_class = child.attribute("childclass").unwrap_or(class);
+            let child_name = self.body_name(child);
+            let pose = self.frame(child);
+            self.body(child, child_class, child_name, (name, offset), pose, tree);
+        }
+    }
+
+    fn joint(
+        &mut self,
+        element: &XmlElement,
+        class: &str,
+        name: String,
+        parent: &str,
+        child: &str,
+        origin: Transform,
+    ) -> urdf_rs::Joint {
+        let attributes = resolve_attributes(&self.defaults, element, class);
+        let joint_type = joint_type(&self.defaults, element, class);
+        let axis = attributes.get("axis").and_then(vec3).unwrap_or(Vec3::Z);
+
+        let range = attributes.values("range").filter(|range| range.len() == 2);
+        let limited = match attributes.get("limited") {
+            Some("true") => true,
+            Some("false") => false,
+            _ => self.compiler.autolimits && range.is_some(),
+        };
+        let (lower, upper) = match (&range, limited) {
+            (Some(range), true) => (range[0], range[1]),
+            _ => (0.0, 0.0),
+        };
+
+        let (joint_type, lower, upper) = match joint_type {
+            "hinge" if limited => (urdf_rs::JointType::Revolute, self.angle(lower) as f64, self.angle(upper) as f64),
+            "hinge" => (urdf_rs::JointType::Continuous, 0.0, 0.0),
+            "slide" => (urdf_rs::JointType::Prismatic, lower, upper),
+            other => {
+                self.warnings.push(format!("joint '{}' has unsupported type '{}'; it is fixed instead", name, other));
+                (urdf_rs::JointType::Fixed, 0.0, 0.0)
+            }
+        };
+
+        let damping = attributes.f64("damping").unwrap_or(0.0);
+        let friction = attributes.f64("frictionloss").unwrap_or(0.0);
+        let effort = attributes.values("actuatorfrcrange")
+            .map(|range| range.iter().fold(0.0_f64, |max, v| max.max(v.abs())))
+            .unwrap_or(0.0);
+
+        let mut joint = urdf_joint(name, joint_type, parent, child, origin, axis);
+        joint.limit = urdf_rs::JointLimit { lower, upper, effort, velocity: 0.0 };
+        if damping != 0.0 || friction != 0.0 {
+            joint.dynamics = Some(urdf_rs::Dynamics { damping, friction });
+        }
+        joint
+    }
+
+    fn geom(
+        &mut self,
+        element: &XmlElement,
+        class: &str,
+        rebase: Transform,
+        link: &mut urdf_rs::Link,
+        samples: &mut Vec<MassSample>,
+    ) {
+        let attributes = resolve_attributes(&self.defaults, element, class);
+        let geom_type = attributes.get("type").unwrap_or("sphere");
+        let size = attributes.values("size").unwrap_or_default();
+        let size = |index: usize| size.get(index).copied().unwrap_or(0.0);
+        let mut transform = self.frame(element);
+        let mut half_length = size(1);
+
+        // fromto は両端の点で軸 (Z) と長さを指定する
+        if let Some([x1, y1, z1, x2, y2, z2]) = attributes.values("fromto").as_deref() {
+            let from = Vec3::new(*x1 as f32, *y1 as f32, *z1 as f32);
+            let to = Vec3::new(*x2 as f32, *y2 as f32, *z2 as f32);
+            transform.translation = (from + to) / 2.0;
+            transform.rotation = Quat::from_rotation_arc(Vec3::Z, (to - from).try_normalize().unwrap_or(Vec3::Z));
+            half_length = ((to - from).length() / 2.0) as f64;
+        }
+
+        let name = element.attribute("name").unwrap_or_default().to_string();
+        let label = if name.is_empty() { link.name.clone() } else { format!("{}/{}", link.name, name) };
+        let (geometry, volume, unit_inertia) = match geom_type {
+            "sphere" => {
+                let r = size(0) as f32;
+                (urdf_rs::Geometry::Sphere { radius: size(0) }, 4.0 / 3.0 * PI * r.powi(3), Vec3::splat(0.4 * r * r))
+            }
+            "capsule" | "cylinder" => {
+                let (r, h) = (size(0) as f32, half_length as f32);
+                let geometry = if geom_type == "capsule" {
+                    urdf_rs::Geometry::Capsule { radius: size(0), length: half_length * 2.0 }
+                } else {
+                    urdf_rs::Geometry::Cylinder { radius: size(0), length: half_length * 2.0 }
+                };
+                // カプセルは半球の分だけ長い円柱として近似する
+                let length = if geom_type == "capsule" { 2.0 * (h + r) } else { 2.0 * h };
+                let radial = (3.0 * r * r + length * length) / 12.0;
+                (geometry, PI * r * r * length, Vec3::new(radial, radial, r * r / 2.0))
+            }
+            "box" => {
+                let half = Vec3::new(size(0) as f32, size(1) as f32, size(2) as f32);
+                let full = half * 2.0;
+                let geometry = urdf_rs::Geometry::Box { size: urdf_rs::Vec3(full.to_array().map(f64::from)) };
+                let squared = full * full;
+                let unit_inertia = Vec3::new(squared.y + squared.z, squared.x + squared.z, squared.x + squared.y) / 12.0;
+                (geometry, full.x * full.y * full.z, unit_inertia)
+            }
+            "ellipsoid" => {
+                let radius = size(0).max(size(1)).max(size(2));
+                self.warnings.push(format!("geom '{}' is an ellipsoid; it is approximated by a sphere", label));
+                let r = radius as f32;
+                (urdf_rs::Geometry::Sphere { radius }, 4.0 / 3.0 * PI * r.powi(3), Vec3::splat(0.4 * r * r))
+            }
+            "mesh" => {
+                let Some(mesh) = attributes.get("mesh").and_then(|mesh| self.meshes.get(mesh)) else {
+                    self.warnings.push(format!("geom '{}' references an unknown mesh", label));
+                    return;
+                };
+                let geometry = urdf_rs::Geometry::Mesh {
+                    filename: mesh.filename.clone(),
+                    scale: mesh.scale.map(|scale| urdf_rs::Vec3(scale.to_array().map(f64::from))),
+                };
+                // メッシュの体積は読み込み前には分からないので、質量の指定がある場合だけ点質量として扱う
+                (geometry, 0.0, Vec3::ZERO)
+            }
+            // 地面は physics::world 側で用意している
+            "plane" => return,
+            other => {
+                self.warnings.push(format!("geom '{}' has unsupported type '{}'", label, other));
+                return;
+            }
+        };
+
+        let transform = rebase * transform;
+        let origin = transform_to_pose(&transform);
+        let group = attributes.i32("group").unwrap_or(0);
+        let collides = attributes.i32("contype").unwrap_or(1) != 0 || attributes.i32("conaffinity").unwrap_or(1) != 0;
+
+        let mass = attributes.f64("mass")
+            .map(|mass| mass as f32)
+            .unwrap_or_else(|| attributes.f64("density").map(|d| d as f32).unwrap_or(DEFAULT_DENSITY) * volume);
+        if mass > 0.0 {
+            samples.push(MassSample { mass, transform, unit_inertia });
+        }
+
+        if group < HIDDEN_GROUP {
+            let color = rgba(&attributes)
+                .or_else(|| attributes.get("material").and_then(|material| self.materials.get(material)).copied())
+                .unwrap_or(DEFAULT_RGBA);
+            link.visual.push(urdf_rs::Visual {
+                name: (!name.is_empty()).then(|| name.clone()),
+                origin: origin.clone(),
+                geometry: geometry.clone(),
+                material: Some(urdf_rs::Material {
+                    name: String::new(),
+                    color: Some(urdf_rs::Color { rgba: urdf_rs::Vec4(color) }),
+                    texture: None,
+                }),
+            });
+        }
+        if collides {
+            link.collision.push(urdf_rs::Collision {
+                name: (!name.is_empty()).then_some(name),
+                origin,
+                geometry,
+            });
+        }
+    }
+
+    fn inertial(&self, element: &XmlElement, rebase: Transform) -> urdf_rs::Inertial {
+        let origin = rebase * self.frame(element);
+        let mass = element.attribute("mass").and_then(|mass| mass.trim().parse().ok()).unwrap_or(0.0);
+        let values = |key: &str| element.attribute(key).and_then(parse_values);
+
+        let inertia = match (values("fullinertia").as_deref(), values("diaginertia").as_deref()) {
+            (Some([ixx, iyy, izz, ixy, ixz, iyz]), _) => urdf_rs::Inertia {
+                ixx: *ixx, ixy: *ixy, ixz: *ixz, iyy: *iyy, iyz: *iyz, izz: *izz,
+            },
+            (_, Some([ixx, iyy, izz])) => urdf_rs::Inertia {
+                ixx: *ixx, ixy: 0.0, ixz: 0.0, iyy: *iyy, iyz: 0.0, izz: *izz,
+            },
+            _ => urdf_rs::Inertia { ixx: 0.0, ixy: 0.0, ixz: 0.0, iyy: 0.0, iyz: 0.0, izz: 0.0 },
+        };
+
+        urdf_rs::Inertial {
+            origin: transform_to_pose(&origin),
+            mass: urdf_rs::Mass { value: mass },
+            inertia,
+        }
+    }
+
+    fn light(&self, element: &XmlElement) -> SdfLight {
+        let attributes = resolve_attributes(&self.defaults, element, DEFAULT_CLASS);
+        let directional = attributes.get("directional").is_some_and(parse_bool)
+            || attributes.get("type").is_some_and(|kind| kind == "directional");
+        // cutoff は compiler の angle に関係なく度で指定する
+        let kind = if directional {
+            SdfLightKind::Directional
+        } else {
+            SdfLightKind::Spot {
+                outer_angle: (attributes.f64("cutoff").unwrap_or(45.0) as f32).to_radians(),
+            }
+        };
+
+        let color = match attributes.values("diffuse").as_deref() {
+            Some([r, g, b, ..]) => Color::rgb(*r as f32, *g as f32, *b as f32),
+            _ => Color::rgb(0.7, 0.7, 0.7),
+        };
+
+        SdfLight {
+            kind,
+            pose: Transform::from_translation(attributes.get("pos").and_then(vec3).unwrap_or(Vec3::ZERO)),
+            color,
+            range: 10.0,
+            direction: attributes.get("dir").and_then(vec3).unwrap_or(Vec3::NEG_Z),
+            cast_shadows: attributes.get("castshadow").map(parse_bool).unwrap_or(true),
+        }
+    }
+
+    // <equality><joint joint1 joint2 polycoef> の 1 次の項までを URDF の mimic として扱う
+    fn apply_equalities(&mut self, root: &XmlElement, models: &mut [ImportedModel]) {
+        for equality in root.children("equality").flat_map(|equality| equality.children("joint")) {
+            let attributes = resolve_attributes(&self.defaults, equality, DEFAULT_CLASS);
+            let (Some(follower), Some(leader)) = (attributes.get("joint1"), attributes.get("joint2")) else {
+                continue;
+            };
+            let coefficients = attributes.values("polycoef").unwrap_or_else(|| vec![0.0, 1.0]);
+            if coefficients.iter().skip(2).any(|c| *c != 0.0) {
+                self.warnings.push(format!("joint equality {} -> {} is nonlinear; only its linear part is used", leader, follower));
+            }
+
+            match find_joint(models, follower) {
+                Some(joint) => {
+                    joint.mimic = Some(urdf_rs::Mimic {
+                        joint: leader.to_string(),
+                        multiplier: Some(coefficients.get(1).copied().unwrap_or(1.0)),
+                        offset: Some(coefficients.first().copied().unwrap_or(0.0)),
+                    });
+                }
+                None => self.warnings.push(format!("joint equality references unknown joint '{}'", follower)),
+            }
+        }
+    }
+
+    // アクチュエータは伝達機構として関節に結び付け、出力範囲を関節の effort にする
+    fn apply_actuators(&mut self, root: &XmlElement, models: &mut [ImportedModel]) {
+        for actuator in root.children("actuator").flat_map(|actuator| actuator.children.iter()) {
+            let attributes = resolve_attributes(&self.defaults, actuator, DEFAULT_CLASS);
+            let name = actuator.attribute("name").unwrap_or(&actuator.name).to_string();
+            let Some(joint_name) = attributes.get("joint").or_else(|| attributes.get("jointinparent")) else {
+                self.warnings.push(format!("actuator '{}' does not drive a joint; it is ignored", name));
+                continue;
+            };
+
+            let max_abs = |range: Vec<f64>| range.iter().fold(0.0_f64, |max, v| max.max(v.abs()));
+            let gear = attributes.values("gear").and_then(|gear| gear.first().copied()).unwrap_or(1.0);
+            let force_limited = attributes.get("forcelimited").map(parse_bool).unwrap_or(true);
+            let effort = match attributes.values("forcerange") {
+                Some(range) if force_limited => max_abs(range),
+                _ if matches!(actuator.name.as_str(), "motor" | "general") => {
+                    attributes.values("ctrlrange").map(max_abs).unwrap_or(0.0) * gear.abs()
+                }
+                _ => 0.0,
+            };
+
+            let interface = match actuator.name.as_str() {
+                "motor" | "general" => ActuatorInterface::Effort,
+                "position" => ActuatorInterface::Position,
+                "velocity" => ActuatorInterface::Velocity,
+                other => {
+                    self.warnings.push(format!("actuator '{}' has unsupported type <{}>; it is ignored", name, other));
+                    continue;
+                }
+            };
+            if gear == 0.0 {
+                self.warnings.push(format!("actuator '{}' has a gear of 0; it is ignored", name));
+                continue;
+            }
+
+            let Some(model) = models.iter_mut().find(|model| model.robot.joints.iter().any(|joint| joint.name == joint_name)) else {
+                self.warnings.push(format!("actuator '{}' references unknown joint '{}'", name, joint_name));
+                continue;
+            };
+            if effort > 0.0 {
+                if let Some(joint) = model.robot.joints.iter_mut().find(|joint| joint.name == joint_name) {
+                    joint.limit.effort = effort;
+                }
+            }
+            model.transmissions.push(Transmission {
+                name: name.clone(),
+                joint: joint_name.to_string(),
+                actuator: name,
+                interface,
+                reduction: gear,
+            });
+        }
+    }
+}
+
+fn resolve_attributes<'a>(defaults: &'a Defaults, element: &'a XmlElement, class: &str) -> Attributes<'a> {
+    let class = element.attribute("class").unwrap_or(class);
+    Attributes {
+        element,
+        defaults: defaults.get(class).and_then(|defaults| defaults.get(&element.name)),
+    }
+}
+
+fn joint_type<'a>(defaults: &'a Defaults, joint: &'a XmlElement, class: &str) -> &'a str {
+    if joint.name == "freejoint" {
+        return "free";
+    }
+    resolve_attributes(defaults, joint, class).get("type").unwrap_or("hinge")
+}
+
+fn movable_joints(body: &XmlElement) -> Vec<&XmlElement> {
+    body.children.iter()
+        .filter(|child| child.name == "joint" || child.name == "freejoint")
+        .collect()
+}
+
+fn find_joint<'a>(models: &'a mut [ImportedModel], name: &str) -> Option<&'a mut urdf_rs::Joint> {
+    models.iter_mut()
+        .flat_map(|model| model.robot.joints.iter_mut())
+        .find(|joint| joint.name == name)
+}
+
+fn rgba(attributes: &Attributes) -> Option<[f64; 4]> {
+    match attributes.values("rgba")?.as_slice() {
+        [r, g, b, a] => Some([*r, *g, *b, *a]),
+        _ => None,
+    }
+}
+
+fn empty_link(name: &str) -> urdf_rs::Link {
+    urdf_rs::Link {
+        name: name.to_string(),
+        inertial: urdf_rs::Inertial {
+            origin: transform_to_pose(&Transform::IDENTITY),
+            mass: urdf_rs::Mass { value: INTERMEDIATE_LINK_MASS },
+            inertia: urdf_rs::Inertia { ixx: 0.0, ixy: 0.0, ixz: 0.0, iyy: 0.0, iyz: 0.0, izz: 0.0 },
+        },
+        visual: Vec::new(),
+        collision: Vec::new(),
+    }
+}
+
+fn urdf_joint(
+    name: String,
+    joint_type: urdf_rs::JointType,
+    parent: &str,
+    child: &str,
+    origin: Transform,
+    axis: Vec3,
+) -> urdf_rs::Joint {
+    urdf_rs::Joint {
+        name,
+        joint_type,
+        origin: transform_to_pose(&origin),
+        parent: urdf_rs::LinkName { link: parent.to_string() },
+        child: urdf_rs::LinkName { link: child.to_string() },
+        axis: urdf_rs::Axis { xyz: urdf_rs::Vec3(axis.to_array().map(f64::from)) },
+        limit: urdf_rs::JointLimit { lower: 0.0, upper: 0.0, effort: 0.0, velocity: 0.0 },
+        dynamics: None,
+        mimic: None,
+        safety_controller: None,
+    }
+}
+
+// inertial がない body は MuJoCo と同じく geom の形状と密度から質量・慣性を求める
+fn inertial_from_geoms(samples: &[MassSample]) -> urdf_rs::Inertial {
+    let mass: f32 = samples.iter().map(|sample| sample.mass).sum();
+    if mass <= 0.0 {
+        return empty_link("").inertial;
+    }
+
+    let center = samples.iter().map(|sample| sample.transform.translation * sample.mass).sum::<Vec3>() / mass;
+    let tensor = samples.iter().fold(Mat3::ZERO, |tensor, sample| {
+        let rotation = Mat3::from_quat(sample.transform.rotation);
+        let local = Mat3::from_diagonal(sample.unit_inertia * sample.mass);
+        // 平行軸の定理で重心まわりに移す
+        let d = sample.transform.translation - center;
+        let shift = (Mat3::IDENTITY * d.length_squared() - outer(d, d)) * sample.mass;
+        tensor + rotation * local * rotation.transpose() + shift
+    });
+
+    urdf_rs::Inertial {
+        origin: transform_to_pose(&Transform::from_translation(center)),
+        mass: urdf_rs::Mass { value: mass as f64 },
+        inertia: urdf_rs::Inertia {
+            ixx: tensor.x_axis.x as f64,
+            ixy: tensor.y_axis.x as f64,
+            ixz: tensor.z_axis.x as f64,
+            iyy: tensor.y_axis.y as f64,
+            iyz: tensor.z_axis.y as f64,
+            izz: tensor.z_axis.z as f64,
+        },
+    }
+}
+
+fn outer(a: Vec3, b: Vec3) -> Mat3 {
+    Mat3::from_cols(a * b.x, a * b.y, a * b.z)
+}
+
+fn parse_document(path: &Path) -> Result<XmlElement, MjcfError> {
+    xml::parse_document(path).map_err(|message| MjcfError::new(path, message))
+}
+
+// URDF のリンク座標系をそのまま body の座標系にするので、関節の pos は常に 0 になる
+pub fn write_robot(robot: &urdf_rs::Robot, fixed_root: bool, mesh_path: impl Fn(&str) -> PathBuf) -> String {
+    let mut writer = MjcfWriter {
+        robot,
+        mesh_path,
+        meshes: Vec::new(),
+        children: HashMap::new(),
+        body: String::new(),
+    };
+    for joint in &robot.joints {
+        writer.children.entry(joint.parent.link.as_str()).or_default().push(joint);
+    }
+
+    let root = robot.links.iter()
+        .find(|link| !robot.joints.iter().any(|joint| joint.child.link == link.name));
+    if let Some(root) = root {
+        writer.write_body(&root.name, None, fixed_root, 2);
+    }
+
+    let mut out = String::new();
+    let _ = writeln!(out, "<mujoco model=\"{}\">", escape(&robot.name));
+    let _ = writeln!(out, "  <compiler angle=\"radian\" autolimits=\"true\"/>");
+
+    if !writer.meshes.is_empty() {
+        let _ = writeln!(out, "  <asset>");
+        for (name, file, scale) in &writer.meshes {
+            let _ = write!(out, "    <mesh name=\"{}\" file=\"{}\"", escape(name), escape(file));
+            if let Some(scale) = scale {
+                let _ = write!(out, " scale=\"{}\"", values(scale));
+            }
+            let _ = writeln!(out, "/>");
+        }
+        let _ = writeln!(out, "  </asset>");
+    }
+
+    let _ = writeln!(out, "  <worldbody>");
+    out.push_str(&writer.body);
+    let _ = writeln!(out, "  </worldbody>");
+
+    let mimics: Vec<(&urdf_rs::Joint, &urdf_rs::Mimic)> = robot.joints.iter()
+        .filter_map(|joint| Some((joint, joint.mimic.as_ref()?)))
+        .collect();
+    if !mimics.is_empty() {
+        let _ = writeln!(out, "  <equality>");
+        for (joint, mimic) in mimics {
+            let _ = writeln!(
+                out,
+                "    <joint joint1=\"{}\" joint2=\"{}\" polycoef=\"{} {} 0 0 0\"/>",
+                escape(&joint.name),
+                escape(&mimic.joint),
+                mimic.offset.unwrap_or(0.0),
+                mimic.multiplier.unwrap_or(1.0),
+            );
+        }
+        let _ = writeln!(out, "  </equality>");
+    }
+
+    let actuated: Vec<&urdf_rs::Joint> = robot.joints.iter()
+        .filter(|joint| is_movable(joint) && joint.mimic.is_none() && joint.limit.effort > 0.0)
+        .collect();
+    if !actuated.is_empty() {
+        let _ = writeln!(out, "  <actuator>");
+        for joint in actuated {
+            let _ = writeln!(
+                out,
+                "    <motor name=\"{}_motor\" joint=\"{}\" ctrlrange=\"{} {}\"/>",
+                escape(&joint.name),
+                escape(&joint.name),
+                -joint.limit.effort,
+                joint.limit.effort,
+            );
+        }
+        let _ = writeln!(out, "  </actuator>");
+    }
+
+    let _ = writeln!(out, "</mujoco>");
+    out
+}
+
+struct MjcfWriter<'a, F> {
+    robot: &'a urdf_rs::Robot,
+    mesh_path: F,
+    // 名前・ファイル・拡大率
+    meshes: Vec<(String, String, Option<[f64; 3]>)>,
+    children: HashMap<&'a str, Vec<&'a urdf_rs::Joint>>,
+    body: String,
+}
+
+impl<'a, F: Fn(&str) -> PathBuf> MjcfWriter<'a, F> {
+    fn write_body(&mut self, link_name: &str, joint: Option<&urdf_rs::Joint>, fixed_root: bool, depth: usize) {
+        let Some(link) = self.robot.links.iter().find(|link| link.name == link_name) else {
+            return;
+        };
+        let indent = "  ".repeat(depth);
+        let inner = "  ".repeat(depth + 1);
+
+        let origin = joint.map(|joint| pose_to_transform(&joint.origin)).unwrap_or_default();
+        let _ = writeln!(self.body, "{}<body name=\"{}\"{}>", indent, escape(&link.name), frame_attributes(&origin));
+
+        match joint {
+            None if !fixed_root => {
+                let _ = writeln!(self.body, "{}<freejoint name=\"{}_free\"/>", inner, escape(&link.name));
+            }
+            None => {}
+            Some(joint) => self.write_joint(joint, &inner),
+        }
+
+        if link.inertial.mass.value > 0.0 {
+            self.write_inertial(&link.inertial, &inner);
+        }
+
+        for visual in &link.visual {
+            if let Some(geom) = self.geom_attributes(&visual.geometry, &visual.origin) {
+                let rgba = visual_rgba(visual, &self.robot.materials).unwrap_or(DEFAULT_RGBA);
+                let _ = writeln!(
+                    self.body,
+                    "{}<geom{} contype=\"0\" conaffinity=\"0\" group=\"1\" rgba=\"{}\"/>",
+                    inner,
+                    geom,
+                    values(&rgba),
+                );
+            }
+        }
+        for collision in &link.collision {
+            if let Some(geom) = self.geom_attributes(&collision.geometry, &collision.origin) {
+                let _ = writeln!(self.body, "{}<geom{} group=\"{}\"/>", inner, geom, HIDDEN_GROUP);
+            }
+        }
+
+        let children = self.children.get(link.name.as_str()).cloned().unwrap_or_default();
+        for child in children {
+            self.write_body(&child.child.link, Some(child), fixed_root, depth + 1);
+        }
+
+        let _ = writeln!(self.body, "{}</body>", indent);
+    }
+
+    // MuJoCo は fullinertia と quat の併用を受け付けないので、回転した慣性座標系は主軸の diaginertia で書く
+    fn write_inertial(&mut self, inertial: &urdf_rs::Inertial, indent: &str) {
+        let i = &inertial.inertia;
+        let origin = pose_to_transform(&inertial.origin);
+        let rotated = !origin.rotation.abs_diff_eq(Quat::IDENTITY, 1.0e-7);
+        let diagonal = i.ixy == 0.0 && i.ixz == 0.0 && i.iyz == 0.0;
+
+        if rotated && diagonal {
+            let _ = writeln!(
+                self.body,
+                "{}<inertial{} mass=\"{}\" diaginertia=\"{} {} {}\"/>",
+                indent,
+                frame_attributes(&origin),
+                inertial.mass.value,
+                i.ixx, i.iyy, i.izz,
+            );
+            return;
+        }
+
+        // 非対角成分があれば body の座標軸に回してから書く。主軸は MuJoCo 側で求め直される
+        let tensor = DMat3::from_cols_array(&[i.ixx, i.ixy, i.ixz, i.ixy, i.iyy, i.iyz, i.ixz, i.iyz, i.izz]);
+        let rotation = DMat3::from_quat(origin.rotation.as_dquat());
+        let tensor = rotation * tensor * rotation.transpose();
+        let _ = writeln!(
+            self.body,
+            "{}<inertial{} mass=\"{}\" fullinertia=\"{} {} {} {} {} {}\"/>",
+            indent,
+            frame_attributes(&Transform::from_translation(origin.translation)),
+            inertial.mass.value,
+            tensor.x_axis.x, tensor.y_axis.y, tensor.z_axis.z,
+            tensor.y_axis.x, tensor.z_axis.x, tensor.z_axis.y,
+        );
+    }
+
+    fn write_joint(&mut self, joint: &urdf_rs::Joint, indent: &str) {
+        let joint_type = match joint.joint_type {
+            urdf_rs::JointType::Revolute | urdf_rs::JointType::Continuous => "hinge",
+            urdf_rs::JointType::Prismatic => "slide",
+            // freejoint はワールド直下の body にしか置けないので、入れ子では直動 3 軸と球関節で代用する
+            urdf_rs::JointType::Floating => {
+                for (suffix, axis) in [("x", "1 0 0"), ("y", "0 1 0"), ("z", "0 0 1")] {
+                    let _ = writeln!(
+                        self.body,
+                        "{}<joint name=\"{}_{}\" type=\"slide\" axis=\"{}\"/>",
+                        indent,
+                        escape(&joint.name),
+                        suffix,
+                        axis,
+                    );
+                }
+                let _ = writeln!(self.body, "{}<joint name=\"{}\" type=\"ball\"/>", indent, escape(&joint.name));
+                return;
+            }
+            urdf_rs::JointType::Fixed => return,
+            _ => {
+                warn!("Joint {} has a type MJCF cannot express; it is exported as fixed", joint.name);
+                return;
+            }
+        };
+
+        let _ = write!(
+            self.body,
+            "{}<joint name=\"{}\" type=\"{}\" axis=\"{}\"",
+            indent,
+            escape(&joint.name),
+            joint_type,
+            values(&joint.axis.xyz.0),
+        );
+        let limited = !matches!(joint.joint_type, urdf_rs::JointType::Continuous) && joint.limit.lower < joint.limit.upper;
+        if limited {
+            let _ = write!(self.body, " range=\"{} {}\"", joint.limit.lower, joint.limit.upper);
+        }
+        if let Some(dynamics) = &joint.dynamics {
+            let _ = write!(self.body, " damping=\"{}\" frictionloss=\"{}\"", dynamics.damping, dynamics.friction);
+        }
+        let _ = writeln!(self.body, "/>");
+    }
+
+    fn geom_attributes(&mut self, geometry: &urdf_rs::Geometry, origin: &urdf_rs::Pose) -> Option<String> {
+        let shape = match geometry {
+            urdf_rs::Geometry::Box { size } => {
+                format!(" type=\"box\" size=\"{}\"", values(&size.0.map(|v| v / 2.0)))
+            }
+            urdf_rs::Geometry::Cylinder { radius, length } => {
+                format!(" type=\"cylinder\" size=\"{} {}\"", radius, length / 2.0)
+            }
+            urdf_rs::Geometry::Capsule { radius, length } => {
+                format!(" type=\"capsule\" size=\"{} {}\"", radius, length / 2.0)
+            }
+            urdf_rs::Geometry::Sphere { radius } => {
+                format!(" type=\"sphere\" size=\"{}\"", radius)
+            }
+            urdf_rs::Geometry::Mesh { filename, scale } => {
+                let file = (self.mesh_path)(filename).to_string_lossy().replace('\\', "/");
+                let scale = scale.as_ref().map(|scale| scale.0);
+                let name = self.mesh_name(file, scale);
+                format!(" type=\"mesh\" mesh=\"{}\"", escape(&name))
+            }
+        };
+        Some(format!("{}{}", shape, frame_attributes(&pose_to_transform(origin))))
+    }
+
+    // 同じファイル・拡大率のメッシュはアセットを共有する
+    fn mesh_name(&mut self, file: String, scale: Option<[f64; 3]>) -> String {
+        if let Some((name, _, _)) = self.meshes.iter().find(|(_, f, s)| *f == file && *s == scale) {
+            return name.clone();
+        }
+
+        let stem = Path::new(&file).file_stem().unwrap_or_default().to_string_lossy().into_owned();
+        let name = if self.meshes.iter().any(|(name, _, _)| *name == stem) {
+            format!("{}_{}", stem, self.meshes.len())
+        } else {
+            stem
+        };
+        self.meshes.push((name.clone(), file, scale));
+        name
+    }
+}
+
+fn is_movable(joint: &urdf_rs::Joint) -> bool {
+    matches!(
+        joint.joint_type,
+        urdf_rs::JointType::Revolute | urdf_rs::JointType::Continuous | urdf_rs::JointType::Prismatic
+    )
+}
+
+fn visual_rgba(visual: &urdf_rs::Visual, materials: &[urdf_rs::Material]) -> Option<[f64; 4]> {
+    let material = visual.material.as_ref()?;
+    material.color.as_ref()
+        .or_else(|| {
+            materials.iter()
+                .find(|named| named.name == material.name)
+                .and_then(|named| named.color.as_ref())
+        })
+        .map(|color| color.rgba.0)
+}
+
+fn frame_attributes(transform: &Transform) -> String {
+    let mut attributes = String::new();
+    if transform.translation != Vec3::ZERO {
+        let _ = write!(attributes, " pos=\"{}\"", values(&transform.translation.to_array().map(f64::from)));
+    }
+    if !transform.rotation.abs_diff_eq(Quat::IDENTITY, 1.0e-7) {
+        let q = transform.rotation;
+        let _ = write!(attributes, " quat=\"{}\"", values(&[q.w, q.x, q.y, q.z].map(f64::from)));
+    }
+    attributes
+}
+
+fn values(values: &[f64]) -> String {
+    values.iter().map(|v| v.to_string()).collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::FRAC_PI_2;
+
+    fn read_str(name: &str, content: &str) -> MjcfDocument {
+        let path = std::env::temp_dir().join(format!("udon_mjcf_{}_{}.xml", std::process::id(), name));
+        std::fs::write(&path, content).unwrap();
+        let document = read_file(&path);
+        let _ = std::fs::remove_file(&path);
+        document.unwrap()
+    }
+
+    fn joint<'a>(robot: &'a urdf_rs::Robot, name: &str) -> &'a urdf_rs::Joint {
+        robot.joints.iter().find(|joint| joint.name == name).unwrap()
+    }
+
+    fn link<'a>(robot: &'a urdf_rs::Robot, name: &str) -> &'a urdf_rs::Link {
+        robot.links.iter().find(|link| link.name == name).unwrap()
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!((actual - expected).abs() < 1.0e-4, "{} != {}", actual, expected);
+    }
+
+    #[test]
+    fn default_classes_inherit_and_override() {
+        let document = read_str("defaults", r#"
+            <mujoco model="defaults">
+              <default>
+                <joint damping="1" frictionloss="0.5"/>
+                <geom type="box" size="0.1 0.1 0.1"/>
+                <default class="arm">
+                  <joint damping="2"/>
+                </default>
+              </default>
+              <worldbody>
+                <body name="base">
+                  <joint name="plain"/>
+                  <geom/>
+                  <body name="upper" childclass="arm">
+                    <joint name="shoulder"/>
+                    <geom/>
+                  </body>
+                </body>
+              </worldbody>
+            </mujoco>"#);
+        let robot = &document.models[0].robot;
+
+        let plain = joint(robot, "plain").dynamics.as_ref().unwrap();
+        assert_eq!((plain.damping, plain.friction), (1.0, 0.5));
+        let shoulder = joint(robot, "shoulder").dynamics.as_ref().unwrap();
+        assert_eq!((shoulder.damping, shoulder.friction), (2.0, 0.5));
+        assert!(matches!(link(robot, "upper").collision[0].geometry, urdf_rs::Geometry::Box { .. }));
+    }
+
+    #[test]
+    fn body_with_several_joints_is_split() {
+        let document = read_str("split", r#"
+            <mujoco>
+              <worldbody>
+                <body name="wrist">
+                  <joint name="pitch" axis="0 1 0"/>
+                  <joint name="yaw" axis="0 0 1" pos="0 0 0.1"/>
+                  <geom size="0.05"/>
+                </body>
+              </worldbody>
+            </mujoco>"#);
+        let robot = &document.models[0].robot;
+
+        let links: Vec<&str> = robot.links.iter().map(|link| link.name.as_str()).collect();
+        assert_eq!(links, [WORLD_LINK, "wrist_pitch", "wrist"]);
+        let pitch = joint(robot, "pitch");
+        assert_eq!((pitch.parent.link.as_str(), pitch.child.link.as_str()), (WORLD_LINK, "wrist_pitch"));
+        let yaw = joint(robot, "yaw");
+        assert_eq!((yaw.parent.link.as_str(), yaw.child.link.as_str()), ("wrist_pitch", "wrist"));
+        assert_close(yaw.origin.xyz[2], 0.1);
+        // リンク座標系は最後の関節の位置にあるので、geom はその分ずれる
+        assert_close(link(robot, "wrist").collision[0].origin.xyz[2], -0.1);
+    }
+
+    #[test]
+    fn frames_accept_fromto_euler_and_xyaxes() {
+        let document = read_str("frames", r#"
+            <mujoco>
+              <worldbody>
+                <body name="base">
+                  <geom type="capsule" size="0.1" fromto="0 0 0 0 0 1"/>
+                  <body name="euler" euler="0 0 90">
+                    <geom size="0.1"/>
+                  </body>
+                  <body name="xyaxes" xyaxes="0 1 0 -1 0 0">
+                    <geom size="0.1"/>
+                  </body>
+                </body>
+              </worldbody>
+            </mujoco>"#);
+        let robot = &document.models[0].robot;
+
+        let capsule = &link(robot, "base").collision[0];
+        match capsule.geometry {
+            urdf_rs::Geometry::Capsule { length, .. } => assert_close(length, 1.0),
+            _ => panic!("fromto geom is not a capsule"),
+        }
+        assert_close(capsule.origin.xyz[2], 0.5);
+
+        let expected = Quat::from_rotation_z(FRAC_PI_2);
+        for name in ["euler_fixed", "xyaxes_fixed"] {
+            let rotation = pose_to_transform(&joint(robot, name).origin).rotation;
+            assert!(rotation.abs_diff_eq(expected, 1.0e-5), "{}: {:?}", name, rotation);
+        }
+    }
+
+    #[test]
+    fn inertia_is_computed_from_geoms() {
+        let document = read_str("inertia", r#"
+            <mujoco>
+              <worldbody>
+                <body name="box">
+                  <geom type="box" size="0.1 0.2 0.3"/>
+                </body>
+                <body name="dumbbell">
+                  <geom size="0.1" mass="1" pos="1 0 0"/>
+                  <geom size="0.1" mass="1" pos="-1 0 0"/>
+                </body>
+              </worldbody>
+            </mujoco>"#);
+
+        let inertial = &document.models[0].robot.links[0].inertial;
+        let mass = 1000.0 * 0.2 * 0.4 * 0.6;
+        assert_close(inertial.mass.value, mass);
+        assert_close(inertial.inertia.ixx, mass * (0.4 * 0.4 + 0.6 * 0.6) / 12.0);
+        assert_close(inertial.inertia.izz, mass * (0.2 * 0.2 + 0.4 * 0.4) / 12.0);
+
+        // 各球の慣性に平行軸の定理の分が加わる
+        let inertial = &document.models[1].robot.links[0].inertial;
+        assert_close(inertial.mass.value, 2.0);
+        assert_close(inertial.origin.xyz[0], 0.0);
+        assert_close(inertial.inertia.ixx, 2.0 * 0.4 * 0.01);
+        assert_close(inertial.inertia.izz, 2.0 * 0.4 * 0.01 + 2.0);
+    }
+
+    #[test]
+    fn exported_robot_reads_back() {
+        let document = read_str("source", r#"
+            <mujoco model="arm">
+              <compiler angle="radian"/>
+              <worldbody>
+                <body name="base">
+                  <freejoint/>
+                  <geom type="box" size="0.1 0.1 0.1"/>
+                  <body name="link1" pos="0 0 0.2">
+                    <joint name="hinge" axis="0 1 0" range="-1 1" damping="0.5"/>
+                    <geom type="cylinder" size="0.05 0.1"/>
+                    <body name="link2" pos="0 0 0.3">
+                      <joint name="slider" type="slide" axis="0 0 1" range="0 0.1"/>
+                      <inertial pos="0 0 0.05" quat="0.7071068 0 0 0.7071068" mass="1" diaginertia="0.1 0.2 0.3"/>
+                    </body>
+                  </body>
+                </body>
+              </worldbody>
+              <actuator>
+                <motor joint="hinge" ctrlrange="-2 2"/>
+              </actuator>
+            </mujoco>"#);
+        let source = &document.models[0].robot;
+
+        let exported = write_robot(source, false, |path| PathBuf::from(path));
+        assert!(exported.contains("<freejoint"));
+        assert!(exported.contains("diaginertia=\"0.1 0.2 0.3\""));
+        let reread = read_str("exported", &exported);
+        let robot = &reread.models[0].robot;
+
+        assert_eq!(reread.name, "arm");
+        for name in ["base", "link1", "link2"] {
+            link(robot, name);
+        }
+        let hinge = joint(robot, "hinge");
+        assert!(matches!(hinge.joint_type, urdf_rs::JointType::Revolute));
+        assert_eq!(hinge.axis.xyz.0, [0.0, 1.0, 0.0]);
+        assert_eq!((hinge.limit.lower, hinge.limit.upper, hinge.limit.effort), (-1.0, 1.0, 2.0));
+        assert_eq!(hinge.dynamics.as_ref().unwrap().damping, 0.5);
+        assert_close(hinge.origin.xyz[2], 0.2);
+        let slider = joint(robot, "slider");
+        assert!(matches!(slider.joint_type, urdf_rs::JointType::Prismatic));
+        assert_eq!((slider.limit.lower, slider.limit.upper), (0.0, 0.1));
+
+        let inertial = &link(robot, "link2").inertial;
+        assert_close(inertial.inertia.iyy, 0.2);
+        assert!(pose_to_transform(&inertial.origin).rotation.abs_diff_eq(Quat::from_rotation_z(FRAC_PI_2), 1.0e-5));
+    }
+
+    #[test]
+    fn writer_handles_rotated_tensors_and_nested_floating_joints() {
+        let robot = urdf_rs::read_from_string(r#"
+            <robot name="floating">
+              <link name="base">
+                <inertial>
+                  <origin rpy="0 0 1.5707963"/>
+                  <mass value="1"/>
+                  <inertia ixx="1" ixy="0.1" ixz="0" iyy="2" iyz="0" izz="3"/>
+                </inertial>
+              </link>
+              <link name="payload"/>
+              <joint name="loose" type="floating">
+                <parent link="base"/>
+                <child link="payload"/>
+              </joint>
+            </robot>"#).unwrap();
+
+        let exported = write_robot(&robot, true, |path| PathBuf::from(path));
+        // 非対角成分のある回転した慣性は body の座標軸で fullinertia として書く
+        let inertial = exported.lines().find(|line| line.contains("<inertial")).unwrap();
+        assert!(inertial.contains("fullinertia=\"2"));
+        assert!(!inertial.contains("quat="));
+        assert!(!exported.contains("<freejoint"));
+        assert!(exported.contains("<joint name=\"loose\" type=\"ball\"/>"));
+    }
+}
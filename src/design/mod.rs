@@ -1,12 +1,30 @@
+pub mod cache;
+pub mod collider;
+pub mod export;
+pub mod hot_reload;
 pub mod loader;
+pub mod mesh_format;
+pub mod mesh_units;
+pub mod mjcf;
+pub mod model_config;
+pub mod package;
+pub mod registry;
+pub mod sdf;
+pub mod spawn;
+pub mod srdf;
+pub mod transmission;
+pub mod validation;
+pub mod xacro;
+mod xml;
 
 use bevy::prelude::*;
+use export::ExportPlugin;
 use loader::RobotLoaderPlugin;
 
 pub struct DesignPlugin;
 
 impl Plugin for DesignPlugin {
     fn build(&self, app: &mut App) {
-        app.add_plugins(RobotLoaderPlugin);
+        app.add_plugins((RobotLoaderPlugin, ExportPlugin));
     }
 }
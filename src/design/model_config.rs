@@ -0,0 +1,145 @@
+use bevy::prelude::*;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum ColliderMode {
+    Aabb,
+    #[default]
+    ConvexHull,
+    ConvexDecomposition,
+    TriMesh,
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[serde(default)]
+pub struct DecompositionSettings {
+    pub resolution: u32,
+    pub max_convex_hulls: u32,
+}
+
+impl Default for DecompositionSettings {
+    fn default() -> Self {
+        Self {
+            resolution: 64,
+            max_convex_hulls: 32,
+        }
+    }
+}
+
+// CAD から書き出したメッシュの単位。Detect / AutoCorrect は関節間隔や慣性と比べて推定する
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MeshUnits {
+    #[default]
+    Detect,
+    AutoCorrect,
+    Metres,
+    Centimetres,
+    Millimetres,
+    Inches,
+}
+
+impl MeshUnits {
+    // メートルに直すための倍率。推定するモードでは None
+    pub fn scale(self) -> Option<f64> {
+        match self {
+            MeshUnits::Detect | MeshUnits::AutoCorrect => None,
+            MeshUnits::Metres => Some(1.0),
+            MeshUnits::Centimetres => Some(0.01),
+            MeshUnits::Millimetres => Some(0.001),
+            MeshUnits::Inches => Some(0.0254),
+        }
+    }
+}
+
+// 同じロボットのリンク同士の当たり判定。disable / allow はリンク名の組で、allow が優先する
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct SelfCollisionConfig {
+    pub enabled: bool,
+    // 関節でつながったリンク同士も当てるか
+    pub adjacent: bool,
+    pub disable: Vec<(String, String)>,
+    pub allow: Vec<(String, String)>,
+    // disable_collisions を読む SRDF。説明ファイルからの相対パス。省略時は同名の .srdf があれば読む
+    pub srdf: Option<PathBuf>,
+}
+
+impl Default for SelfCollisionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            adjacent: false,
+            disable: Vec::new(),
+            allow: Vec::new(),
+            srdf: None,
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct ModelConfig {
+    pub collider: ColliderMode,
+    pub link_colliders: HashMap<String, ColliderMode>,
+    pub fixed_links: Vec<String>,
+    pub decomposition: DecompositionSettings,
+    pub mesh_units: MeshUnits,
+    // DriveInput を付けるリンク。省略時はルートリンク
+    pub base_link: Option<String>,
+    pub self_collision: SelfCollisionConfig,
+}
+
+impl ModelConfig {
+    // robot.xacro / robot.urdf.xacro に対して robot.udon.ron を読む
+    pub fn path_for(description_path: &Path) -> PathBuf {
+        let file_name = description_path.file_name().unwrap_or_default().to_string_lossy();
+        let base_name = file_name.split('.').next().unwrap_or_default();
+        description_path.with_file_name(format!("{}.udon.ron", base_name))
+    }
+
+    pub fn load_for(description_path: &Path) -> Self {
+        let path = Self::path_for(description_path);
+        if !path.is_file() {
+            return Self::default();
+        }
+
+        let parsed = fs::read_to_string(&path)
+            .map_err(|e| e.to_string())
+            .and_then(|content| ron::from_str::<ModelConfig>(&content).map_err(|e| e.to_string()));
+
+        match parsed {
+            Ok(config) => {
+                info!("Loaded model config {:?}", path);
+                config
+            }
+            Err(e) => {
+                warn!("Ignoring invalid model config {:?}: {}", path, e);
+                Self::default()
+            }
+        }
+    }
+
+    // robot.urdf.xacro に対して robot.srdf (なければ robot.srdf.xacro)
+    pub fn srdf_path(&self, description_path: &Path) -> Option<PathBuf> {
+        if let Some(path) = &self.self_collision.srdf {
+            return Some(description_path.with_file_name(path));
+        }
+
+        let file_name = description_path.file_name().unwrap_or_default().to_string_lossy();
+        let base_name = file_name.split('.').next().unwrap_or_default();
+        ["srdf", "srdf.xacro"].iter()
+            .map(|extension| description_path.with_file_name(format!("{}.{}", base_name, extension)))
+            .find(|path| path.is_file())
+    }
+
+    pub fn collider_mode(&self, link_name: &str) -> ColliderMode {
+        self.link_colliders.get(link_name).copied().unwrap_or(self.collider)
+    }
+
+    pub fn is_fixed(&self, link_name: &str) -> bool {
+        self.fixed_links.iter().any(|name| name == link_name)
+    }
+}
@@ -0,0 +1,88 @@
+use std::collections::HashMap;
+use std::env;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+use super::loader::read_file_to_string_smart;
+use super::xacro::absolute_path;
+use super::xml;
+
+// package.xml を探すときに潜る深さの上限 (巨大なフォルダを登録しても固まらないように)
+const MAX_SCAN_DEPTH: usize = 8;
+
+// ROS と同じく package.xml の <name> でパッケージを引く。
+// fusion2urdf の出力のように package.xml が無いものはフォルダ名で探す
+#[derive(Clone, Debug, Default)]
+pub struct PackageResolver {
+    roots: Vec<PathBuf>,
+    packages: HashMap<String, PathBuf>,
+}
+
+impl PackageResolver {
+    // roots に加えて ROS_PACKAGE_PATH と AMENT_PREFIX_PATH (ROS 2 の share) も探す
+    pub fn scan(roots: &[PathBuf]) -> Self {
+        let mut search_roots = roots.to_vec();
+        if let Some(paths) = env::var_os("ROS_PACKAGE_PATH") {
+            search_roots.extend(env::split_paths(&paths));
+        }
+        if let Some(prefixes) = env::var_os("AMENT_PREFIX_PATH") {
+            search_roots.extend(env::split_paths(&prefixes).map(|prefix| prefix.join("share")));
+        }
+        search_roots.retain(|root| root.is_dir());
+
+        let mut packages = HashMap::new();
+        for root in &search_roots {
+            let manifests = WalkDir::new(root)
+                .max_depth(MAX_SCAN_DEPTH)
+                .into_iter()
+                .filter_map(|e| e.ok())
+                .filter(|entry| entry.file_name() == "package.xml");
+
+            for manifest in manifests {
+                let Some(dir) = manifest.path().parent() else {
+                    continue;
+                };
+                let name = package_name(manifest.path())
+                    .unwrap_or_else(|| dir.file_name().unwrap_or_default().to_string_lossy().into_owned());
+                // 先に登録されたルートを優先する (ROS_PACKAGE_PATH の順序と同じ)
+                packages.entry(name).or_insert_with(|| absolute_path(dir));
+            }
+        }
+
+        Self { roots: search_roots, packages }
+    }
+
+    pub fn find(&self, package: &str, current_file: &Path) -> Option<PathBuf> {
+        // 同じ名前のパッケージが複数あるときは、参照元のファイルを含むものを使う
+        let from_ancestors = absolute_path(current_file)
+            .ancestors()
+            .find(|dir| {
+                let manifest = dir.join("package.xml");
+                if manifest.is_file() {
+                    package_name(&manifest).is_some_and(|name| name == package)
+                } else {
+                    dir.file_name().is_some_and(|name| name == package)
+                }
+            })
+            .map(Path::to_path_buf);
+
+        from_ancestors
+            .or_else(|| self.packages.get(package).cloned())
+            .or_else(|| {
+                self.roots.iter()
+                    .map(|root| absolute_path(&root.join(package)))
+                    .find(|dir| dir.is_dir())
+            })
+    }
+
+    pub fn package_count(&self) -> usize {
+        self.packages.len()
+    }
+}
+
+fn package_name(manifest: &Path) -> Option<String> {
+    let content = read_file_to_string_smart(manifest)?;
+    let root = xml::parse_str(&content).ok()?;
+    let name = root.child_text("name")?.trim();
+    (!name.is_empty()).then(|| name.to_string())
+}
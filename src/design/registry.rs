@@ -0,0 +1,237 @@
+use bevy::prelude::*;
+use chrono::{DateTime, Local};
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+
+use super::loader::{LoadRobotRequest, WORLD_SLOT};
+use super::spawn::{SpawnPose, SpawnPoses};
+
+// 説明ファイル中の 1 モデル分のエンティティ (SDF のワールドなどでは 1 インスタンスに複数ある)
+pub struct RobotModelEntities {
+    pub name: String,
+    pub base: Option<Entity>,
+    pub links: HashMap<String, Entity>,
+    // 可動関節の名前から子リンクのエンティティ (RobotJoint を持つ) を引く
+    pub joints: HashMap<String, Entity>,
+}
+
+pub struct RobotInstance {
+    pub slot: usize,
+    // インスタンスごとに一意な名前。スクリプトなどからはこの名前で指す
+    pub name: String,
+    // 説明ファイルに書かれたロボット (またはワールド) の名前
+    pub description_name: String,
+    pub source: PathBuf,
+    pub loaded_at: DateTime<Local>,
+    pub spawn: SpawnPose,
+    pub models: Vec<RobotModelEntities>,
+    // リンクと照明。visual やコライダーの子エンティティはリンクの子として消える
+    pub parts: Vec<Entity>,
+}
+
+impl RobotInstance {
+    pub fn base(&self) -> Option<Entity> {
+        self.models.iter().find_map(|model| model.base)
+    }
+
+    // コントローラから関節を名前で引く
+    pub fn joint(&self, name: &str) -> Option<Entity> {
+        self.models.iter().find_map(|model| model.joints.get(name).copied())
+    }
+
+    pub fn joints(&self) -> impl Iterator<Item = (&str, Entity)> {
+        self.models.iter()
+            .flat_map(|model| model.joints.iter().map(|(name, &entity)| (name.as_str(), entity)))
+    }
+}
+
+// 読み込み済みのロボットをスロット番号と名前で引けるようにする
+#[derive(Resource)]
+pub struct RobotRegistry {
+    instances: BTreeMap<usize, RobotInstance>,
+    next_slot: usize,
+}
+
+impl Default for RobotRegistry {
+    fn default() -> Self {
+        Self {
+            instances: BTreeMap::new(),
+            next_slot: WORLD_SLOT + 1,
+        }
+    }
+}
+
+impl RobotRegistry {
+    pub fn get(&self, slot: usize) -> Option<&RobotInstance> {
+        self.instances.get(&slot)
+    }
+
+    pub fn find(&self, name: &str) -> Option<&RobotInstance> {
+        self.instances.values().find(|instance| instance.name == name)
+    }
+
+    pub fn contains(&self, slot: usize) -> bool {
+        self.instances.contains_key(&slot)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &RobotInstance> {
+        self.instances.values()
+    }
+
+    // 次に reserve_slot が返す番号
+    pub fn next_slot(&self) -> usize {
+        self.next_slot
+    }
+
+    // 新しいインスタンス用のスロット番号。一度使った番号は使い回さない
+    pub fn reserve_slot(&mut self) -> usize {
+        let slot = self.next_slot;
+        self.next_slot += 1;
+        slot
+    }
+
+    // 同じ名前のインスタンスが他のスロットにあれば _2, _3 ... を付ける
+    pub fn unique_name(&self, name: &str, slot: usize) -> String {
+        let taken = |candidate: &str| self.find(candidate).is_some_and(|instance| instance.slot != slot);
+        if !taken(name) {
+            return name.to_string();
+        }
+        (2..)
+            .map(|n| format!("{}_{}", name, n))
+            .find(|candidate| !taken(candidate))
+            .unwrap_or_default()
+    }
+
+    pub(super) fn insert(&mut self, instance: RobotInstance) {
+        self.next_slot = self.next_slot.max(instance.slot + 1);
+        self.instances.insert(instance.slot, instance);
+    }
+
+    pub(super) fn remove(&mut self, slot: usize) -> Option<RobotInstance> {
+        self.instances.remove(&slot)
+    }
+}
+
+// スロットのロボットを消す (読み込み中ならキャンセルする)
+#[derive(Event)]
+pub struct UnloadRobotRequest {
+    pub slot: usize,
+}
+
+// 同じファイル・名前・スポーン位置で読み込み直す
+#[derive(Event)]
+pub struct ReloadRobotRequest {
+    pub slot: usize,
+}
+
+// 同じファイルを新しいスロットに読み込む。spawn が無ければ新しいスロットの既定位置に置く
+#[derive(Event)]
+pub struct DuplicateRobotRequest {
+    pub slot: usize,
+    pub spawn: Option<SpawnPose>,
+}
+
+pub(super) fn handle_reload_requests(
+    mut reload_events: EventReader<ReloadRobotRequest>,
+    mut duplicate_events: EventReader<DuplicateRobotRequest>,
+    mut registry: ResMut<RobotRegistry>,
+    spawn_poses: Res<SpawnPoses>,
+    mut load_events: EventWriter<LoadRobotRequest>,
+) {
+    for event in reload_events.read() {
+        let Some(instance) = registry.get(event.slot) else {
+            warn!("Cannot reload slot {}: nothing is loaded there", event.slot);
+            continue;
+        };
+        load_events.send(LoadRobotRequest {
+            path: instance.source.clone(),
+            slot: event.slot,
+            spawn: spawn_poses.pose(event.slot),
+            name: Some(instance.name.clone()),
+        });
+    }
+
+    for event in duplicate_events.read() {
+        let Some(instance) = registry.get(event.slot) else {
+            warn!("Cannot duplicate slot {}: nothing is loaded there", event.slot);
+            continue;
+        };
+        let (path, name) = (instance.source.clone(), instance.name.clone());
+
+        let slot = registry.reserve_slot();
+        info!("Duplicating {} from slot {} into slot {}", name, event.slot, slot);
+        load_events.send(LoadRobotRequest {
+            path,
+            slot,
+            spawn: event.spawn.unwrap_or_else(|| spawn_poses.pose(slot)),
+            name: Some(registry.unique_name(&name, slot)),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn instance(slot: usize, name: &str, joints: &[(&str, Entity)]) -> RobotInstance {
+        RobotInstance {
+            slot,
+            name: name.to_string(),
+            description_name: name.to_string(),
+            source: PathBuf::from(format!("{}.urdf", name)),
+            loaded_at: Local::now(),
+            spawn: SpawnPose::for_slot(slot),
+            models: vec![RobotModelEntities {
+                name: name.to_string(),
+                base: None,
+                links: HashMap::new(),
+                joints: joints.iter().map(|(name, entity)| (name.to_string(), *entity)).collect(),
+            }],
+            parts: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn slots_are_never_reused() {
+        let mut registry = RobotRegistry::default();
+        assert_eq!(registry.reserve_slot(), 1);
+        assert_eq!(registry.reserve_slot(), 2);
+
+        // 直接読み込まれたスロットより後ろから払い出す
+        registry.insert(instance(5, "arm", &[]));
+        assert_eq!(registry.next_slot(), 6);
+        registry.remove(5);
+        assert!(!registry.contains(5));
+        assert_eq!(registry.reserve_slot(), 6);
+
+        // ワールドを読み込んでもロボット用の番号は進まない
+        registry.insert(instance(WORLD_SLOT, "yard", &[]));
+        assert_eq!(registry.next_slot(), 7);
+    }
+
+    #[test]
+    fn names_are_unique_across_slots() {
+        let mut registry = RobotRegistry::default();
+        registry.insert(instance(1, "arm", &[]));
+        registry.insert(instance(2, "arm_2", &[]));
+
+        assert_eq!(registry.unique_name("arm", 1), "arm");
+        assert_eq!(registry.unique_name("arm", 3), "arm_3");
+        assert_eq!(registry.unique_name("rover", 3), "rover");
+    }
+
+    #[test]
+    fn instances_and_joints_are_found_by_name() {
+        let shoulder = Entity::from_raw(10);
+        let mut registry = RobotRegistry::default();
+        registry.insert(instance(1, "arm", &[("shoulder", shoulder)]));
+        registry.insert(instance(2, "rover", &[]));
+
+        let arm = registry.find("arm").unwrap();
+        assert_eq!(arm.slot, 1);
+        assert_eq!(arm.joint("shoulder"), Some(shoulder));
+        assert_eq!(arm.joint("elbow"), None);
+        assert_eq!(registry.find("rover").map(|instance| instance.slot), Some(2));
+        assert!(registry.find("missing").is_none());
+    }
+}
@@ -0,0 +1,849 @@
+use bevy::prelude::*;
+use std::collections::HashMap;
+use std::env;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use super::loader::{pose_to_transform, transform_to_pose, ImportedModel};
+use super::xacro::absolute_path;
+use super::xml::{self, parse_bool, parse_values, vec3, XmlElement};
+
+const MAX_INCLUDE_DEPTH: usize = 16;
+// SDF の可動域の既定値 (±1e16) は実質無制限を表す
+const UNLIMITED: f64 = 1.0e15;
+
+#[derive(Debug, Clone)]
+pub struct SdfError {
+    pub file: PathBuf,
+    pub message: String,
+}
+
+impl SdfError {
+    fn new(file: &Path, message: impl Into<String>) -> Self {
+        Self {
+            file: file.to_path_buf(),
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for SdfError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.file.display(), self.message)
+    }
+}
+
+impl std::error::Error for SdfError {}
+
+pub struct SdfDocument {
+    pub name: String,
+    pub is_world: bool,
+    pub models: Vec<ImportedModel>,
+    pub lights: Vec<SdfLight>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SdfLightKind {
+    Directional,
+    Point,
+    Spot { outer_angle: f32 },
+}
+
+#[derive(Clone, Debug)]
+pub struct SdfLight {
+    pub kind: SdfLightKind,
+    pub pose: Transform,
+    pub color: Color,
+    pub range: f32,
+    pub direction: Vec3,
+    pub cast_shadows: bool,
+}
+
+pub fn is_sdf(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "sdf" || ext == "world")
+}
+
+pub fn read_file(path: &Path, search_roots: &[PathBuf]) -> Result<SdfDocument, SdfError> {
+    let mut model_roots = search_roots.to_vec();
+    if let Some(extra) = env::var_os("GAZEBO_MODEL_PATH") {
+        model_roots.extend(env::split_paths(&extra));
+    }
+
+    let mut importer = SdfImporter { model_roots, depth: 0 };
+    let root = parse_document(path)?;
+    if root.name != "sdf" {
+        return Err(SdfError::new(path, format!("root element is <{}>, expected <sdf>", root.name)));
+    }
+
+    let mut document = SdfDocument {
+        name: path.file_stem().unwrap_or_default().to_string_lossy().into_owned(),
+        is_world: false,
+        models: Vec::new(),
+        lights: Vec::new(),
+    };
+
+    let scope = match root.child("world") {
+        Some(world) => {
+            document.is_world = true;
+            if let Some(name) = world.attribute("name") {
+                document.name = name.to_string();
+            }
+            world
+        }
+        None => &root,
+    };
+
+    for element in &scope.children {
+        match element.name.as_str() {
+            "model" => document.models.extend(importer.model(element, path, element_pose(element))?),
+            "include" => document.models.extend(importer.include(element, path, Transform::IDENTITY)?),
+            "light" => document.lights.push(light(element)),
+            _ => {}
+        }
+    }
+
+    if document.models.is_empty() && !document.is_world {
+        return Err(SdfError::new(path, "no <model> element found"));
+    }
+
+    if let [model] = document.models.as_slice() {
+        if !document.is_world {
+            document.name = model.robot.name.clone();
+        }
+    }
+
+    Ok(document)
+}
+
+struct SdfImporter {
+    model_roots: Vec<PathBuf>,
+    depth: usize,
+}
+
+struct SdfJoint {
+    name: String,
+    joint_type: urdf_rs::JointType,
+    parent: String,
+    child: String,
+    // 子リンク座標系での関節の位置・姿勢
+    pose: Transform,
+    axis: Vec3,
+    axis_in_model_frame: bool,
+    limit: urdf_rs::JointLimit,
+    dynamics: Option<urdf_rs::Dynamics>,
+}
+
+impl SdfImporter {
+    fn model(&mut self, element: &XmlElement, file: &Path, pose: Transform) -> Result<Vec<ImportedModel>, SdfError> {
+        let name = element.attribute("name").unwrap_or("model").to_string();
+        let is_static = element.child_text("static").is_some_and(parse_bool);
+        let mut warnings = Vec::new();
+
+        let links: Vec<(urdf_rs::Link, Transform)> = element.children("link")
+            .map(|link| self.link(link, file, &mut warnings))
+            .collect();
+        let joints: Vec<SdfJoint> = element.children("joint")
+            .map(|joint| sdf_joint(joint, &mut warnings))
+            .collect();
+
+        // 入れ子のモデルは独立したモデルとして並べる
+        let mut models = Vec::new();
+        for nested in element.children("model") {
+            models.extend(self.model(nested, file, pose * element_pose(nested))?);
+        }
+        for include in element.children("include") {
+            models.extend(self.include(include, file, pose)?);
+        }
+
+        if !links.is_empty() {
+            let converted = convert_model(name, pose, is_static, links, joints, warnings);
+            models.splice(0..0, converted);
+        }
+        Ok(models)
+    }
+
+    fn include(&mut self, element: &XmlElement, file: &Path, parent_pose: Transform) -> Result<Vec<ImportedModel>, SdfError> {
+        let uri = element.child_text("uri")
+            .ok_or_else(|| SdfError::new(file, "<include> without <uri>"))?;
+        let model_file = self.find_model_file(uri, file)
+            .ok_or_else(|| SdfError::new(file, format!("included model '{}' not found", uri)))?;
+
+        if self.depth >= MAX_INCLUDE_DEPTH {
+            return Err(SdfError::new(file, format!("includes nested too deeply at '{}'", uri)));
+        }
+
+        let root = parse_document(&model_file)?;
+        let Some(model) = root.child("model") else {
+            return Err(SdfError::new(&model_file, "no <model> element found"));
+        };
+
+        let pose = match element.child("pose") {
+            Some(_) => parent_pose * element_pose(element),
+            None => parent_pose * element_pose(model),
+        };
+
+        self.depth += 1;
+        let result = self.model(model, &model_file, pose);
+        self.depth -= 1;
+        let mut models = result?;
+
+        if let Some(first) = models.first_mut() {
+            if let Some(name) = element.child_text("name") {
+                first.robot.name = name.to_string();
+            }
+        }
+        // <include> の static は入れ子のモデルや分けた木も含めたモデル全体に効く
+        if element.child_text("static").is_some_and(parse_bool) {
+            for model in &mut models {
+                model.fixed_links = model.robot.links.iter().map(|link| link.name.clone()).collect();
+            }
+        }
+        Ok(models)
+    }
+
+    // model://name は Gazebo と同じく name という名前のモデルディレクトリを探す
+    fn find_model_dir(&self, name: &str, current_file: &Path) -> Option<PathBuf> {
+        current_file.parent()
+            .into_iter()
+            .flat_map(Path::ancestors)
+            .flat_map(|dir| [dir.join(name), dir.to_path_buf()])
+            .find(|dir| dir.file_name().is_some_and(|n| n == name) && dir.is_dir())
+            .or_else(|| {
+                self.model_roots.iter()
+                    .map(|root| root.join(name))
+                    .find(|dir| dir.is_dir())
+            })
+    }
+
+    fn find_model_file(&self, uri: &str, current_file: &Path) -> Option<PathBuf> {
+        let path = match uri.strip_prefix("model://").or_else(|| uri.strip_prefix("package://")) {
+            Some(rest) => {
+                let (name, relative) = rest.split_once('/').unwrap_or((rest, ""));
+                self.find_model_dir(name, current_file)?.join(relative)
+            }
+            None => {
+                let path = uri.strip_prefix("file://").unwrap_or(uri);
+                current_file.parent().unwrap_or(Path::new(".")).join(path)
+            }
+        };
+
+        if path.is_file() {
+            return Some(path);
+        }
+
+        let sdf_name = parse_document(&path.join("model.config")).ok()
+            .and_then(|config| config.child_text("sdf").map(str::to_string))
+            .unwrap_or_else(|| "model.sdf".to_string());
+        Some(path.join(sdf_name)).filter(|file| file.is_file())
+    }
+
+    // メッシュの URI は、それを書いたモデルのファイルを基準にした絶対パスに直しておく。
+    // 読み込み側は記述ファイル (ワールド) を基準にし、GAZEBO_MODEL_PATH も見ないため
+    fn mesh_path(&self, uri: &str, file: &Path) -> String {
+        let resolved = match uri.strip_prefix("model://") {
+            Some(rest) => {
+                let (name, relative) = rest.split_once('/').unwrap_or((rest, ""));
+                self.find_model_dir(name, file).map(|dir| dir.join(relative))
+            }
+            // package:// と file:// は読み込み側で解決する
+            None if uri.contains("://") => None,
+            None => Some(file.parent().unwrap_or(Path::new(".")).join(uri)),
+        };
+
+        match resolved {
+            Some(path) => absolute_path(&path).to_string_lossy().replace('\\', "/"),
+            // 見つからないモデルは package:// として残し、検証で欠落として報告させる
+            None => mesh_filename(uri),
+        }
+    }
+
+    fn link(&self, element: &XmlElement, file: &Path, warnings: &mut Vec<String>) -> (urdf_rs::Link, Transform) {
+        let name = element.attribute("name").unwrap_or_default().to_string();
+        let geometry = |element: &XmlElement, warnings: &mut Vec<String>| {
+            let mut geometry = geometry(element, &name, warnings)?;
+            if let urdf_rs::Geometry::Mesh { filename, .. } = &mut geometry {
+                *filename = self.mesh_path(filename, file);
+            }
+            Some(geometry)
+        };
+
+        let visual = element.children("visual")
+            .filter_map(|visual| {
+                let geometry = geometry(visual.child("geometry")?, warnings)?;
+                Some(urdf_rs::Visual {
+                    name: visual.attribute("name").map(str::to_string),
+                    origin: transform_to_pose(&element_pose(visual)),
+                    geometry,
+                    material: visual.child("material").and_then(material),
+                })
+            })
+            .collect();
+
+        let collision = element.children("collision")
+            .filter_map(|collision| {
+                let geometry = geometry(collision.child("geometry")?, warnings)?;
+                Some(urdf_rs::Collision {
+                    name: collision.attribute("name").map(str::to_string),
+                    origin: transform_to_pose(&element_pose(collision)),
+                    geometry,
+                })
+            })
+            .collect();
+
+        let link = urdf_rs::Link {
+            name,
+            inertial: element.child("inertial").map(inertial).unwrap_or_else(default_inertial),
+            visual,
+            collision,
+        };
+        (link, element_pose(element))
+    }
+}
+
+fn sdf_joint(element: &XmlElement, warnings: &mut Vec<String>) -> SdfJoint {
+    let name = element.attribute("name").unwrap_or_default().to_string();
+    let axis_element = element.child("axis");
+    let limit_element = axis_element.and_then(|axis| axis.child("limit"));
+
+    let lower = limit_element.and_then(|limit| limit.child_f64("lower")).unwrap_or(-1.0e16);
+    let upper = limit_element.and_then(|limit| limit.child_f64("upper")).unwrap_or(1.0e16);
+    let limited = lower > -UNLIMITED && upper < UNLIMITED;
+
+    let joint_type = match element.attribute("type").unwrap_or_default() {
+        "revolute" if limited => urdf_rs::JointType::Revolute,
+        "revolute" | "continuous" => urdf_rs::JointType::Continuous,
+        "prismatic" => urdf_rs::JointType::Prismatic,
+        "fixed" => urdf_rs::JointType::Fixed,
+        other => {
+            warnings.push(format!("joint '{}' has unsupported type '{}'; it is fixed instead", name, other));
+            urdf_rs::JointType::Fixed
+        }
+    };
+
+    // effort / velocity の負値は SDF では「制限なし」
+    let limit = urdf_rs::JointLimit {
+        lower: if limited { lower } else { 0.0 },
+        upper: if limited { upper } else { 0.0 },
+        effort: limit_element.and_then(|limit| limit.child_f64("effort")).unwrap_or(-1.0).max(0.0),
+        velocity: limit_element.and_then(|limit| limit.child_f64("velocity")).unwrap_or(-1.0).max(0.0),
+    };
+
+    let dynamics = axis_element.and_then(|axis| axis.child("dynamics")).map(|dynamics| urdf_rs::Dynamics {
+        damping: dynamics.child_f64("damping").unwrap_or(0.0),
+        friction: dynamics.child_f64("friction").unwrap_or(0.0),
+    });
+
+    let axis_xyz = axis_element.and_then(|axis| axis.child("xyz"));
+    let axis_in_model_frame = axis_element
+        .and_then(|axis| axis.child_text("use_parent_model_frame"))
+        .is_some_and(parse_bool)
+        || axis_xyz.and_then(|xyz| xyz.attribute("expressed_in")).is_some_and(|frame| frame == "__model__");
+
+    SdfJoint {
+        name,
+        joint_type,
+        parent: element.child_text("parent").unwrap_or_default().to_string(),
+        child: element.child_text("child").unwrap_or_default().to_string(),
+        pose: element_pose(element),
+        axis: axis_xyz.and_then(|xyz| vec3(&xyz.text)).unwrap_or(Vec3::Z),
+        axis_in_model_frame,
+        limit,
+        dynamics,
+    }
+}
+
+// SDF ではリンクの位置がモデル座標系、関節の位置が子リンク座標系で書かれる。
+// URDF では子リンクの座標系が関節の座標系と一致するので、リンクの中身をその座標系に移す。
+// リンク・関節は URDF と同じ形に変換し、URDF と同じ生成処理に渡す。
+// 関節でつながっていないリンクが複数あるときは、static なら固定関節で最初のリンクにつなぎ、
+// そうでなければ木ごとに別のモデルに分ける
+fn convert_model(
+    name: String,
+    pose: Transform,
+    is_static: bool,
+    links: Vec<(urdf_rs::Link, Transform)>,
+    joints: Vec<SdfJoint>,
+    mut warnings: Vec<String>,
+) -> Vec<ImportedModel> {
+    let link_poses: HashMap<String, Transform> = links.iter()
+        .map(|(link, pose)| (link.name.clone(), *pose))
+        .collect();
+
+    let mut fixed_links = Vec::new();
+    let mut joint_frames: HashMap<String, Transform> = HashMap::new();
+    let mut model_joints = Vec::new();
+
+    for joint in &joints {
+        if joint.parent == "world" {
+            fixed_links.push(joint.child.clone());
+            continue;
+        }
+        let Some(child_pose) = link_poses.get(&joint.child) else {
+            warnings.push(format!("joint '{}' references unknown child link '{}'", joint.name, joint.child));
+            continue;
+        };
+        joint_frames.insert(joint.child.clone(), *child_pose * joint.pose);
+        model_joints.push(joint);
+    }
+
+    let link_frame = |link: &str| -> Transform {
+        joint_frames.get(link).or_else(|| link_poses.get(link)).copied().unwrap_or_default()
+    };
+
+    let mut urdf_joints: Vec<urdf_rs::Joint> = model_joints.into_iter()
+        .map(|joint| {
+            let frame = link_frame(&joint.child);
+            let axis = if joint.axis_in_model_frame {
+                frame.rotation.inverse() * joint.axis
+            } else {
+                joint.axis
+            };
+            let origin = link_frame(&joint.parent).compute_affine().inverse() * frame.compute_affine();
+
+            urdf_rs::Joint {
+                name: joint.name.clone(),
+                joint_type: joint.joint_type.clone(),
+                origin: transform_to_pose(&Transform::from_matrix(origin.into())),
+                parent: urdf_rs::LinkName { link: joint.parent.clone() },
+                child: urdf_rs::LinkName { link: joint.child.clone() },
+                axis: urdf_rs::Axis { xyz: urdf_rs::Vec3(axis.to_array().map(f64::from)) },
+                limit: joint.limit.clone(),
+                dynamics: joint.dynamics.clone(),
+                mimic: None,
+                safety_controller: None,
+            }
+        })
+        .collect();
+
+    let urdf_links: Vec<urdf_rs::Link> = links.into_iter()
+        .map(|(mut link, link_pose)| {
+            let offset = link_frame(&link.name).compute_affine().inverse() * link_pose.compute_affine();
+            let offset = Transform::from_matrix(offset.into());
+            let rebase = |origin: &urdf_rs::Pose| transform_to_pose(&(offset * pose_to_transform(origin)));
+
+            link.inertial.origin = rebase(&link.inertial.origin);
+            for visual in &mut link.visual {
+                visual.origin = rebase(&visual.origin);
+            }
+            for collision in &mut link.collision {
+                collision.origin = rebase(&collision.origin);
+            }
+            link
+        })
+        .collect();
+
+    let mut roots: Vec<String> = urdf_links.iter()
+        .filter(|link| !urdf_joints.iter().any(|joint| joint.child.link == link.name))
+        .map(|link| link.name.clone())
+        .collect();
+
+    if is_static {
+        fixed_links = urdf_links.iter().map(|link| link.name.clone()).collect();
+        if let [first, rest @ ..] = roots.as_slice() {
+            let first_frame = link_frame(first).compute_affine().inverse();
+            for root in rest {
+                let origin = first_frame * link_frame(root).compute_affine();
+                urdf_joints.push(fixed_joint(format!("{}_to_{}", first, root), first, root, &Transform::from_matrix(origin.into())));
+            }
+        }
+        roots.truncate(1);
+    }
+
+    // ルートが見つからない (関節が輪になっている) ときは分けずに検証に任せる
+    if roots.len() <= 1 {
+        let root_frame = roots.first().map(|root| link_frame(root)).unwrap_or_default();
+        return vec![ImportedModel {
+            robot: urdf_rs::Robot {
+                name,
+                links: urdf_links,
+                joints: urdf_joints,
+                materials: Vec::new(),
+            },
+            pose: pose * root_frame,
+            fixed_links,
+            warnings,
+            transmissions: Vec::new(),
+        }];
+    }
+
+    warnings.push(format!("links are not connected by joints; split into {} models", roots.len()));
+    let (mut links, mut joints) = (urdf_links, urdf_joints);
+    roots.iter().enumerate().map(|(index, root)| {
+        let mut members = vec![root.clone()];
+        let mut next = 0;
+        while let Some(parent) = members.get(next).cloned() {
+            members.extend(joints.iter().filter(|joint| joint.parent.link == parent).map(|joint| joint.child.link.clone()));
+            next += 1;
+        }
+
+        let (tree_links, rest): (Vec<_>, Vec<_>) = links.drain(..).partition(|link| members.contains(&link.name));
+        links = rest;
+        let (tree_joints, rest): (Vec<_>, Vec<_>) = joints.drain(..).partition(|joint| members.contains(&joint.child.link));
+        joints = rest;
+
+        ImportedModel {
+            robot: urdf_rs::Robot {
+                name: if index == 0 { name.clone() } else { format!("{}_{}", name, root) },
+                links: tree_links,
+                joints: tree_joints,
+                materials: Vec::new(),
+            },
+            pose: pose * link_frame(root),
+            fixed_links: fixed_links.iter().filter(|link| members.contains(link)).cloned().collect(),
+            warnings: if index == 0 { std::mem::take(&mut warnings) } else { Vec::new() },
+            transmissions: Vec::new(),
+        }
+    }).collect()
+}
+
+fn fixed_joint(name: String, parent: &str, child: &str, origin: &Transform) -> urdf_rs::Joint {
+    urdf_rs::Joint {
+        name,
+        joint_type: urdf_rs::JointType::Fixed,
+        origin: transform_to_pose(origin),
+        parent: urdf_rs::LinkName { link: parent.to_string() },
+        child: urdf_rs::LinkName { link: child.to_string() },
+        axis: urdf_rs::Axis { xyz: urdf_rs::Vec3([1.0, 0.0, 0.0]) },
+        limit: urdf_rs::JointLimit { lower: 0.0, upper: 0.0, effort: 0.0, velocity: 0.0 },
+        dynamics: None,
+        mimic: None,
+        safety_controller: None,
+    }
+}
+
+fn geometry(element: &XmlElement, link: &str, warnings: &mut Vec<String>) -> Option<urdf_rs::Geometry> {
+    let shape = element.children.first()?;
+    let geometry = match shape.name.as_str() {
+        "box" => urdf_rs::Geometry::Box {
+            size: urdf_rs::Vec3(vec3(shape.child_text("size")?)?.to_array().map(f64::from)),
+        },
+        "cylinder" => urdf_rs::Geometry::Cylinder {
+            radius: shape.child_f64("radius")?,
+            length: shape.child_f64("length")?,
+        },
+        "capsule" => urdf_rs::Geometry::Capsule {
+            radius: shape.child_f64("radius")?,
+            length: shape.child_f64("length")?,
+        },
+        "sphere" => urdf_rs::Geometry::Sphere {
+            radius: shape.child_f64("radius")?,
+        },
+        "mesh" => urdf_rs::Geometry::Mesh {
+            filename: shape.child_text("uri")?.to_string(),
+            scale: shape.child_text("scale").and_then(vec3).map(|scale| urdf_rs::Vec3(scale.to_array().map(f64::from))),
+        },
+        // 地面は physics::world 側で用意している
+        "plane" => return None,
+        other => {
+            warnings.push(format!("link '{}' uses unsupported geometry <{}>", link, other));
+            return None;
+        }
+    };
+    Some(geometry)
+}
+
+// model:// はモデル名のディレクトリを指すので、package:// と同じ探し方で解決を試せる
+fn mesh_filename(uri: &str) -> String {
+    match uri.strip_prefix("model://") {
+        Some(rest) => format!("package://{}", rest),
+        None => uri.to_string(),
+    }
+}
+
+fn material(element: &XmlElement) -> Option<urdf_rs::Material> {
+    let rgba = element.child_text("diffuse")
+        .or_else(|| element.child_text("ambient"))
+        .and_then(parse_values)?;
+    let rgba = match rgba.as_slice() {
+        [r, g, b] => [*r, *g, *b, 1.0],
+        [r, g, b, a, ..] => [*r, *g, *b, *a],
+        _ => return None,
+    };
+
+    Some(urdf_rs::Material {
+        name: String::new(),
+        color: Some(urdf_rs::Color { rgba: urdf_rs::Vec4(rgba) }),
+        texture: None,
+    })
+}
+
+fn inertial(element: &XmlElement) -> urdf_rs::Inertial {
+    let inertia = element.child("inertia");
+    let value = |key: &str, default: f64| inertia.and_then(|i| i.child_f64(key)).unwrap_or(default);
+
+    urdf_rs::Inertial {
+        origin: transform_to_pose(&element_pose(element)),
+        mass: urdf_rs::Mass { value: element.child_f64("mass").unwrap_or(1.0) },
+        inertia: urdf_rs::Inertia {
+            ixx: value("ixx", 1.0),
+            ixy: value("ixy", 0.0),
+            ixz: value("ixz", 0.0),
+            iyy: value("iyy", 1.0),
+            iyz: value("iyz", 0.0),
+            izz: value("izz", 1.0),
+        },
+    }
+}
+
+// SDF の既定値: 1 kg、単位慣性
+fn default_inertial() -> urdf_rs::Inertial {
+    urdf_rs::Inertial {
+        origin: transform_to_pose(&Transform::IDENTITY),
+        mass: urdf_rs::Mass { value: 1.0 },
+        inertia: urdf_rs::Inertia { ixx: 1.0, ixy: 0.0, ixz: 0.0, iyy: 1.0, iyz: 0.0, izz: 1.0 },
+    }
+}
+
+fn light(element: &XmlElement) -> SdfLight {
+    let kind = match element.attribute("type").unwrap_or("point") {
+        "directional" => SdfLightKind::Directional,
+        "spot" => SdfLightKind::Spot {
+            outer_angle: element.child("spot").and_then(|spot| spot.child_f64("outer_angle")).unwrap_or(1.0) as f32,
+        },
+        _ => SdfLightKind::Point,
+    };
+
+    let color = match element.child_text("diffuse").and_then(parse_values).as_deref() {
+        Some([r, g, b, ..]) => Color::rgb(*r as f32, *g as f32, *b as f32),
+        _ => Color::WHITE,
+    };
+
+    SdfLight {
+        kind,
+        pose: element_pose(element),
+        color,
+        range: element.child("attenuation").and_then(|a| a.child_f64("range")).unwrap_or(10.0) as f32,
+        direction: element.child_text("direction").and_then(vec3).unwrap_or(Vec3::NEG_Z),
+        cast_shadows: element.child_text("cast_shadows").is_some_and(parse_bool),
+    }
+}
+
+fn element_pose(element: &XmlElement) -> Transform {
+    let Some(pose) = element.child("pose") else {
+        return Transform::IDENTITY;
+    };
+
+    match parse_values(&pose.text).as_deref() {
+        Some([x, y, z, qx, qy, qz, qw]) => Transform {
+            translation: Vec3::new(*x as f32, *y as f32, *z as f32),
+            rotation: Quat::from_xyzw(*qx as f32, *qy as f32, *qz as f32, *qw as f32).normalize(),
+            ..default()
+        },
+        Some([x, y, z, roll, pitch, yaw]) => pose_to_transform(&urdf_rs::Pose {
+            xyz: urdf_rs::Vec3([*x, *y, *z]),
+            rpy: urdf_rs::Vec3([*roll, *pitch, *yaw]),
+        }),
+        _ => Transform::IDENTITY,
+    }
+}
+
+fn parse_document(path: &Path) -> Result<XmlElement, SdfError> {
+    xml::parse_document(path).map_err(|message| SdfError::new(path, message))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    // パスは一時ディレクトリからの相対で、途中のディレクトリも作る
+    fn write_files(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("udon_sdf_{}_{}", std::process::id(), name));
+        for (file, content) in files {
+            let path = dir.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+        dir
+    }
+
+    fn read_str(name: &str, content: &str) -> SdfDocument {
+        let dir = write_files(name, &[("model.sdf", content)]);
+        let document = read_file(&dir.join("model.sdf"), &[]);
+        let _ = fs::remove_dir_all(&dir);
+        document.unwrap()
+    }
+
+    fn joint<'a>(robot: &'a urdf_rs::Robot, name: &str) -> &'a urdf_rs::Joint {
+        robot.joints.iter().find(|joint| joint.name == name).unwrap()
+    }
+
+    fn visual_mesh(link: &urdf_rs::Link) -> &str {
+        match &link.visual[0].geometry {
+            urdf_rs::Geometry::Mesh { filename, .. } => filename,
+            other => panic!("expected a mesh, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn single_model_becomes_one_robot() {
+        let document = read_str("single", r#"
+            <sdf version="1.6">
+              <model name="arm">
+                <pose>1 0 0 0 0 0</pose>
+                <link name="base">
+                  <collision name="c"><geometry><box><size>0.2 0.2 0.1</size></box></geometry></collision>
+                </link>
+                <link name="upper">
+                  <pose>0 0 0.5 0 0 0</pose>
+                  <inertial><mass>2</mass></inertial>
+                </link>
+                <joint name="shoulder" type="revolute">
+                  <parent>base</parent>
+                  <child>upper</child>
+                  <axis>
+                    <xyz>0 1 0</xyz>
+                    <limit><lower>-1</lower><upper>1</upper><effort>10</effort></limit>
+                  </axis>
+                </joint>
+              </model>
+            </sdf>"#);
+
+        assert!(!document.is_world);
+        assert_eq!(document.name, "arm");
+        let [model] = document.models.as_slice() else {
+            panic!("expected one model, got {}", document.models.len());
+        };
+        assert!(model.warnings.is_empty(), "{:?}", model.warnings);
+        assert!(model.pose.translation.abs_diff_eq(Vec3::X, 1e-5));
+
+        let shoulder = joint(&model.robot, "shoulder");
+        assert!(matches!(shoulder.joint_type, urdf_rs::JointType::Revolute));
+        assert_eq!(shoulder.origin.xyz.0, [0.0, 0.0, 0.5]);
+        assert_eq!(shoulder.axis.xyz.0, [0.0, 1.0, 0.0]);
+        assert_eq!((shoulder.limit.lower, shoulder.limit.upper, shoulder.limit.effort), (-1.0, 1.0, 10.0));
+        let upper = model.robot.links.iter().find(|link| link.name == "upper").unwrap();
+        assert_eq!(upper.inertial.mass.value, 2.0);
+    }
+
+    #[test]
+    fn static_model_joins_its_roots_with_fixed_joints() {
+        let document = read_str("static", r#"
+            <sdf version="1.6">
+              <model name="shelf">
+                <static>true</static>
+                <link name="frame"/>
+                <link name="board"><pose>0 0 1 0 0 0</pose></link>
+              </model>
+            </sdf>"#);
+
+        let [model] = document.models.as_slice() else {
+            panic!("expected one model, got {}", document.models.len());
+        };
+        assert_eq!(model.fixed_links, ["frame", "board"]);
+        let joined = joint(&model.robot, "frame_to_board");
+        assert!(matches!(joined.joint_type, urdf_rs::JointType::Fixed));
+        assert_eq!(joined.origin.xyz.0, [0.0, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn unjointed_roots_of_a_dynamic_model_are_split() {
+        let document = read_str("split", r#"
+            <sdf version="1.6">
+              <model name="pair">
+                <link name="left"/>
+                <link name="right"><pose>2 0 0 0 0 0</pose></link>
+              </model>
+            </sdf>"#);
+
+        let names: Vec<&str> = document.models.iter().map(|model| model.robot.name.as_str()).collect();
+        assert_eq!(names, ["pair", "pair_right"]);
+        assert!(document.models.iter().all(|model| model.robot.links.len() == 1 && model.fixed_links.is_empty()));
+        assert!(document.models[1].pose.translation.abs_diff_eq(Vec3::new(2.0, 0.0, 0.0), 1e-5));
+        assert!(document.models[0].warnings.iter().any(|warning| warning.contains("split into 2 models")));
+    }
+
+    #[test]
+    fn world_reads_includes_obstacles_and_lights() {
+        let dir = write_files("world", &[
+            ("empty.world", r#"
+                <sdf version="1.6">
+                  <world name="yard">
+                    <light name="sun" type="directional"><direction>0 0 -1</direction></light>
+                    <model name="ground_plane">
+                      <static>true</static>
+                      <link name="plane"><collision name="c"><geometry><plane/></geometry></collision></link>
+                    </model>
+                    <model name="crate">
+                      <static>true</static>
+                      <pose>3 0 0.5 0 0 0</pose>
+                      <link name="box"><collision name="c"><geometry><box><size>1 1 1</size></box></geometry></collision></link>
+                    </model>
+                    <include>
+                      <uri>model://cone</uri>
+                      <name>cone_1</name>
+                      <pose>0 2 0 0 0 0</pose>
+                      <static>true</static>
+                    </include>
+                  </world>
+                </sdf>"#),
+            ("cone/model.config", "<model><sdf>cone.sdf</sdf></model>"),
+            ("cone/cone.sdf", r#"
+                <sdf version="1.6">
+                  <model name="cone"><link name="body"/></model>
+                </sdf>"#),
+        ]);
+        let document = read_file(&dir.join("empty.world"), &[]);
+        let _ = fs::remove_dir_all(&dir);
+        let document = document.unwrap();
+
+        assert!(document.is_world);
+        assert_eq!(document.name, "yard");
+        assert_eq!(document.lights.len(), 1);
+        assert_eq!(document.lights[0].kind, SdfLightKind::Directional);
+
+        let names: Vec<&str> = document.models.iter().map(|model| model.robot.name.as_str()).collect();
+        assert_eq!(names, ["ground_plane", "crate", "cone_1"]);
+        // 平面は physics::world の地面に任せる
+        assert!(document.models[0].robot.links[0].collision.is_empty());
+        assert!(document.models[1].pose.translation.abs_diff_eq(Vec3::new(3.0, 0.0, 0.5), 1e-5));
+        let cone = &document.models[2];
+        assert!(cone.pose.translation.abs_diff_eq(Vec3::new(0.0, 2.0, 0.0), 1e-5));
+        assert_eq!(cone.fixed_links, ["body"]);
+    }
+
+    #[test]
+    fn mesh_uris_resolve_against_the_declaring_model() {
+        let dir = write_files("meshes", &[
+            ("scene.world", r#"
+                <sdf version="1.6">
+                  <world name="scene">
+                    <include><uri>model://robot</uri></include>
+                  </world>
+                </sdf>"#),
+            ("models/robot/model.sdf", r#"
+                <sdf version="1.6">
+                  <model name="robot">
+                    <link name="body">
+                      <visual name="v"><geometry><mesh><uri>model://robot/meshes/body.dae</uri></mesh></geometry></visual>
+                    </link>
+                    <link name="wheel">
+                      <visual name="v"><geometry><mesh><uri>meshes/wheel.stl</uri><scale>2 2 2</scale></mesh></geometry></visual>
+                    </link>
+                    <link name="missing">
+                      <visual name="v"><geometry><mesh><uri>model://absent/mesh.obj</uri></mesh></geometry></visual>
+                    </link>
+                    <joint name="axle" type="continuous"><parent>body</parent><child>wheel</child></joint>
+                    <joint name="mount" type="fixed"><parent>body</parent><child>missing</child></joint>
+                  </model>
+                </sdf>"#),
+        ]);
+        // model:// はワールドの隣ではなく、モデルの検索パスから見つかる
+        let document = read_file(&dir.join("scene.world"), &[dir.join("models")]);
+        let model_dir = absolute_path(&dir.join("models/robot"));
+        let _ = fs::remove_dir_all(&dir);
+        let document = document.unwrap();
+
+        let robot = &document.models[0].robot;
+        let link = |name: &str| robot.links.iter().find(|link| link.name == name).unwrap();
+        let expected = |relative: &str| model_dir.join(relative).to_string_lossy().replace('\\', "/");
+        assert_eq!(visual_mesh(link("body")), expected("meshes/body.dae"));
+        assert_eq!(visual_mesh(link("wheel")), expected("meshes/wheel.stl"));
+        assert!(matches!(
+            &link("wheel").visual[0].geometry,
+            urdf_rs::Geometry::Mesh { scale: Some(urdf_rs::Vec3([2.0, 2.0, 2.0])), .. }
+        ));
+        // 見つからないモデルは検証で欠落として報告できる形で残す
+        assert_eq!(visual_mesh(link("missing")), "package://absent/mesh.obj");
+    }
+}
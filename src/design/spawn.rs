@@ -0,0 +1,221 @@
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+use bevy_rapier3d::rapier::math::Isometry;
+use std::collections::HashMap;
+
+use super::loader::{PendingCollider, RobotPart, RobotRoot};
+use super::registry::RobotRegistry;
+
+// 地面 (physics::world の平面) の高さ
+const GROUND_HEIGHT: f32 = 0.0;
+// 接地させるときに浮かせておく隙間
+const DROP_CLEARANCE: f32 = 0.005;
+
+// ロボットを置く位置。heading は上 (Y 軸) まわりの向き [rad]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SpawnPose {
+    pub position: Vec3,
+    pub heading: f32,
+    // コライダーができたあと、いちばん低い点が地面に触れる高さまで下ろす
+    pub drop_to_ground: bool,
+}
+
+impl SpawnPose {
+    // スロットごとに X 軸方向へ 2m 間隔で、地面から 2m 上に置く
+    pub fn for_slot(slot: usize) -> Self {
+        Self {
+            position: Vec3::new((slot as f32 - 1.0) * 2.0, 2.0, 0.0),
+            heading: 0.0,
+            drop_to_ground: false,
+        }
+    }
+
+    pub fn transform(&self) -> Transform {
+        Transform::from_translation(self.position).with_rotation(Quat::from_rotation_y(self.heading))
+    }
+
+    // シーン内で動かしたベースリンクの今の姿勢から、同じ置き方になるスポーン位置を逆算する
+    pub fn moved_with(&self, spawned: &Transform, current: &Transform) -> Self {
+        let origin = current.compute_affine() * spawned.compute_affine().inverse() * self.transform().compute_affine();
+        let (_, rotation, translation) = origin.to_scale_rotation_translation();
+        let (heading, _, _) = rotation.to_euler(EulerRot::YXZ);
+        Self {
+            position: translation,
+            heading,
+            drop_to_ground: self.drop_to_ground,
+        }
+    }
+}
+
+// UI で編集するスロットごとのスポーン位置
+#[derive(Resource, Default)]
+pub struct SpawnPoses {
+    pub poses: HashMap<usize, SpawnPose>,
+}
+
+impl SpawnPoses {
+    pub fn pose(&self, slot: usize) -> SpawnPose {
+        self.poses.get(&slot).copied().unwrap_or_else(|| SpawnPose::for_slot(slot))
+    }
+}
+
+// 生成 (と接地) 直後のリンクの姿勢。リセットではスポーン位置の変更分だけ動かして戻す
+#[derive(Component)]
+pub struct SpawnTransform(pub Transform);
+
+#[derive(Component)]
+pub(super) struct DropToGround;
+
+#[derive(Event)]
+pub struct ResetRobotRequest {
+    pub slot: usize,
+}
+
+type SpawnLinkState = (
+    &'static RobotRoot,
+    &'static mut Transform,
+    &'static mut SpawnTransform,
+    Option<&'static mut Velocity>,
+    Option<&'static Collider>,
+    Option<&'static Children>,
+);
+
+pub(super) fn drop_robots_to_ground(
+    mut commands: Commands,
+    bases: Query<Entity, With<DropToGround>>,
+    pending: Query<&RobotRoot, With<PendingCollider>>,
+    mut links: Query<SpawnLinkState>,
+    child_colliders: Query<(&Collider, &Transform), Without<RobotRoot>>,
+) {
+    for base in bases.iter() {
+        if pending.iter().any(|root| root.base == base) {
+            continue;
+        }
+        commands.entity(base).remove::<DropToGround>();
+
+        // 三角形メッシュのコライダーはリンクの子エンティティに付いている
+        let child_colliders = &child_colliders;
+        let lowest = links.iter()
+            .filter(|(root, ..)| root.base == base)
+            .flat_map(|(_, transform, _, _, collider, children)| {
+                let children = children.into_iter()
+                    .flat_map(|children| children.iter())
+                    .filter_map(move |child| child_colliders.get(*child).ok())
+                    .map(move |(collider, local)| (collider, transform.mul_transform(*local)));
+                collider.map(|collider| (collider, *transform)).into_iter().chain(children)
+            })
+            .map(|(collider, transform)| collider.raw.compute_aabb(&Isometry::from_parts(transform.translation.into(), transform.rotation.into())).mins.y)
+            .reduce(f32::min);
+
+        let Some(lowest) = lowest else {
+            warn!("Robot base {:?} has no colliders; leaving it at its spawn height", base);
+            continue;
+        };
+
+        let shift = Vec3::Y * (GROUND_HEIGHT + DROP_CLEARANCE - lowest);
+        for (root, mut transform, mut spawn_transform, velocity, ..) in links.iter_mut() {
+            if root.base != base {
+                continue;
+            }
+            transform.translation += shift;
+            spawn_transform.0.translation += shift;
+            if let Some(mut velocity) = velocity {
+                *velocity = Velocity::zero();
+            }
+        }
+    }
+}
+
+pub(super) fn reset_robots(
+    mut commands: Commands,
+    mut reset_events: EventReader<ResetRobotRequest>,
+    registry: Res<RobotRegistry>,
+    spawn_poses: Res<SpawnPoses>,
+    mut parts: Query<(&RobotPart, &SpawnTransform, &mut Transform, Option<&mut Velocity>)>,
+) {
+    for event in reset_events.read() {
+        let Some(instance) = registry.get(event.slot) else {
+            warn!("Cannot reset slot {}: nothing is loaded there", event.slot);
+            continue;
+        };
+        let pose = spawn_poses.pose(event.slot);
+        info!("Resetting {} to its spawn pose {:?}", instance.name, pose);
+
+        // 読み込んだときのスポーン位置から今のスポーン位置への移動。ベースからのずれはそのまま保つ
+        let moved = pose.transform().compute_affine() * instance.spawn.transform().compute_affine().inverse();
+        for (part, spawn_transform, mut transform, velocity) in parts.iter_mut() {
+            if part.slot != event.slot {
+                continue;
+            }
+            *transform = Transform::from_matrix((moved * spawn_transform.0.compute_affine()).into());
+            if let Some(mut velocity) = velocity {
+                *velocity = Velocity::zero();
+            }
+        }
+
+        // 高さを変えていれば接地し直す
+        if pose.drop_to_ground {
+            for base in instance.models.iter().filter_map(|model| model.base) {
+                commands.entity(base).insert(DropToGround);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::design::registry::{RobotInstance, RobotModelEntities};
+
+    #[test]
+    fn reset_moves_robots_to_the_edited_spawn_pose() {
+        let mut app = App::new();
+        app.add_event::<ResetRobotRequest>()
+            .init_resource::<RobotRegistry>()
+            .init_resource::<SpawnPoses>()
+            .add_systems(Update, reset_robots);
+
+        let loaded = SpawnPose::for_slot(1);
+        let base_spawn = loaded.transform();
+        let arm_spawn = base_spawn * Transform::from_xyz(0.5, 0.2, 0.0);
+        let mut spawn_link = |transform: Transform| {
+            app.world.spawn((RobotPart { slot: 1 }, SpawnTransform(transform), Transform::from_xyz(9.0, 9.0, 9.0))).id()
+        };
+        let base = spawn_link(base_spawn);
+        let arm = spawn_link(arm_spawn);
+
+        app.world.resource_mut::<RobotRegistry>().insert(RobotInstance {
+            slot: 1,
+            name: "robot".to_string(),
+            description_name: "robot".to_string(),
+            source: "robot.urdf".into(),
+            loaded_at: chrono::Local::now(),
+            spawn: loaded,
+            models: vec![RobotModelEntities {
+                name: "robot".to_string(),
+                base: Some(base),
+                links: HashMap::new(),
+                joints: HashMap::new(),
+            }],
+            parts: vec![base, arm],
+        });
+        let edited = SpawnPose {
+            position: Vec3::new(3.0, 1.0, -2.0),
+            heading: std::f32::consts::FRAC_PI_2,
+            drop_to_ground: false,
+        };
+        app.world.resource_mut::<SpawnPoses>().poses.insert(1, edited);
+
+        app.world.send_event(ResetRobotRequest { slot: 1 });
+        app.update();
+
+        let base_transform = *app.world.get::<Transform>(base).unwrap();
+        let arm_transform = *app.world.get::<Transform>(arm).unwrap();
+        assert!(base_transform.translation.abs_diff_eq(edited.position, 1e-5), "{:?}", base_transform);
+        assert!(base_transform.rotation.abs_diff_eq(Quat::from_rotation_y(edited.heading), 1e-5));
+        // 腕はベースから見て同じ位置に付いたまま
+        let expected_arm = edited.transform() * Transform::from_xyz(0.5, 0.2, 0.0);
+        assert!(arm_transform.translation.abs_diff_eq(expected_arm.translation, 1e-5), "{:?}", arm_transform);
+        assert!(arm_transform.rotation.abs_diff_eq(expected_arm.rotation, 1e-5));
+    }
+}
@@ -0,0 +1,21 @@
+use std::path::Path;
+
+use super::xml;
+
+// robot.srdf / robot.srdf.xacro。link を含むのでロボットの説明ファイルと間違えないようにする
+pub fn is_srdf(path: &Path) -> bool {
+    path.file_name().is_some_and(|name| name.to_string_lossy().contains(".srdf"))
+}
+
+// SRDF のうち <disable_collisions link1="..." link2="..."/> だけを読む。グループやポーズは使わない
+pub fn parse_disabled_collisions(content: &str) -> Result<Vec<(String, String)>, String> {
+    let root = xml::parse_str(content)?;
+    let pairs = root.children("disable_collisions")
+        .filter_map(|element| {
+            let link1 = element.attribute("link1")?;
+            let link2 = element.attribute("link2")?;
+            Some((link1.to_string(), link2.to_string()))
+        })
+        .collect();
+    Ok(pairs)
+}
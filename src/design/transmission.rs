@@ -0,0 +1,110 @@
+use std::path::{Path, PathBuf};
+
+use crate::robot::actuator::ActuatorInterface;
+use super::xml::{self, XmlElement};
+
+// <transmission> 1 つ分。差動などの複数関節の伝達機構は扱わない
+#[derive(Clone, Debug)]
+pub struct Transmission {
+    pub name: String,
+    pub joint: String,
+    pub actuator: String,
+    pub interface: ActuatorInterface,
+    pub reduction: f64,
+}
+
+// ROS 1 (joint/actuator の子要素) と ROS 2 (joint 側の mechanicalReduction) のどちらの書き方も読む
+pub fn parse_transmissions(content: &str) -> Result<(Vec<Transmission>, Vec<String>), String> {
+    let root = xml::parse_str(content)?;
+    let mut transmissions = Vec::new();
+    let mut warnings = Vec::new();
+
+    for element in root.children("transmission") {
+        let name = element.attribute("name").unwrap_or_default().to_string();
+        let joints: Vec<&XmlElement> = element.children("joint").collect();
+        let actuators: Vec<&XmlElement> = element.children("actuator").collect();
+
+        let ([joint], [actuator]) = (joints.as_slice(), actuators.as_slice()) else {
+            warnings.push(format!(
+                "transmission '{}' has {} joint(s) and {} actuator(s); only simple transmissions are supported",
+                name,
+                joints.len(),
+                actuators.len(),
+            ));
+            continue;
+        };
+
+        let Some(joint_name) = joint.attribute("name") else {
+            warnings.push(format!("transmission '{}' has a joint without a name", name));
+            continue;
+        };
+
+        let reduction = actuator.child_f64("mechanicalReduction")
+            .or_else(|| joint.child_f64("mechanicalReduction"))
+            .unwrap_or(1.0);
+        if reduction == 0.0 {
+            warnings.push(format!("transmission '{}' has a mechanical reduction of 0", name));
+            continue;
+        }
+
+        let interface = joint.children("hardwareInterface")
+            .chain(actuator.children("hardwareInterface"))
+            .find_map(|interface| ActuatorInterface::from_hardware_interface(&interface.text))
+            .unwrap_or(ActuatorInterface::Effort);
+
+        transmissions.push(Transmission {
+            name,
+            joint: joint_name.to_string(),
+            actuator: actuator.attribute("name").map(str::to_string).unwrap_or_else(|| format!("{}_actuator", joint_name)),
+            interface,
+            reduction,
+        });
+    }
+
+    Ok((transmissions, warnings))
+}
+
+// fusion2urdf はメインの xacro とは別に <名前>.trans を書き出す。
+// 同じフォルダでも名前の違う *.trans は別のモデルのものなので読まない
+pub fn sibling_transmission_files(description_path: &Path) -> Vec<PathBuf> {
+    let Some(dir) = description_path.parent() else {
+        return Vec::new();
+    };
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let stem = base_name(description_path);
+
+    let mut files: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "trans") && base_name(path) == stem)
+        .collect();
+    files.sort();
+    files
+}
+
+// robot.urdf.xacro と robot.trans を同じ名前とみなすため、最初の '.' より前を使う
+fn base_name(path: &Path) -> Option<&str> {
+    path.file_name()?.to_str()?.split('.').next()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn sibling_transmission_files_share_the_description_name() {
+        let dir = std::env::temp_dir().join(format!("udon_transmission_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for name in ["rover.xacro", "rover.trans", "arm.trans", "notes.txt"] {
+            fs::write(dir.join(name), "").unwrap();
+        }
+
+        assert_eq!(sibling_transmission_files(&dir.join("rover.xacro")), [dir.join("rover.trans")]);
+        assert_eq!(sibling_transmission_files(&dir.join("rover.urdf.xacro")), [dir.join("rover.trans")]);
+        assert!(sibling_transmission_files(&dir.join("gripper.xacro")).is_empty());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,168 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use super::mesh_format;
+use super::transmission::Transmission;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IssueSeverity {
+    Warning,
+    Error,
+}
+
+#[derive(Clone, Debug)]
+pub struct ValidationIssue {
+    pub severity: IssueSeverity,
+    pub message: String,
+}
+
+impl fmt::Display for ValidationIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.severity {
+            IssueSeverity::Warning => write!(f, "warning: {}", self.message),
+            IssueSeverity::Error => write!(f, "error: {}", self.message),
+        }
+    }
+}
+
+struct Validator {
+    issues: Vec<ValidationIssue>,
+}
+
+impl Validator {
+    fn warning(&mut self, message: String) {
+        self.issues.push(ValidationIssue { severity: IssueSeverity::Warning, message });
+    }
+
+    fn error(&mut self, message: String) {
+        self.issues.push(ValidationIssue { severity: IssueSeverity::Error, message });
+    }
+}
+
+pub fn has_errors(issues: &[ValidationIssue]) -> bool {
+    issues.iter().any(|issue| issue.severity == IssueSeverity::Error)
+}
+
+// mesh_exists には URDF に書かれたファイル名をそのまま渡す
+pub fn validate_robot(robot: &urdf_rs::Robot, mesh_exists: impl Fn(&str) -> bool) -> Vec<ValidationIssue> {
+    let mut validator = Validator { issues: Vec::new() };
+
+    let mut link_names = HashSet::new();
+    for link in &robot.links {
+        if !link_names.insert(link.name.as_str()) {
+            validator.error(format!("duplicate link name '{}'", link.name));
+        }
+    }
+
+    let mut joint_names = HashSet::new();
+    for joint in &robot.joints {
+        if !joint_names.insert(joint.name.as_str()) {
+            validator.error(format!("duplicate joint name '{}'", joint.name));
+        }
+    }
+
+    let mut parent_of: HashMap<&str, &str> = HashMap::new();
+    let mut children_of: HashMap<&str, Vec<&str>> = HashMap::new();
+    for joint in &robot.joints {
+        let parent = joint.parent.link.as_str();
+        let child = joint.child.link.as_str();
+
+        if !link_names.contains(parent) {
+            validator.error(format!("joint '{}' references unknown parent link '{}'", joint.name, parent));
+        }
+        if !link_names.contains(child) {
+            validator.error(format!("joint '{}' references unknown child link '{}'", joint.name, child));
+        }
+        if let Some(previous) = parent_of.insert(child, parent) {
+            validator.error(format!("link '{}' has more than one parent ('{}' and '{}')", child, previous, parent));
+        }
+        if let Some(mimic) = &joint.mimic {
+            if !robot.joints.iter().any(|other| other.name == mimic.joint) {
+                validator.error(format!("joint '{}' mimics unknown joint '{}'", joint.name, mimic.joint));
+            }
+        }
+        children_of.entry(parent).or_default().push(child);
+    }
+
+    let roots: Vec<&str> = robot.links.iter()
+        .map(|link| link.name.as_str())
+        .filter(|name| !parent_of.contains_key(name))
+        .collect();
+
+    match roots.as_slice() {
+        [] => validator.error("no root link found; the joints form a cycle".to_string()),
+        [_] => {}
+        [root, others @ ..] => validator.error(format!(
+            "multiple root links: '{}' and {}",
+            root,
+            others.iter().map(|name| format!("'{}'", name)).collect::<Vec<_>>().join(", "),
+        )),
+    }
+
+    if let Some(root) = roots.first() {
+        let mut reachable = HashSet::from([*root]);
+        let mut stack = vec![*root];
+        while let Some(link) = stack.pop() {
+            for &child in children_of.get(link).into_iter().flatten() {
+                if reachable.insert(child) {
+                    stack.push(child);
+                }
+            }
+        }
+
+        for link in &robot.links {
+            if !reachable.contains(link.name.as_str()) && !roots.contains(&link.name.as_str()) {
+                validator.warning(format!("link '{}' is not connected to root link '{}'", link.name, root));
+            }
+        }
+    }
+
+    for link in &robot.links {
+        if link.inertial.mass.value <= 0.0 {
+            validator.warning(format!("link '{}' has zero or negative mass ({})", link.name, link.inertial.mass.value));
+        }
+
+        let geometries = link.visual.iter().map(|v| &v.geometry)
+            .chain(link.collision.iter().map(|c| &c.geometry));
+        for geometry in geometries {
+            if let urdf_rs::Geometry::Mesh { filename, .. } = geometry {
+                if !mesh_exists(filename) {
+                    validator.warning(format!("link '{}' references missing mesh '{}'", link.name, filename));
+                }
+                if !mesh_format::is_supported_mesh(filename) {
+                    validator.warning(format!("link '{}' references mesh '{}' in an unsupported format", link.name, filename));
+                }
+            }
+        }
+    }
+
+    validator.issues
+}
+
+pub fn validate_transmissions(robot: &urdf_rs::Robot, transmissions: &[Transmission]) -> Vec<ValidationIssue> {
+    let mut validator = Validator { issues: Vec::new() };
+
+    let mut actuator_names = HashSet::new();
+    for transmission in transmissions {
+        if !actuator_names.insert(transmission.actuator.as_str()) {
+            validator.warning(format!("actuator '{}' is driven by more than one transmission", transmission.actuator));
+        }
+
+        match robot.joints.iter().find(|joint| joint.name == transmission.joint) {
+            None => validator.warning(format!(
+                "transmission '{}' references unknown joint '{}'",
+                transmission.name, transmission.joint,
+            )),
+            Some(joint) if !matches!(
+                joint.joint_type,
+                urdf_rs::JointType::Revolute | urdf_rs::JointType::Continuous | urdf_rs::JointType::Prismatic
+            ) => validator.warning(format!(
+                "transmission '{}' drives joint '{}', which is not revolute or prismatic",
+                transmission.name, transmission.joint,
+            )),
+            Some(_) => {}
+        }
+    }
+
+    validator.issues
+}
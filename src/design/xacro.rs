@@ -0,0 +1,1357 @@
+use std::collections::HashMap;
+use std::env;
+use std::f64::consts::{E, PI};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+
+use super::loader::read_file_to_string_smart;
+use super::package::PackageResolver;
+
+const XACRO_PREFIX: &str = "xacro:";
+const MAX_INCLUDE_DEPTH: usize = 64;
+
+#[derive(Debug, Clone)]
+pub struct XacroError {
+    pub file: PathBuf,
+    pub line: usize,
+    pub message: String,
+}
+
+impl XacroError {
+    fn new(file: &Path, line: usize, message: impl Into<String>) -> Self {
+        Self {
+            file: file.to_path_buf(),
+            line,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for XacroError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.file.display(), self.line, self.message)
+    }
+}
+
+impl std::error::Error for XacroError {}
+
+// 展開結果を左右した $(find) と $(env)/$(optenv) の解決結果。キャッシュの検証に使う
+#[derive(Clone, Debug, PartialEq)]
+pub enum Substitution {
+    Package { name: String, file: PathBuf, path: Option<PathBuf> },
+    Env { name: String, value: Option<String> },
+}
+
+impl Substitution {
+    // 今解決し直しても同じ結果になるか
+    pub fn is_current(&self, packages: &PackageResolver) -> bool {
+        match self {
+            Substitution::Package { name, file, path } => packages.find(name, file) == *path,
+            Substitution::Env { name, value } => env::var(name).ok() == *value,
+        }
+    }
+}
+
+pub struct Expansion {
+    pub urdf: String,
+    // 展開に使ったファイル (自身と include したもの)
+    pub inputs: Vec<PathBuf>,
+    pub substitutions: Vec<Substitution>,
+}
+
+// $(arg) の値は展開するファイルの中の既定値からしか決まらないので、入力ファイルに含まれる
+pub fn expand_file_with_inputs(path: &Path, packages: &PackageResolver) -> Result<Expansion, XacroError> {
+    let mut processor = XacroProcessor::new(packages.clone());
+    let root = parse_document(path)?;
+
+    let mut output = Vec::new();
+    processor.include_stack.push(absolute_path(path));
+    processor.inputs.push(absolute_path(path));
+    processor.expand_element(&root, &mut output)?;
+
+    let robot = output.into_iter()
+        .find_map(|node| match node {
+            Node::Element(element) => Some(element),
+            _ => None,
+        })
+        .ok_or_else(|| root.error("document has no root element after expansion"))?;
+
+    Ok(Expansion {
+        urdf: serialize_document(&robot),
+        inputs: processor.inputs,
+        substitutions: processor.substitutions,
+    })
+}
+
+#[derive(Clone)]
+enum Node {
+    Element(Element),
+    Text(String),
+    Attribute(String, String),
+}
+
+#[derive(Clone)]
+struct Element {
+    name: String,
+    attributes: Vec<(String, String)>,
+    children: Vec<Node>,
+    file: Rc<PathBuf>,
+    line: usize,
+}
+
+impl Element {
+    fn attribute(&self, key: &str) -> Option<&str> {
+        self.attributes.iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    fn required_attribute(&self, key: &str) -> Result<&str, XacroError> {
+        self.attribute(key)
+            .ok_or_else(|| self.error(format!("<{}> is missing the '{}' attribute", self.name, key)))
+    }
+
+    fn child_elements(&self) -> impl Iterator<Item = &Element> {
+        self.children.iter().filter_map(|node| match node {
+            Node::Element(element) => Some(element),
+            _ => None,
+        })
+    }
+
+    fn error(&self, message: impl Into<String>) -> XacroError {
+        XacroError::new(&self.file, self.line, message)
+    }
+}
+
+#[derive(Clone)]
+enum Value {
+    Number(f64),
+    Text(String),
+    Bool(bool),
+    Block(Rc<Vec<Node>>),
+}
+
+impl Value {
+    // xacro は文字列として定義されたプロパティも数値・真偽値として解釈する
+    fn literal(self) -> Value {
+        match self {
+            Value::Text(text) => {
+                let trimmed = text.trim();
+                if let Ok(number) = trimmed.parse::<f64>() {
+                    Value::Number(number)
+                } else if trimmed == "true" || trimmed == "True" {
+                    Value::Bool(true)
+                } else if trimmed == "false" || trimmed == "False" {
+                    Value::Bool(false)
+                } else {
+                    Value::Text(text)
+                }
+            }
+            other => other,
+        }
+    }
+
+    fn as_number(&self) -> Option<f64> {
+        match self {
+            Value::Number(number) => Some(*number),
+            Value::Bool(flag) => Some(if *flag { 1.0 } else { 0.0 }),
+            Value::Text(text) => text.trim().parse().ok(),
+            Value::Block(_) => None,
+        }
+    }
+
+    fn to_text(&self) -> String {
+        match self {
+            Value::Number(number) => format!("{}", number),
+            Value::Text(text) => text.clone(),
+            Value::Bool(flag) => if *flag { "True".to_string() } else { "False".to_string() },
+            Value::Block(_) => String::new(),
+        }
+    }
+
+    fn truthy(&self) -> bool {
+        match self {
+            Value::Number(number) => *number != 0.0,
+            Value::Text(text) => !text.is_empty(),
+            Value::Bool(flag) => *flag,
+            Value::Block(nodes) => !nodes.is_empty(),
+        }
+    }
+
+    fn is_text(&self) -> bool {
+        matches!(self, Value::Text(_))
+    }
+}
+
+enum Property {
+    Lazy(String),
+    Resolved(Value),
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum ParamKind {
+    Value,
+    Block,
+    MultiBlock,
+}
+
+struct MacroParam {
+    name: String,
+    kind: ParamKind,
+    default: Option<String>,
+    inherit: bool,
+}
+
+struct Macro {
+    params: Vec<MacroParam>,
+    body: Vec<Node>,
+}
+
+#[derive(Default)]
+struct Frame {
+    properties: HashMap<String, Property>,
+    macros: HashMap<String, Rc<Macro>>,
+}
+
+struct XacroProcessor {
+    args: HashMap<String, String>,
+    frames: Vec<Frame>,
+    resolving: Vec<String>,
+    include_stack: Vec<PathBuf>,
+    inputs: Vec<PathBuf>,
+    substitutions: Vec<Substitution>,
+    packages: PackageResolver,
+}
+
+impl XacroProcessor {
+    fn new(packages: PackageResolver) -> Self {
+        Self {
+            args: HashMap::new(),
+            frames: vec![Frame::default()],
+            resolving: Vec::new(),
+            include_stack: Vec::new(),
+            inputs: Vec::new(),
+            substitutions: Vec::new(),
+            packages,
+        }
+    }
+
+    fn expand_nodes(&mut self, nodes: &[Node], context: &Element, out: &mut Vec<Node>) -> Result<(), XacroError> {
+        for node in nodes {
+            match node {
+                Node::Element(element) => self.expand_element(element, out)?,
+                Node::Text(text) => {
+                    let value = self.eval_text(text, context)?;
+                    out.push(Node::Text(value.to_text()));
+                }
+                Node::Attribute(key, value) => out.push(Node::Attribute(key.clone(), value.clone())),
+            }
+        }
+        Ok(())
+    }
+
+    fn expand_element(&mut self, element: &Element, out: &mut Vec<Node>) -> Result<(), XacroError> {
+        let directive = match element.name.strip_prefix(XACRO_PREFIX) {
+            Some(directive) => directive,
+            None => return self.expand_regular(element, &element.name, out),
+        };
+
+        match directive {
+            "property" => self.define_property(element),
+            "arg" => self.define_arg(element),
+            "macro" => self.define_macro(element),
+            "include" => self.include(element, out),
+            "if" | "unless" => {
+                let condition = self.eval_condition(element)?;
+                if condition == (directive == "if") {
+                    self.expand_nodes(&element.children, element, out)?;
+                }
+                Ok(())
+            }
+            "insert_block" => {
+                let name = element.required_attribute("name")?;
+                match self.lookup(name, self.frames.len(), element)? {
+                    Value::Block(nodes) => self.expand_nodes(&nodes, element, out),
+                    _ => Err(element.error(format!("'{}' is not a block", name))),
+                }
+            }
+            "call" => {
+                let name = self.eval_text(element.required_attribute("macro")?, element)?.to_text();
+                let mut call = element.clone();
+                call.attributes.retain(|(key, _)| key != "macro");
+                self.call_macro(&name, &call, out)
+            }
+            "element" => {
+                let name = self.eval_text(element.required_attribute("xacro:name")?, element)?.to_text();
+                let mut renamed = element.clone();
+                renamed.attributes.retain(|(key, _)| key != "xacro:name");
+                self.expand_regular(&renamed, &name, out)
+            }
+            "attribute" => {
+                let name = self.eval_text(element.required_attribute("name")?, element)?.to_text();
+                let value = self.eval_text(element.required_attribute("value")?, element)?.to_text();
+                out.push(Node::Attribute(name, value));
+                Ok(())
+            }
+            name => self.call_macro(name, element, out),
+        }
+    }
+
+    fn expand_regular(&mut self, element: &Element, name: &str, out: &mut Vec<Node>) -> Result<(), XacroError> {
+        let mut attributes = Vec::with_capacity(element.attributes.len());
+        for (key, value) in &element.attributes {
+            if key == "xmlns:xacro" {
+                continue;
+            }
+            attributes.push((key.clone(), self.eval_text(value, element)?.to_text()));
+        }
+
+        let mut expanded = Vec::new();
+        self.expand_nodes(&element.children, element, &mut expanded)?;
+
+        let mut children = Vec::with_capacity(expanded.len());
+        for child in expanded {
+            match child {
+                Node::Attribute(key, value) => {
+                    match attributes.iter_mut().find(|(k, _)| *k == key) {
+                        Some(existing) => existing.1 = value,
+                        None => attributes.push((key, value)),
+                    }
+                }
+                Node::Text(text) if text.trim().is_empty() => {}
+                other => children.push(other),
+            }
+        }
+
+        out.push(Node::Element(Element {
+            name: name.to_string(),
+            attributes,
+            children,
+            file: element.file.clone(),
+            line: element.line,
+        }));
+        Ok(())
+    }
+
+    fn define_property(&mut self, element: &Element) -> Result<(), XacroError> {
+        let name = element.required_attribute("name")?.to_string();
+
+        let target = match element.attribute("scope") {
+            None | Some("local") => self.frames.len() - 1,
+            Some("parent") => self.frames.len().saturating_sub(2),
+            Some("global") => 0,
+            Some(other) => return Err(element.error(format!("unknown property scope '{}'", other))),
+        };
+
+        let property = if let Some(value) = element.attribute("value") {
+            if target == self.frames.len() - 1 {
+                Property::Lazy(value.to_string())
+            } else {
+                Property::Resolved(self.eval_text(value, element)?.literal())
+            }
+        } else if let Some(default) = element.attribute("default") {
+            if self.is_defined(&name) {
+                return Ok(());
+            }
+            Property::Resolved(self.eval_text(default, element)?.literal())
+        } else {
+            Property::Resolved(Value::Block(Rc::new(element.children.clone())))
+        };
+
+        self.frames[target].properties.insert(name, property);
+        Ok(())
+    }
+
+    fn define_arg(&mut self, element: &Element) -> Result<(), XacroError> {
+        let name = element.required_attribute("name")?.to_string();
+        if self.args.contains_key(&name) {
+            return Ok(());
+        }
+        if let Some(default) = element.attribute("default") {
+            let value = self.eval_text(default, element)?.to_text();
+            self.args.insert(name, value);
+        }
+        Ok(())
+    }
+
+    fn define_macro(&mut self, element: &Element) -> Result<(), XacroError> {
+        let name = element.required_attribute("name")?;
+        let name = name.strip_prefix(XACRO_PREFIX).unwrap_or(name).to_string();
+        let params = parse_macro_params(element.attribute("params").unwrap_or(""));
+
+        let definition = Macro {
+            params,
+            body: element.children.clone(),
+        };
+
+        if let Some(frame) = self.frames.last_mut() {
+            frame.macros.insert(name, Rc::new(definition));
+        }
+        Ok(())
+    }
+
+    fn include(&mut self, element: &Element, out: &mut Vec<Node>) -> Result<(), XacroError> {
+        let filename = self.eval_text(element.required_attribute("filename")?, element)?.to_text();
+        let requested = Path::new(&filename);
+
+        let path = if requested.is_absolute() {
+            requested.to_path_buf()
+        } else {
+            element.file.parent().unwrap_or(Path::new(".")).join(requested)
+        };
+        let path = absolute_path(&path);
+
+        if !path.is_file() {
+            return Err(element.error(format!("included file not found: {}", path.display())));
+        }
+        if self.include_stack.contains(&path) || self.include_stack.len() >= MAX_INCLUDE_DEPTH {
+            return Err(element.error(format!("recursive include of {}", path.display())));
+        }
+
+        let root = parse_document(&path)?;
+        if !self.inputs.contains(&path) {
+            self.inputs.push(path.clone());
+        }
+        self.include_stack.push(path);
+        let result = self.expand_nodes(&root.children, &root, out);
+        self.include_stack.pop();
+        result
+    }
+
+    fn call_macro(&mut self, name: &str, element: &Element, out: &mut Vec<Node>) -> Result<(), XacroError> {
+        let definition = self.find_macro(name)
+            .ok_or_else(|| element.error(format!("unknown macro 'xacro:{}'", name)))?;
+
+        for (key, _) in &element.attributes {
+            if !definition.params.iter().any(|param| param.kind == ParamKind::Value && param.name == *key) {
+                return Err(element.error(format!("macro '{}' has no parameter '{}'", name, key)));
+            }
+        }
+
+        let mut blocks = element.child_elements();
+        let mut frame = Frame::default();
+
+        for param in &definition.params {
+            let value = match param.kind {
+                ParamKind::Block => {
+                    let block = blocks.next()
+                        .ok_or_else(|| element.error(format!("macro '{}' expects block parameter '{}'", name, param.name)))?;
+                    Value::Block(Rc::new(vec![Node::Element(block.clone())]))
+                }
+                ParamKind::MultiBlock => {
+                    let block = blocks.next()
+                        .ok_or_else(|| element.error(format!("macro '{}' expects block parameter '{}'", name, param.name)))?;
+                    Value::Block(Rc::new(block.children.clone()))
+                }
+                ParamKind::Value => {
+                    if let Some(raw) = element.attribute(&param.name) {
+                        self.eval_text(raw, element)?.literal()
+                    } else if param.inherit && self.is_defined(&param.name) {
+                        self.lookup(&param.name, self.frames.len(), element)?
+                    } else if let Some(default) = &param.default {
+                        self.eval_text(default, element)?.literal()
+                    } else {
+                        return Err(element.error(format!("macro '{}' is missing parameter '{}'", name, param.name)));
+                    }
+                }
+            };
+            frame.properties.insert(param.name.clone(), Property::Resolved(value));
+        }
+
+        if blocks.next().is_some() {
+            return Err(element.error(format!("macro '{}' was given more blocks than it declares", name)));
+        }
+
+        self.frames.push(frame);
+        let result = self.expand_nodes(&definition.body, element, out);
+        self.frames.pop();
+        result
+    }
+
+    fn find_macro(&self, name: &str) -> Option<Rc<Macro>> {
+        self.frames.iter().rev().find_map(|frame| frame.macros.get(name).cloned())
+    }
+
+    fn is_defined(&self, name: &str) -> bool {
+        self.frames.iter().any(|frame| frame.properties.contains_key(name))
+    }
+
+    fn lookup(&mut self, name: &str, upto: usize, context: &Element) -> Result<Value, XacroError> {
+        for index in (0..upto.min(self.frames.len())).rev() {
+            let raw = match self.frames[index].properties.get(name) {
+                Some(Property::Resolved(value)) => return Ok(value.clone()),
+                Some(Property::Lazy(raw)) => raw.clone(),
+                None => continue,
+            };
+
+            if self.resolving.iter().any(|pending| pending == name) {
+                return Err(context.error(format!("recursive definition of property '{}'", name)));
+            }
+
+            self.resolving.push(name.to_string());
+            let value = self.eval_text_upto(&raw, index + 1, context);
+            self.resolving.pop();
+
+            let value = value?.literal();
+            self.frames[index].properties.insert(name.to_string(), Property::Resolved(value.clone()));
+            return Ok(value);
+        }
+
+        Err(context.error(format!("undefined property '{}'", name)))
+    }
+
+    fn eval_condition(&mut self, element: &Element) -> Result<bool, XacroError> {
+        let value = self.eval_text(element.required_attribute("value")?, element)?;
+        match value {
+            Value::Text(text) => match text.trim() {
+                "true" | "True" => Ok(true),
+                "false" | "False" => Ok(false),
+                other => other.parse::<f64>()
+                    .map(|number| number != 0.0)
+                    .map_err(|_| element.error(format!("'{}' is not a boolean expression", other))),
+            },
+            other => Ok(other.truthy()),
+        }
+    }
+
+    fn eval_text(&mut self, text: &str, context: &Element) -> Result<Value, XacroError> {
+        let upto = self.frames.len();
+        self.eval_text_upto(text, upto, context)
+    }
+
+    fn eval_text_upto(&mut self, text: &str, upto: usize, context: &Element) -> Result<Value, XacroError> {
+        let mut parts: Vec<Value> = Vec::new();
+        let mut literal = String::new();
+        let mut index = 0;
+
+        while index < text.len() {
+            let rest = &text[index..];
+
+            if rest.starts_with("$${") || rest.starts_with("$$(") {
+                literal.push_str(&rest[1..3]);
+                index += 3;
+            } else if rest.starts_with("${") || rest.starts_with("$(") {
+                let (open, close) = if rest.starts_with("${") { ('{', '}') } else { ('(', ')') };
+                let end = find_closing(rest, open, close)
+                    .ok_or_else(|| context.error(format!("unterminated expression in '{}'", text)))?;
+                let inner = &rest[2..end];
+
+                if !literal.is_empty() {
+                    parts.push(Value::Text(std::mem::take(&mut literal)));
+                }
+
+                if open == '{' {
+                    parts.push(self.eval_expression(inner, upto, context)?);
+                } else {
+                    parts.push(Value::Text(self.eval_substitution(inner, context)?));
+                }
+                index += end + 1;
+            } else {
+                let c = rest.chars().next().unwrap_or_default();
+                literal.push(c);
+                index += c.len_utf8();
+            }
+        }
+
+        if !literal.is_empty() {
+            parts.push(Value::Text(literal));
+        }
+
+        if parts.len() == 1 {
+            return Ok(parts.remove(0));
+        }
+
+        Ok(Value::Text(parts.iter().map(Value::to_text).collect()))
+    }
+
+    fn eval_expression(&mut self, source: &str, upto: usize, context: &Element) -> Result<Value, XacroError> {
+        let tokens = tokenize(source)
+            .map_err(|message| context.error(format!("{} in '${{{}}}'", message, source)))?;
+
+        let mut parser = ExpressionParser {
+            tokens,
+            position: 0,
+            processor: self,
+            upto,
+            context,
+        };
+
+        let value = parser.parse_expression()?;
+        if parser.position != parser.tokens.len() {
+            return Err(context.error(format!("unexpected trailing tokens in '${{{}}}'", source)));
+        }
+        Ok(value)
+    }
+
+    fn eval_substitution(&mut self, source: &str, context: &Element) -> Result<String, XacroError> {
+        let mut words = source.split_whitespace();
+        let command = words.next().unwrap_or_default();
+        let argument = words.next();
+
+        match (command, argument) {
+            ("find", Some(package)) => {
+                let path = self.packages.find(package, &context.file);
+                self.record(Substitution::Package {
+                    name: package.to_string(),
+                    file: context.file.to_path_buf(),
+                    path: path.clone(),
+                });
+                path.map(|path| path.to_string_lossy().replace('\\', "/"))
+                    .ok_or_else(|| context.error(format!("package '{}' not found", package)))
+            }
+            ("arg", Some(name)) => self.args.get(name)
+                .cloned()
+                .ok_or_else(|| context.error(format!("undefined substitution argument '{}'", name))),
+            ("env", Some(name)) => {
+                let value = env::var(name).ok();
+                self.record(Substitution::Env { name: name.to_string(), value: value.clone() });
+                value.ok_or_else(|| context.error(format!("environment variable '{}' is not set", name)))
+            }
+            ("optenv", Some(name)) => {
+                let value = env::var(name).ok();
+                self.record(Substitution::Env { name: name.to_string(), value: value.clone() });
+                Ok(value.unwrap_or_else(|| words.collect::<Vec<_>>().join(" ")))
+            }
+            ("dirname", None) => Ok(context.file.parent()
+                .map(|dir| absolute_path(dir).to_string_lossy().replace('\\', "/"))
+                .unwrap_or_default()),
+            _ => Err(context.error(format!("unsupported substitution '$({})'", source))),
+        }
+    }
+
+    fn record(&mut self, substitution: Substitution) {
+        if !self.substitutions.contains(&substitution) {
+            self.substitutions.push(substitution);
+        }
+    }
+}
+
+fn parse_macro_params(spec: &str) -> Vec<MacroParam> {
+    spec.split_whitespace()
+        .map(|token| {
+            let (kind, rest) = if let Some(rest) = token.strip_prefix("**") {
+                (ParamKind::MultiBlock, rest)
+            } else if let Some(rest) = token.strip_prefix('*') {
+                (ParamKind::Block, rest)
+            } else {
+                (ParamKind::Value, token)
+            };
+
+            let (name, default) = match rest.split_once(":=").or_else(|| rest.split_once('=')) {
+                Some((name, default)) => (name, Some(default.trim_matches(|c| c == '\'' || c == '"'))),
+                None => (rest, None),
+            };
+
+            let (inherit, default) = match default.and_then(|d| d.strip_prefix('^')) {
+                Some(fallback) => (true, fallback.strip_prefix('|').map(str::to_string)),
+                None => (false, default.map(str::to_string)),
+            };
+
+            MacroParam {
+                name: name.to_string(),
+                kind,
+                default,
+                inherit,
+            }
+        })
+        .collect()
+}
+
+fn find_closing(text: &str, open: char, close: char) -> Option<usize> {
+    let mut depth = 0;
+    let mut quote: Option<char> = None;
+
+    for (index, c) in text.char_indices().skip(1) {
+        if let Some(q) = quote {
+            if c == q {
+                quote = None;
+            }
+            continue;
+        }
+        match c {
+            '\'' | '"' => quote = Some(c),
+            c if c == open => depth += 1,
+            c if c == close => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(index);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+pub(super) fn absolute_path(path: &Path) -> PathBuf {
+    if path.is_absolute() {
+        return path.to_path_buf();
+    }
+    env::current_dir()
+        .map(|cwd| cwd.join(path))
+        .unwrap_or_else(|_| path.to_path_buf())
+}
+
+#[derive(Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    Str(String),
+    Name(String),
+    Op(&'static str),
+}
+
+const OPERATORS: [&str; 18] = [
+    "**", "//", "==", "!=", "<=", ">=",
+    "+", "-", "*", "/", "%", "<", ">", "(", ")", ",", "[", "]",
+];
+
+fn tokenize(source: &str) -> Result<Vec<Token>, String> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut tokens = Vec::new();
+    let mut index = 0;
+
+    while index < chars.len() {
+        let c = chars[index];
+
+        if c.is_whitespace() {
+            index += 1;
+        } else if c.is_ascii_digit() || (c == '.' && chars.get(index + 1).is_some_and(|n| n.is_ascii_digit())) {
+            let start = index;
+            while index < chars.len() && (chars[index].is_ascii_digit() || chars[index] == '.') {
+                index += 1;
+            }
+            if index < chars.len() && (chars[index] == 'e' || chars[index] == 'E') {
+                let mut lookahead = index + 1;
+                if lookahead < chars.len() && (chars[lookahead] == '+' || chars[lookahead] == '-') {
+                    lookahead += 1;
+                }
+                if lookahead < chars.len() && chars[lookahead].is_ascii_digit() {
+                    index = lookahead;
+                    while index < chars.len() && chars[index].is_ascii_digit() {
+                        index += 1;
+                    }
+                }
+            }
+            let text: String = chars[start..index].iter().collect();
+            let number = text.parse::<f64>().map_err(|_| format!("invalid number '{}'", text))?;
+            tokens.push(Token::Number(number));
+        } else if c.is_alphabetic() || c == '_' {
+            let start = index;
+            while index < chars.len() && (chars[index].is_alphanumeric() || chars[index] == '_' || chars[index] == '.') {
+                index += 1;
+            }
+            tokens.push(Token::Name(chars[start..index].iter().collect()));
+        } else if c == '\'' || c == '"' {
+            let start = index + 1;
+            index = start;
+            while index < chars.len() && chars[index] != c {
+                index += 1;
+            }
+            if index >= chars.len() {
+                return Err("unterminated string literal".to_string());
+            }
+            tokens.push(Token::Str(chars[start..index].iter().collect()));
+            index += 1;
+        } else {
+            let rest: String = chars[index..chars.len().min(index + 2)].iter().collect();
+            let op = OPERATORS.iter()
+                .find(|op| rest.starts_with(**op))
+                .ok_or_else(|| format!("unexpected character '{}'", c))?;
+            tokens.push(Token::Op(op));
+            index += op.chars().count();
+        }
+    }
+
+    Ok(tokens)
+}
+
+struct ExpressionParser<'a> {
+    tokens: Vec<Token>,
+    position: usize,
+    processor: &'a mut XacroProcessor,
+    upto: usize,
+    context: &'a Element,
+}
+
+impl<'a> ExpressionParser<'a> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn accept_op(&mut self, op: &str) -> bool {
+        if matches!(self.peek(), Some(Token::Op(o)) if *o == op) {
+            self.position += 1;
+            return true;
+        }
+        false
+    }
+
+    fn accept_keyword(&mut self, keyword: &str) -> bool {
+        if matches!(self.peek(), Some(Token::Name(n)) if n == keyword) {
+            self.position += 1;
+            return true;
+        }
+        false
+    }
+
+    fn expect_op(&mut self, op: &str) -> Result<(), XacroError> {
+        if self.accept_op(op) {
+            Ok(())
+        } else {
+            Err(self.error(format!("expected '{}'", op)))
+        }
+    }
+
+    fn error(&self, message: impl Into<String>) -> XacroError {
+        self.context.error(format!("expression error: {}", message.into()))
+    }
+
+    fn parse_expression(&mut self) -> Result<Value, XacroError> {
+        let value = self.parse_or()?;
+        if self.accept_keyword("if") {
+            let condition = self.parse_or()?;
+            if !self.accept_keyword("else") {
+                return Err(self.error("expected 'else'"));
+            }
+            let alternative = self.parse_expression()?;
+            return Ok(if condition.truthy() { value } else { alternative });
+        }
+        Ok(value)
+    }
+
+    fn parse_or(&mut self) -> Result<Value, XacroError> {
+        let mut value = self.parse_and()?;
+        while self.accept_keyword("or") {
+            let rhs = self.parse_and()?;
+            value = if value.truthy() { value } else { rhs };
+        }
+        Ok(value)
+    }
+
+    fn parse_and(&mut self) -> Result<Value, XacroError> {
+        let mut value = self.parse_not()?;
+        while self.accept_keyword("and") {
+            let rhs = self.parse_not()?;
+            value = if value.truthy() { rhs } else { value };
+        }
+        Ok(value)
+    }
+
+    fn parse_not(&mut self) -> Result<Value, XacroError> {
+        if self.accept_keyword("not") {
+            let value = self.parse_not()?;
+            return Ok(Value::Bool(!value.truthy()));
+        }
+        self.parse_comparison()
+    }
+
+    fn parse_comparison(&mut self) -> Result<Value, XacroError> {
+        let mut lhs = self.parse_sum()?;
+        loop {
+            let op = match self.peek() {
+                Some(Token::Op(op)) if matches!(*op, "==" | "!=" | "<" | "<=" | ">" | ">=") => *op,
+                _ => return Ok(lhs),
+            };
+            self.position += 1;
+            let rhs = self.parse_sum()?;
+
+            let ordering = match (lhs.as_number(), rhs.as_number()) {
+                (Some(a), Some(b)) => a.partial_cmp(&b),
+                _ => Some(lhs.to_text().cmp(&rhs.to_text())),
+            };
+            let result = match (op, ordering) {
+                ("==", Some(o)) => o.is_eq(),
+                ("!=", o) => !o.is_some_and(|o| o.is_eq()),
+                ("<", Some(o)) => o.is_lt(),
+                ("<=", Some(o)) => o.is_le(),
+                (">", Some(o)) => o.is_gt(),
+                (">=", Some(o)) => o.is_ge(),
+                _ => false,
+            };
+            lhs = Value::Bool(result);
+        }
+    }
+
+    fn parse_sum(&mut self) -> Result<Value, XacroError> {
+        let mut lhs = self.parse_term()?;
+        loop {
+            if self.accept_op("+") {
+                let rhs = self.parse_term()?;
+                lhs = match (&lhs, &rhs) {
+                    (Value::Text(a), Value::Text(b)) => Value::Text(format!("{}{}", a, b)),
+                    _ => match (lhs.as_number(), rhs.as_number()) {
+                        (Some(a), Some(b)) if !(lhs.is_text() && rhs.is_text()) => Value::Number(a + b),
+                        _ => Value::Text(format!("{}{}", lhs.to_text(), rhs.to_text())),
+                    },
+                };
+            } else if self.accept_op("-") {
+                let rhs = self.parse_term()?;
+                lhs = Value::Number(self.number(&lhs)? - self.number(&rhs)?);
+            } else {
+                return Ok(lhs);
+            }
+        }
+    }
+
+    fn parse_term(&mut self) -> Result<Value, XacroError> {
+        let mut lhs = self.parse_unary()?;
+        loop {
+            let op = match self.peek() {
+                Some(Token::Op(op)) if matches!(*op, "*" | "/" | "//" | "%") => *op,
+                _ => return Ok(lhs),
+            };
+            self.position += 1;
+            let rhs = self.parse_unary()?;
+            let (a, b) = (self.number(&lhs)?, self.number(&rhs)?);
+
+            if op != "*" && b == 0.0 {
+                return Err(self.error("division by zero"));
+            }
+            lhs = Value::Number(match op {
+                "*" => a * b,
+                "/" => a / b,
+                "//" => (a / b).floor(),
+                _ => a - b * (a / b).floor(),
+            });
+        }
+    }
+
+    fn parse_unary(&mut self) -> Result<Value, XacroError> {
+        if self.accept_op("-") {
+            let value = self.parse_unary()?;
+            return Ok(Value::Number(-self.number(&value)?));
+        }
+        if self.accept_op("+") {
+            let value = self.parse_unary()?;
+            return Ok(Value::Number(self.number(&value)?));
+        }
+        self.parse_power()
+    }
+
+    fn parse_power(&mut self) -> Result<Value, XacroError> {
+        let base = self.parse_atom()?;
+        if self.accept_op("**") {
+            let exponent = self.parse_unary()?;
+            return Ok(Value::Number(self.number(&base)?.powf(self.number(&exponent)?)));
+        }
+        Ok(base)
+    }
+
+    fn parse_atom(&mut self) -> Result<Value, XacroError> {
+        let token = self.peek().cloned().ok_or_else(|| self.error("unexpected end of expression"))?;
+        self.position += 1;
+
+        match token {
+            Token::Number(number) => Ok(Value::Number(number)),
+            Token::Str(text) => Ok(Value::Text(text)),
+            Token::Op("(") => {
+                let value = self.parse_expression()?;
+                self.expect_op(")")?;
+                Ok(value)
+            }
+            Token::Name(name) => {
+                if self.accept_op("(") {
+                    let mut args = Vec::new();
+                    if !self.accept_op(")") {
+                        loop {
+                            args.push(self.parse_expression()?);
+                            if self.accept_op(")") {
+                                break;
+                            }
+                            self.expect_op(",")?;
+                        }
+                    }
+                    return self.call_function(&name, &args);
+                }
+                self.resolve_name(&name)
+            }
+            Token::Op(op) => Err(self.error(format!("unexpected '{}'", op))),
+        }
+    }
+
+    fn resolve_name(&mut self, name: &str) -> Result<Value, XacroError> {
+        match name {
+            "True" | "true" => return Ok(Value::Bool(true)),
+            "False" | "false" => return Ok(Value::Bool(false)),
+            _ => {}
+        }
+
+        if self.processor.is_defined(name) {
+            return self.processor.lookup(name, self.upto, self.context);
+        }
+
+        match name.strip_prefix("math.").unwrap_or(name) {
+            "pi" => Ok(Value::Number(PI)),
+            "e" => Ok(Value::Number(E)),
+            "inf" => Ok(Value::Number(f64::INFINITY)),
+            _ => Err(self.context.error(format!("undefined property '{}'", name))),
+        }
+    }
+
+    fn call_function(&self, name: &str, args: &[Value]) -> Result<Value, XacroError> {
+        let numbers = args.iter()
+            .map(|arg| self.number(arg))
+            .collect::<Result<Vec<f64>, XacroError>>();
+
+        let unary = |f: fn(f64) -> f64| -> Result<Value, XacroError> {
+            match numbers.as_deref() {
+                Ok([x]) => Ok(Value::Number(f(*x))),
+                Ok(_) => Err(self.error(format!("{}() takes exactly one argument", name))),
+                Err(e) => Err(e.clone()),
+            }
+        };
+
+        match name.strip_prefix("math.").unwrap_or(name) {
+            "sin" => unary(f64::sin),
+            "cos" => unary(f64::cos),
+            "tan" => unary(f64::tan),
+            "asin" => unary(f64::asin),
+            "acos" => unary(f64::acos),
+            "atan" => unary(f64::atan),
+            "sqrt" => unary(f64::sqrt),
+            "exp" => unary(f64::exp),
+            "log" => unary(f64::ln),
+            "log10" => unary(f64::log10),
+            "fabs" | "abs" => unary(f64::abs),
+            "floor" => unary(f64::floor),
+            "ceil" => unary(f64::ceil),
+            "int" | "trunc" => unary(f64::trunc),
+            "float" => unary(|x| x),
+            "radians" => unary(f64::to_radians),
+            "degrees" => unary(f64::to_degrees),
+            "round" => match numbers?.as_slice() {
+                [x] => Ok(Value::Number(x.round())),
+                [x, digits] => {
+                    let scale = 10f64.powi(*digits as i32);
+                    Ok(Value::Number((x * scale).round() / scale))
+                }
+                _ => Err(self.error("round() takes one or two arguments")),
+            },
+            "atan2" | "pow" | "copysign" | "fmod" | "hypot" => match numbers?.as_slice() {
+                [a, b] => Ok(Value::Number(match name.strip_prefix("math.").unwrap_or(name) {
+                    "atan2" => a.atan2(*b),
+                    "pow" => a.powf(*b),
+                    "copysign" => a.copysign(*b),
+                    "fmod" => a % b,
+                    _ => a.hypot(*b),
+                })),
+                _ => Err(self.error(format!("{}() takes exactly two arguments", name))),
+            },
+            "min" | "max" => {
+                let numbers = numbers?;
+                if numbers.is_empty() {
+                    return Err(self.error(format!("{}() needs at least one argument", name)));
+                }
+                let fold: fn(f64, f64) -> f64 = if name == "min" { f64::min } else { f64::max };
+                Ok(Value::Number(numbers.into_iter().reduce(fold).unwrap_or_default()))
+            }
+            "str" => match args {
+                [arg] => Ok(Value::Text(arg.to_text())),
+                _ => Err(self.error("str() takes exactly one argument")),
+            },
+            "bool" => match args {
+                [arg] => Ok(Value::Bool(arg.clone().literal().truthy())),
+                _ => Err(self.error("bool() takes exactly one argument")),
+            },
+            _ => Err(self.error(format!("unknown function '{}'", name))),
+        }
+    }
+
+    fn number(&self, value: &Value) -> Result<f64, XacroError> {
+        value.as_number()
+            .ok_or_else(|| self.error(format!("'{}' is not a number", value.to_text())))
+    }
+}
+
+fn parse_document(path: &Path) -> Result<Element, XacroError> {
+    let content = read_file_to_string_smart(path)
+        .ok_or_else(|| XacroError::new(path, 0, "failed to read file"))?;
+    let file = Rc::new(path.to_path_buf());
+
+    let line_starts: Vec<usize> = std::iter::once(0)
+        .chain(content.match_indices('\n').map(|(index, _)| index + 1))
+        .collect();
+    let line_at = |offset: usize| line_starts.partition_point(|start| *start <= offset);
+
+    let mut reader = Reader::from_str(&content);
+    let mut stack: Vec<Element> = Vec::new();
+    let mut root: Option<Element> = None;
+
+    loop {
+        let offset = reader.buffer_position();
+        let event = reader.read_event()
+            .map_err(|e| XacroError::new(path, line_at(reader.buffer_position()), e.to_string()))?;
+
+        match event {
+            Event::Start(start) => {
+                stack.push(element_from_start(&start, &file, line_at(offset))?);
+            }
+            Event::Empty(start) => {
+                let element = element_from_start(&start, &file, line_at(offset))?;
+                attach_element(&mut stack, &mut root, element);
+            }
+            Event::End(_) => {
+                if let Some(element) = stack.pop() {
+                    attach_element(&mut stack, &mut root, element);
+                }
+            }
+            Event::Text(text) => {
+                let text = text.unescape()
+                    .map_err(|e| XacroError::new(path, line_at(offset), e.to_string()))?;
+                if let Some(parent) = stack.last_mut() {
+                    if !text.trim().is_empty() {
+                        parent.children.push(Node::Text(text.trim().to_string()));
+                    }
+                }
+            }
+            Event::CData(data) => {
+                if let Some(parent) = stack.last_mut() {
+                    parent.children.push(Node::Text(String::from_utf8_lossy(&data).into_owned()));
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    if let Some(unclosed) = stack.last() {
+        return Err(unclosed.error(format!("<{}> is never closed", unclosed.name)));
+    }
+
+    root.ok_or_else(|| XacroError::new(path, 1, "document has no root element"))
+}
+
+fn element_from_start(start: &BytesStart, file: &Rc<PathBuf>, line: usize) -> Result<Element, XacroError> {
+    let name = String::from_utf8_lossy(start.name().as_ref()).into_owned();
+    let mut attributes = Vec::new();
+
+    for attribute in start.attributes() {
+        let attribute = attribute.map_err(|e| XacroError::new(file, line, e.to_string()))?;
+        let key = String::from_utf8_lossy(attribute.key.as_ref()).into_owned();
+        let value = attribute.unescape_value()
+            .map_err(|e| XacroError::new(file, line, e.to_string()))?
+            .into_owned();
+        attributes.push((key, value));
+    }
+
+    Ok(Element {
+        name,
+        attributes,
+        children: Vec::new(),
+        file: file.clone(),
+        line,
+    })
+}
+
+fn attach_element(stack: &mut [Element], root: &mut Option<Element>, element: Element) {
+    match stack.last_mut() {
+        Some(parent) => parent.children.push(Node::Element(element)),
+        None => {
+            if root.is_none() {
+                *root = Some(element);
+            }
+        }
+    }
+}
+
+fn serialize_document(root: &Element) -> String {
+    let mut out = String::from("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+    write_element(&mut out, root, 0);
+    out
+}
+
+fn write_element(out: &mut String, element: &Element, depth: usize) {
+    let indent = "  ".repeat(depth);
+    out.push_str(&indent);
+    out.push('<');
+    out.push_str(&element.name);
+    for (key, value) in &element.attributes {
+        out.push_str(&format!(" {}=\"{}\"", key, escape_xml(value)));
+    }
+
+    if element.children.is_empty() {
+        out.push_str("/>\n");
+        return;
+    }
+
+    if let [Node::Text(text)] = element.children.as_slice() {
+        out.push_str(&format!(">{}</{}>\n", escape_xml(text), element.name));
+        return;
+    }
+
+    out.push_str(">\n");
+    for child in &element.children {
+        match child {
+            Node::Element(child) => write_element(out, child, depth + 1),
+            Node::Text(text) => {
+                out.push_str(&"  ".repeat(depth + 1));
+                out.push_str(&escape_xml(text));
+                out.push('\n');
+            }
+            Node::Attribute(..) => {}
+        }
+    }
+    out.push_str(&indent);
+    out.push_str(&format!("</{}>\n", element.name));
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEADER: &str = r#"<robot name="test" xmlns:xacro="http://www.ros.org/wiki/xacro">"#;
+
+    // files: (ファイル名, 内容)。最初のファイルを展開する
+    fn expand(name: &str, files: &[(&str, &str)]) -> Result<String, XacroError> {
+        let dir = std::env::temp_dir().join(format!("udon_xacro_{}_{}", std::process::id(), name));
+        std::fs::create_dir_all(&dir).unwrap();
+        for (file, content) in files {
+            std::fs::write(dir.join(file), content).unwrap();
+        }
+        let result = expand_file_with_inputs(&dir.join(files[0].0), &PackageResolver::default()).map(|expansion| expansion.urdf);
+        let _ = std::fs::remove_dir_all(&dir);
+        result
+    }
+
+    fn expand_body(name: &str, body: &str) -> String {
+        expand(name, &[("robot.xacro", &format!("{}\n{}\n</robot>", HEADER, body))]).unwrap()
+    }
+
+    #[test]
+    fn properties_follow_scopes() {
+        let urdf = expand_body("scopes", r#"
+            <xacro:property name="lazy" value="${size + 1}"/>
+            <xacro:property name="size" value="1"/>
+            <xacro:macro name="part">
+              <xacro:property name="size" value="2"/>
+              <xacro:property name="exported" value="${size * 3}" scope="parent"/>
+              <link name="inner_${size}"/>
+            </xacro:macro>
+            <xacro:part/>
+            <link name="outer_${size}_${exported}_${lazy}"/>"#);
+
+        assert!(urdf.contains(r#"<link name="inner_2"/>"#), "{}", urdf);
+        assert!(urdf.contains(r#"<link name="outer_1_6_2"/>"#), "{}", urdf);
+    }
+
+    #[test]
+    fn block_parameters_insert_elements_and_contents() {
+        let urdf = expand_body("blocks", r#"
+            <xacro:macro name="part" params="name *origin **contents">
+              <joint name="${name}_joint" type="fixed">
+                <xacro:insert_block name="origin"/>
+              </joint>
+              <link name="${name}">
+                <xacro:insert_block name="contents"/>
+              </link>
+            </xacro:macro>
+            <xacro:part name="arm">
+              <origin xyz="1 2 3"/>
+              <contents>
+                <visual/>
+                <collision/>
+              </contents>
+            </xacro:part>"#);
+
+        assert!(urdf.contains(r#"<origin xyz="1 2 3"/>"#), "{}", urdf);
+        assert!(urdf.contains("<visual/>") && urdf.contains("<collision/>"), "{}", urdf);
+        assert!(!urdf.contains("<contents"), "{}", urdf);
+    }
+
+    #[test]
+    fn caret_defaults_inherit_from_outer_scope() {
+        let urdf = expand_body("caret", r#"
+            <xacro:property name="color" value="blue"/>
+            <xacro:macro name="part" params="name color:=^ shade:=^|dark">
+              <link name="${name}_${color}_${shade}"/>
+            </xacro:macro>
+            <xacro:part name="a"/>
+            <xacro:part name="b" color="red" shade="light"/>"#);
+
+        assert!(urdf.contains(r#"<link name="a_blue_dark"/>"#), "{}", urdf);
+        assert!(urdf.contains(r#"<link name="b_red_light"/>"#), "{}", urdf);
+    }
+
+    #[test]
+    fn conditionals_select_elements() {
+        let urdf = expand_body("conditionals", r#"
+            <xacro:property name="count" value="2"/>
+            <xacro:if value="${count > 1}"><link name="if_true"/></xacro:if>
+            <xacro:if value="false"><link name="if_false"/></xacro:if>
+            <xacro:unless value="${count == 2}"><link name="unless_true"/></xacro:unless>
+            <xacro:unless value="0"><link name="unless_false"/></xacro:unless>"#);
+
+        assert!(urdf.contains("if_true") && urdf.contains("unless_false"), "{}", urdf);
+        assert!(!urdf.contains("if_false") && !urdf.contains("unless_true"), "{}", urdf);
+    }
+
+    #[test]
+    fn expressions_follow_python_precedence() {
+        let cases = [
+            ("1 + 2 * 3", "7"),
+            ("(1 + 2) * 3", "9"),
+            ("-2 ** 2", "-4"),
+            ("2 ** 3 ** 2", "512"),
+            ("7 // 2", "3"),
+            ("-7 % 3", "2"),
+            ("1 < 2 and not 0", "True"),
+            ("1 > 2 or 3 == 4", "False"),
+            ("'ab' + 'cd'", "abcd"),
+        ];
+        let body: String = cases.iter()
+            .enumerate()
+            .map(|(index, (expression, _))| format!("<link name=\"l{}\" value=\"${{{}}}\"/>\n", index, expression))
+            .collect();
+        let urdf = expand_body("expressions", &body);
+
+        for (index, (expression, expected)) in cases.iter().enumerate() {
+            let expected = format!("<link name=\"l{}\" value=\"{}\"/>", index, expected);
+            assert!(urdf.contains(&expected), "{} should give {}", expression, expected);
+        }
+    }
+
+    #[test]
+    fn environment_lookups_are_recorded() {
+        let name = format!("UDON_XACRO_TEST_{}", std::process::id());
+        env::set_var(&name, "base");
+        let dir = std::env::temp_dir().join(format!("udon_xacro_{}_env", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("robot.xacro");
+        std::fs::write(&path, format!("{}\n<link name=\"$(env {})\"/>\n</robot>", HEADER, name)).unwrap();
+
+        let expansion = expand_file_with_inputs(&path, &PackageResolver::default()).unwrap();
+        let _ = std::fs::remove_dir_all(&dir);
+        assert!(expansion.urdf.contains(r#"<link name="base"/>"#), "{}", expansion.urdf);
+        assert_eq!(expansion.substitutions, [Substitution::Env { name: name.clone(), value: Some("base".to_string()) }]);
+
+        let packages = PackageResolver::default();
+        assert!(expansion.substitutions[0].is_current(&packages));
+        env::set_var(&name, "changed");
+        assert!(!expansion.substitutions[0].is_current(&packages));
+        env::remove_var(&name);
+    }
+
+    #[test]
+    fn errors_report_the_file_and_line() {
+        let included = "<robot xmlns:xacro=\"http://www.ros.org/wiki/xacro\">\n  <link name=\"base\"/>\n  <link name=\"${missing}\"/>\n</robot>";
+        let error = expand("errors", &[
+            ("robot.xacro", &format!("{}\n  <xacro:include filename=\"part.xacro\"/>\n</robot>", HEADER)),
+            ("part.xacro", included),
+        ]).unwrap_err();
+
+        assert!(error.file.ends_with("part.xacro"), "{}", error);
+        assert_eq!(error.line, 3, "{}", error);
+        assert!(error.message.contains("missing"), "{}", error);
+    }
+}
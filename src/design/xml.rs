@@ -0,0 +1,119 @@
+use bevy::prelude::*;
+use std::path::Path;
+
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+
+use super::loader::read_file_to_string_smart;
+
+// SDF / MJCF の読み込みに使う簡易 XML ツリー
+pub(super) struct XmlElement {
+    pub name: String,
+    pub attributes: Vec<(String, String)>,
+    pub text: String,
+    pub children: Vec<XmlElement>,
+}
+
+impl XmlElement {
+    pub fn attribute(&self, key: &str) -> Option<&str> {
+        self.attributes.iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    pub fn child(&self, name: &str) -> Option<&XmlElement> {
+        self.children.iter().find(|child| child.name == name)
+    }
+
+    pub fn children<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a XmlElement> {
+        self.children.iter().filter(move |child| child.name == name)
+    }
+
+    pub fn child_text(&self, name: &str) -> Option<&str> {
+        self.child(name).map(|child| child.text.as_str())
+    }
+
+    pub fn child_f64(&self, name: &str) -> Option<f64> {
+        self.child_text(name)?.trim().parse().ok()
+    }
+}
+
+pub(super) fn parse_values(text: &str) -> Option<Vec<f64>> {
+    text.split_whitespace().map(|value| value.parse().ok()).collect()
+}
+
+pub(super) fn vec3(text: &str) -> Option<Vec3> {
+    match parse_values(text)?.as_slice() {
+        [x, y, z] => Some(Vec3::new(*x as f32, *y as f32, *z as f32)),
+        _ => None,
+    }
+}
+
+pub(super) fn parse_bool(text: &str) -> bool {
+    matches!(text.trim(), "true" | "1")
+}
+
+pub(super) fn parse_document(path: &Path) -> Result<XmlElement, String> {
+    let content = read_file_to_string_smart(path)
+        .ok_or_else(|| "failed to read file".to_string())?;
+    parse_str(&content)
+}
+
+pub(super) fn parse_str(content: &str) -> Result<XmlElement, String> {
+    let mut reader = Reader::from_str(content);
+    let mut stack: Vec<XmlElement> = Vec::new();
+
+    loop {
+        let event = reader.read_event()
+            .map_err(|e| format!("at byte {}: {}", reader.buffer_position(), e))?;
+
+        match event {
+            Event::Start(start) => stack.push(element_from_start(&start)?),
+            Event::Empty(start) => {
+                let element = element_from_start(&start)?;
+                match stack.last_mut() {
+                    Some(parent) => parent.children.push(element),
+                    None => return Ok(element),
+                }
+            }
+            Event::End(_) => {
+                let Some(element) = stack.pop() else {
+                    continue;
+                };
+                match stack.last_mut() {
+                    Some(parent) => parent.children.push(element),
+                    None => return Ok(element),
+                }
+            }
+            Event::Text(text) => {
+                let text = text.unescape().map_err(|e| e.to_string())?;
+                if let Some(element) = stack.last_mut() {
+                    element.text.push_str(text.trim());
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    Err("document has no root element".to_string())
+}
+
+fn element_from_start(start: &BytesStart) -> Result<XmlElement, String> {
+    let mut attributes = Vec::new();
+    for attribute in start.attributes() {
+        let attribute = attribute.map_err(|e| e.to_string())?;
+        let key = String::from_utf8_lossy(attribute.key.as_ref()).into_owned();
+        let value = attribute.unescape_value()
+            .map_err(|e| e.to_string())?
+            .into_owned();
+        attributes.push((key, value));
+    }
+
+    Ok(XmlElement {
+        name: String::from_utf8_lossy(start.name().as_ref()).into_owned(),
+        attributes,
+        text: String::new(),
+        children: Vec::new(),
+    })
+}
@@ -0,0 +1,87 @@
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+use std::collections::HashSet;
+
+// ロボットのコライダーが属するグループ。地面・フィールドの物体・他のロボットすべてと当たる
+pub const ROBOT_GROUP: Group = Group::GROUP_2;
+
+pub fn robot_collision_groups() -> CollisionGroups
+{
+    CollisionGroups::new(ROBOT_GROUP, Group::ALL)
+}
+
+// どのロボット (ベースリンク) のどのリンクに属するコライダーか。メッシュの子コライダーにも付ける
+#[derive(Component, Clone, Copy)]
+pub struct CollisionOwner
+{
+    pub base: Entity,
+    pub link: Entity,
+}
+
+// 同じロボットのリンク同士を当てるかどうか。ベースリンクに付ける
+#[derive(Component, Default)]
+pub struct SelfCollisionRules
+{
+    pub enabled: bool,
+    disabled_pairs: HashSet<(Entity, Entity)>,
+}
+
+impl SelfCollisionRules
+{
+    pub fn new(enabled: bool) -> Self
+    {
+        Self
+        {
+            enabled,
+            disabled_pairs: HashSet::new(),
+        }
+    }
+
+    pub fn disable(&mut self, a: Entity, b: Entity)
+    {
+        self.disabled_pairs.insert(Self::key(a, b));
+    }
+
+    pub fn allow(&mut self, a: Entity, b: Entity)
+    {
+        self.disabled_pairs.remove(&Self::key(a, b));
+    }
+
+    pub fn collides(&self, a: Entity, b: Entity) -> bool
+    {
+        self.enabled && a != b && !self.disabled_pairs.contains(&Self::key(a, b))
+    }
+
+    fn key(a: Entity, b: Entity) -> (Entity, Entity)
+    {
+        if a < b { (a, b) } else { (b, a) }
+    }
+}
+
+// 接触の直前に呼ばれ、同じロボットのリンク同士の接触を SelfCollisionRules で間引く
+#[derive(SystemParam)]
+pub struct RobotContactFilter<'w, 's>
+{
+    owners: Query<'w, 's, &'static CollisionOwner>,
+    rules: Query<'w, 's, &'static SelfCollisionRules>,
+}
+
+impl BevyPhysicsHooks for RobotContactFilter<'_, '_>
+{
+    fn filter_contact_pair(&self, context: PairFilterContextView) -> Option<SolverFlags>
+    {
+        let (Ok(a), Ok(b)) = (self.owners.get(context.collider1()), self.owners.get(context.collider2())) else
+        {
+            return Some(SolverFlags::COMPUTE_IMPULSES);
+        };
+
+        if a.base != b.base
+        {
+            return Some(SolverFlags::COMPUTE_IMPULSES);
+        }
+
+        let collides = self.rules.get(a.base).is_ok_and(|rules| rules.collides(a.link, b.link));
+        collides.then_some(SolverFlags::COMPUTE_IMPULSES)
+    }
+}
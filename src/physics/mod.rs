@@ -1,3 +1,4 @@
+pub mod contact;
 pub mod drag;
 pub mod world;
 
@@ -18,7 +19,7 @@ impl Plugin for PhysicsPlugin
                 }
             )
             .insert_resource(drag::AirEnvironment::default())
-            .add_plugins(RapierPhysicsPlugin::<NoUserData>::default())
+            .add_plugins(RapierPhysicsPlugin::<contact::RobotContactFilter>::default())
             .add_systems(Startup, world::spawn_world)
             .add_systems(Update, (drag::update_air_environment, drag::apply_aerodynamic_drag));
     }
@@ -0,0 +1,135 @@
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+
+use crate::design::registry::RobotRegistry;
+use super::joint::RobotJoint;
+
+// 位置指令を追従させる位置モーターのゲイン (加速度ベース)
+const POSITION_STIFFNESS: f32 = 400.0;
+const POSITION_DAMPING: f32 = 40.0;
+const VELOCITY_GAIN: f32 = 40.0;
+// トルク指令は、到達しない速度を目標にして最大力を指令値に制限した速度モーターで近似する
+const EFFORT_MOTOR_GAIN: f32 = 1.0e6;
+const EFFORT_TARGET_SPEED: f32 = 1.0e3;
+
+// ros_control の hardwareInterface に対応する指令の種類
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ActuatorInterface
+{
+    Effort,
+    Velocity,
+    Position,
+}
+
+impl ActuatorInterface
+{
+    pub fn from_hardware_interface(name: &str) -> Option<Self>
+    {
+        if name.contains("Position")
+        {
+            Some(ActuatorInterface::Position)
+        }
+        else if name.contains("Velocity")
+        {
+            Some(ActuatorInterface::Velocity)
+        }
+        else if name.contains("Effort")
+        {
+            Some(ActuatorInterface::Effort)
+        }
+        else
+        {
+            None
+        }
+    }
+}
+
+// 伝達機構で関節につながるアクチュエータ。関節 (子リンク) のエンティティに付ける
+#[derive(Component)]
+pub struct JointActuator
+{
+    pub name: String,
+    pub interface: ActuatorInterface,
+    // アクチュエータ側の変位 = 関節変位 * reduction
+    pub reduction: f32,
+    // 最後に受け取ったアクチュエータ側の指令値
+    pub command: Option<f32>,
+}
+
+// アクチュエータ名 (または関節名) で関節を駆動する。value の意味はアクチュエータの interface による
+#[derive(Event)]
+pub struct ActuatorCommand
+{
+    pub slot: usize,
+    pub actuator: String,
+    pub value: f32,
+}
+
+// モーターには指令だけを書く。関節の減衰・摩擦は JointDynamics が別に加え、最大力は生成時に effort で抑えてある
+pub fn apply_actuator_commands(
+    mut commands: EventReader<ActuatorCommand>,
+    registry: Res<RobotRegistry>,
+    mut actuators: Query<(&mut JointActuator, &RobotJoint, &mut ImpulseJoint)>,
+)
+{
+    for command in commands.read()
+    {
+        let Some(instance) = registry.get(command.slot) else {
+            warn!("No robot in slot {} for actuator {}", command.slot, command.actuator);
+            continue;
+        };
+        // 伝達機構は関節ごとに 1 つなので、関節名で指されたらその関節のアクチュエータを使う
+        let entity = instance.joint(&command.actuator).or_else(|| {
+            instance.joints()
+                .map(|(_, entity)| entity)
+                .find(|&entity| actuators.get(entity).is_ok_and(|(actuator, ..)| actuator.name == command.actuator))
+        });
+        let Some((mut actuator, joint, mut impulse_joint)) = entity.and_then(|entity| actuators.get_mut(entity).ok()) else {
+            warn!("No actuator named {} in {}", command.actuator, instance.name);
+            continue;
+        };
+
+        actuator.command = Some(command.value);
+        let axis = joint.kind.free_axis();
+        let data = &mut impulse_joint.data;
+
+        match actuator.interface
+        {
+            ActuatorInterface::Effort =>
+            {
+                let mut effort = command.value * actuator.reduction;
+                if joint.effort > 0.0
+                {
+                    effort = effort.clamp(-joint.effort, joint.effort);
+                }
+                let speed = if joint.velocity > 0.0 { joint.velocity } else { EFFORT_TARGET_SPEED };
+
+                data.set_motor_model(axis, MotorModel::ForceBased);
+                data.set_motor_velocity(axis, speed * effort.signum(), EFFORT_MOTOR_GAIN);
+                data.set_motor_max_force(axis, effort.abs());
+            }
+            ActuatorInterface::Velocity =>
+            {
+                let mut speed = command.value / actuator.reduction;
+                if joint.velocity > 0.0
+                {
+                    speed = speed.clamp(-joint.velocity, joint.velocity);
+                }
+
+                data.set_motor_model(axis, MotorModel::AccelerationBased);
+                data.set_motor_velocity(axis, speed, VELOCITY_GAIN);
+            }
+            ActuatorInterface::Position =>
+            {
+                let mut position = command.value / actuator.reduction;
+                if let Some([lower, upper]) = joint.limits
+                {
+                    position = position.clamp(lower, upper);
+                }
+
+                data.set_motor_model(axis, MotorModel::AccelerationBased);
+                data.set_motor_position(axis, position, POSITION_STIFFNESS, POSITION_DAMPING);
+            }
+        }
+    }
+}
@@ -0,0 +1,372 @@
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+use bevy_rapier3d::na::Vector3;
+use std::f32::consts::{PI, TAU};
+
+// 可動域・速度の超過とみなすまでの余裕
+const POSITION_TOLERANCE: f32 = 0.01;
+const VELOCITY_TOLERANCE: f32 = 1.05;
+// モーターが effort の上限に張り付いているとみなす割合
+const EFFORT_SATURATION: f32 = 0.99;
+// mimic 関節を追従させる位置モーターの固有角振動数 [rad/s] と減衰比。
+// ゲインは関節軸まわりの実効慣性に掛けて求めるので、重いリンクでも同じ速さで追従する
+const MIMIC_NATURAL_FREQUENCY: f32 = 20.0;
+const MIMIC_DAMPING_RATIO: f32 = 1.0;
+const MIMIC_TARGET_EPSILON: f32 = 1.0e-4;
+// 質量の変更などでゲインがこの割合以上ずれたら設定し直す
+const MIMIC_GAIN_TOLERANCE: f32 = 0.01;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum JointKind
+{
+    Revolute,
+    Prismatic,
+}
+
+impl JointKind
+{
+    // 関節座標系で拘束されずに残る軸
+    pub fn free_axis(self) -> JointAxis
+    {
+        match self
+        {
+            JointKind::Revolute => JointAxis::AngX,
+            JointKind::Prismatic => JointAxis::X,
+        }
+    }
+}
+
+#[derive(Component)]
+pub struct RobotJoint
+{
+    pub name: String,
+    pub parent: Entity,
+    pub kind: JointKind,
+    // 子リンク座標系での回転軸・直動軸
+    pub axis: Vec3,
+    // 関節変位 0 のときの親リンクから見た子リンクの位置・姿勢
+    pub rest_translation: Vec3,
+    pub rest_rotation: Quat,
+    pub limits: Option<[f32; 2]>,
+    pub effort: f32,
+    pub velocity: f32,
+    pub position: f32,
+    pub speed: f32,
+    // 直前のステップでモーターが関節軸に出した力・トルク
+    pub applied_effort: f32,
+    limit_violated: bool,
+    velocity_violated: bool,
+    effort_saturated: bool,
+}
+
+impl RobotJoint
+{
+    pub fn new(name: String, parent: Entity, kind: JointKind, axis: Vec3, origin: &Transform) -> Self
+    {
+        Self
+        {
+            name,
+            parent,
+            kind,
+            axis,
+            rest_translation: origin.translation,
+            rest_rotation: origin.rotation,
+            limits: None,
+            effort: 0.0,
+            velocity: 0.0,
+            position: 0.0,
+            speed: 0.0,
+            applied_effort: 0.0,
+            limit_violated: false,
+            velocity_violated: false,
+            effort_saturated: false,
+        }
+    }
+}
+
+// URDF の dynamics。モーターは指令と mimic に空けておき、粘性減衰とクーロン摩擦は関節軸まわりの力として加える
+#[derive(Component)]
+pub struct JointDynamics
+{
+    pub damping: f32,
+    pub friction: f32,
+}
+
+// follower = leader * multiplier + offset
+#[derive(Component)]
+pub struct JointMimic
+{
+    pub leader: Entity,
+    pub multiplier: f32,
+    pub offset: f32,
+}
+
+pub fn update_joint_states(
+    context: Res<RapierContext>,
+    mut joints: Query<(&mut RobotJoint, &GlobalTransform, Option<&Velocity>, Option<&RapierImpulseJointHandle>)>,
+    bodies: Query<(&GlobalTransform, Option<&Velocity>)>,
+)
+{
+    let dt = context.integration_parameters.dt;
+    for (mut joint, child_transform, child_velocity, handle) in joints.iter_mut()
+    {
+        let Ok((parent_transform, parent_velocity)) = bodies.get(joint.parent) else {
+            continue;
+        };
+
+        let parent = parent_transform.compute_transform();
+        let child = child_transform.compute_transform();
+        let child_velocity = child_velocity.copied().unwrap_or_default();
+        let parent_velocity = parent_velocity.copied().unwrap_or_default();
+
+        let (position, speed) = match joint.kind
+        {
+            JointKind::Revolute =>
+            {
+                // 親に対する子の姿勢から、回転軸まわりのねじれ角を取り出す
+                let relative = joint.rest_rotation.inverse() * parent.rotation.inverse() * child.rotation;
+                let twist = Vec3::new(relative.x, relative.y, relative.z).dot(joint.axis);
+                let mut position = 2.0 * twist.atan2(relative.w);
+                if position > PI
+                {
+                    position -= TAU;
+                }
+                else if position < -PI
+                {
+                    position += TAU;
+                }
+
+                let speed = (child_velocity.angvel - parent_velocity.angvel).dot(child.rotation * joint.axis);
+                (position, speed)
+            }
+            JointKind::Prismatic =>
+            {
+                let offset = parent.rotation.inverse() * (child.translation - parent.translation) - joint.rest_translation;
+                let position = offset.dot(joint.rest_rotation * joint.axis);
+                let speed = (child_velocity.linvel - parent_velocity.linvel).dot(child.rotation * joint.axis);
+                (position, speed)
+            }
+        };
+
+        // Rapier は解いたモーターの力積を関節に書き戻す
+        let axis = joint.kind.free_axis();
+        let motor_impulse = handle
+            .and_then(|handle| context.impulse_joints.get(handle.0))
+            .and_then(|impulse_joint| impulse_joint.data.motor(axis))
+            .map_or(0.0, |motor| motor.impulse);
+
+        joint.position = position;
+        joint.speed = speed;
+        joint.applied_effort = if dt > 0.0 { motor_impulse / dt } else { 0.0 };
+
+        if let Some([lower, upper]) = joint.limits
+        {
+            let violated = position < lower - POSITION_TOLERANCE || position > upper + POSITION_TOLERANCE;
+            if violated && !joint.limit_violated
+            {
+                warn!("Joint {} left its range [{:.3}, {:.3}]: {:.3}", joint.name, lower, upper, position);
+            }
+            joint.limit_violated = violated;
+        }
+
+        if joint.velocity > 0.0
+        {
+            let violated = speed.abs() > joint.velocity * VELOCITY_TOLERANCE;
+            if violated && !joint.velocity_violated
+            {
+                warn!("Joint {} exceeded its velocity limit {:.3}: {:.3}", joint.name, joint.velocity, speed);
+            }
+            joint.velocity_violated = violated;
+        }
+
+        if joint.effort > 0.0
+        {
+            let saturated = joint.applied_effort.abs() >= joint.effort * EFFORT_SATURATION;
+            if saturated && !joint.effort_saturated
+            {
+                warn!("Joint {} reached its effort limit {:.3}: {:.3}", joint.name, joint.effort, joint.applied_effort);
+            }
+            joint.effort_saturated = saturated;
+        }
+    }
+}
+
+// これから進むシミュレーション時間。Rapier が step_simulation で使う値と同じ式で求める
+fn upcoming_step_time(config: &RapierConfiguration, time: &Time) -> f32
+{
+    match config.timestep_mode
+    {
+        TimestepMode::Fixed { dt, .. } => dt,
+        TimestepMode::Variable { max_dt, time_scale, .. } => (time.delta_seconds() * time_scale).min(max_dt),
+        // 1 フレームに何ステップ進んでも力積は一度しか加わらないので、フレーム分の時間を使う
+        TimestepMode::Interpolated { time_scale, .. } => time.delta_seconds() * time_scale,
+    }
+}
+
+// 力積が関節の相対速度を逆向きにしない大きさまでに抑え、摩擦で止まった関節が振動しないようにする。
+// 物理ステップの直前に、そのステップで進む時間に合わせた力積を加えるのでフレームレートに依らない
+pub fn apply_joint_dynamics(
+    context: Res<RapierContext>,
+    config: Res<RapierConfiguration>,
+    time: Res<Time>,
+    joints: Query<(Entity, &RobotJoint, &JointDynamics, &GlobalTransform)>,
+    handles: Query<&RapierRigidBodyHandle>,
+    mut impulses: Query<&mut ExternalImpulse>,
+)
+{
+    let dt = upcoming_step_time(&config, &time);
+    if dt <= 0.0 || !config.physics_pipeline_active
+    {
+        return;
+    }
+    let mut applied: Vec<(Entity, Entity, JointKind, Vec3, f32)> = Vec::new();
+
+    for (entity, joint, dynamics, child_transform) in joints.iter()
+    {
+        if joint.speed == 0.0
+        {
+            continue;
+        }
+
+        let axis = child_transform.compute_transform().rotation * joint.axis;
+        let inverse_inertia = axis_inverse_inertia(&context, &handles, entity, joint, axis);
+        if inverse_inertia <= 0.0
+        {
+            continue;
+        }
+
+        let resisting = (dynamics.damping * joint.speed.abs() + dynamics.friction) * dt;
+        let impulse = resisting.min(joint.speed.abs() / inverse_inertia) * joint.speed.signum();
+        applied.push((entity, joint.parent, joint.kind, axis, impulse));
+    }
+
+    for (child, parent, kind, axis, impulse) in applied
+    {
+        for (body, sign) in [(child, -1.0), (parent, 1.0)]
+        {
+            let Ok(mut external) = impulses.get_mut(body) else {
+                continue;
+            };
+            match kind
+            {
+                JointKind::Revolute => external.torque_impulse += axis * impulse * sign,
+                JointKind::Prismatic => external.impulse += axis * impulse * sign,
+            }
+        }
+    }
+}
+
+// 子と親を関節軸の向きに動かしにくさ (逆質量・逆慣性モーメント) の和。剛体がまだ無ければ 0
+fn axis_inverse_inertia(
+    context: &RapierContext,
+    handles: &Query<&RapierRigidBodyHandle>,
+    child: Entity,
+    joint: &RobotJoint,
+    axis: Vec3,
+) -> f32
+{
+    [child, joint.parent].into_iter()
+        .filter_map(|body| context.bodies.get(handles.get(body).ok()?.0))
+        .filter(|body| body.is_dynamic())
+        .map(|body| {
+            let properties = body.mass_properties();
+            match joint.kind
+            {
+                JointKind::Revolute =>
+                {
+                    // 逆慣性テンソルの平方根は対称なので axis・I⁻¹・axis = |√I⁻¹ axis|²
+                    (properties.effective_world_inv_inertia_sqrt * Vector3::from(axis)).norm_squared()
+                }
+                JointKind::Prismatic => properties.local_mprops.inv_mass,
+            }
+        })
+        .sum()
+}
+
+// 実効慣性 m に対し、剛性 m ω² と減衰 2 ζ m ω の力ベースのモーターで追従させる。
+// 追従側の減衰・摩擦は JointDynamics が別に加えるので、モーターは位置目標だけを持つ
+fn mimic_gains(inverse_inertia: f32) -> (f32, f32)
+{
+    let inertia = 1.0 / inverse_inertia;
+    let stiffness = inertia * MIMIC_NATURAL_FREQUENCY * MIMIC_NATURAL_FREQUENCY;
+    let damping = inertia * 2.0 * MIMIC_DAMPING_RATIO * MIMIC_NATURAL_FREQUENCY;
+    (stiffness, damping)
+}
+
+// leader の位置から follower の目標位置を決めるだけで、follower の反力は leader に返さない
+pub fn drive_mimic_joints(
+    context: Res<RapierContext>,
+    mut followers: Query<(Entity, &JointMimic, &RobotJoint, &GlobalTransform, &mut ImpulseJoint)>,
+    leaders: Query<&RobotJoint>,
+    handles: Query<&RapierRigidBodyHandle>,
+)
+{
+    for (entity, mimic, joint, transform, mut impulse_joint) in followers.iter_mut()
+    {
+        let Ok(leader) = leaders.get(mimic.leader) else {
+            continue;
+        };
+
+        let axis = transform.compute_transform().rotation * joint.axis;
+        let inverse_inertia = axis_inverse_inertia(&context, &handles, entity, joint, axis);
+        if inverse_inertia <= 0.0
+        {
+            continue;
+        }
+        let (stiffness, damping) = mimic_gains(inverse_inertia);
+
+        let free_axis = joint.kind.free_axis();
+        let target = leader.position * mimic.multiplier + mimic.offset;
+        let current = impulse_joint.data.motor(free_axis);
+        if current.is_some_and(|motor| {
+            (motor.target_pos - target).abs() < MIMIC_TARGET_EPSILON
+                && (motor.stiffness - stiffness).abs() <= stiffness * MIMIC_GAIN_TOLERANCE
+        })
+        {
+            continue;
+        }
+
+        let data = &mut impulse_joint.data;
+        data.set_motor_model(free_axis, MotorModel::ForceBased);
+        data.set_motor_position(free_axis, target, stiffness, damping);
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn dynamics_impulse_spans_the_upcoming_step()
+    {
+        let mut time = Time::<()>::default();
+        time.advance_by(Duration::from_millis(8));
+        let variable = RapierConfiguration::default();
+        assert!((upcoming_step_time(&variable, &time) - 0.008).abs() < 1.0e-6);
+
+        // 長いフレームでも Rapier は max_dt までしか進めない
+        time.advance_by(Duration::from_millis(100));
+        assert_eq!(upcoming_step_time(&variable, &time), 1.0 / 60.0);
+
+        let fixed = RapierConfiguration
+        {
+            timestep_mode: TimestepMode::Fixed { dt: 0.005, substeps: 1 },
+            ..default()
+        };
+        assert_eq!(upcoming_step_time(&fixed, &time), 0.005);
+    }
+
+    #[test]
+    fn mimic_gains_scale_with_inertia()
+    {
+        let (light_stiffness, light_damping) = mimic_gains(1.0);
+        let (heavy_stiffness, heavy_damping) = mimic_gains(0.1);
+        assert!((heavy_stiffness - light_stiffness * 10.0).abs() < 1.0e-3);
+        assert!((heavy_damping - light_damping * 10.0).abs() < 1.0e-3);
+
+        // 臨界減衰: c² = 4 k m
+        assert!((light_damping * light_damping - 4.0 * light_stiffness).abs() < 1.0e-3);
+    }
+}
@@ -1,4 +1,6 @@
+pub mod actuator;
 pub mod drive;
+pub mod joint;
 
 use crate::core::time::{TickEvent, TimeSystem};
 use bevy::prelude::*;
@@ -11,7 +13,15 @@ impl Plugin for RobotPlugin
 {
     fn build(&self, app: &mut App)
     {
-        app.add_systems(Update, apply_drive_input_velocity.after(TimeSystem::Accumulate));
+        app.add_event::<actuator::ActuatorCommand>()
+            .add_systems(Update, (
+                apply_drive_input_velocity.after(TimeSystem::Accumulate),
+                joint::update_joint_states,
+                joint::drive_mimic_joints.after(joint::update_joint_states),
+                actuator::apply_actuator_commands,
+            ))
+            // 力積は SyncBackend で Rapier に渡され、次のステップにだけ効く
+            .add_systems(PostUpdate, joint::apply_joint_dynamics.before(PhysicsSet::SyncBackend));
     }
 }
 
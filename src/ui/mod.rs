@@ -1,10 +1,22 @@
 use bevy::prelude::*;
 use bevy_egui::{egui, EguiContexts, EguiPlugin};
 use std::fs;
-use std::path::Path;
-use crate::design::loader::LoadRobotRequest;
+use std::path::{Path, PathBuf};
+use crate::design::loader::{
+    discover_robot_descriptions, discover_world_descriptions, LoadRobotRequest, LoadStage, ModelSearchPaths,
+    RobotLoadFinished, RobotLoadProgress, WORLD_SLOT,
+};
+use crate::design::export::{ExportFormat, ExportRobotRequest, RobotExported};
+use crate::design::hot_reload::HotReloadSettings;
+use crate::design::registry::{DuplicateRobotRequest, ReloadRobotRequest, RobotInstance, RobotRegistry, UnloadRobotRequest};
+use crate::design::spawn::{ResetRobotRequest, SpawnPose, SpawnPoses, SpawnTransform};
+use crate::design::validation::{IssueSeverity, ValidationIssue};
+use crate::robot::joint::RobotJoint;
 
 pub mod screenshot;
+pub mod spawn_handle;
+
+use spawn_handle::SpawnHandles;
 
 pub struct UiPlugin;
 
@@ -12,15 +24,41 @@ impl Plugin for UiPlugin {
     fn build(&self, app: &mut App) {
         app.add_plugins(EguiPlugin)
            .add_plugins(screenshot::ScreenshotPlugin)
+           .add_plugins(spawn_handle::SpawnHandlePlugin)
            .init_resource::<AvailableModels>()
+           .init_resource::<LoadReport>()
+           .init_resource::<ExportNotification>()
            .add_systems(Startup, (scan_models_directory, configure_ui_font)) // <--- フォント設定を追加
-           .add_systems(Update, ui_system);
+           .add_systems(Update, (ui_system, collect_load_reports, load_report_window, load_progress_window, notify_exports, export_notification_ui));
     }
 }
 
 #[derive(Resource, Default)]
 struct AvailableModels {
-    models: Vec<String>,
+    models: Vec<AvailableModel>,
+    worlds: Vec<AvailableModel>,
+}
+
+struct AvailableModel {
+    label: String,
+    path: PathBuf,
+}
+
+// 直近の読み込み結果をウィンドウに出すための控え
+#[derive(Resource, Default)]
+struct LoadReport {
+    open: bool,
+    title: String,
+    error: Option<String>,
+    issues: Vec<ValidationIssue>,
+}
+
+// 書き出しの結果。スクリーンショットの通知とは別に、その上に出す
+#[derive(Resource, Default)]
+struct ExportNotification {
+    message: Option<String>,
+    failed: bool,
+    timer: Timer,
 }
 
 fn configure_ui_font(mut contexts: EguiContexts) {
@@ -51,29 +89,49 @@ fn configure_ui_font(mut contexts: EguiContexts) {
     }
 }
 
-fn scan_models_directory(mut available_models: ResMut<AvailableModels>) {
-    let models_dir = Path::new("assets/models");
-    if !models_dir.exists() {
-        return;
-    }
-
-    if let Ok(entries) = fs::read_dir(models_dir) {
-        available_models.models = entries
-            .filter_map(|entry| entry.ok())
-            .filter(|entry| entry.path().is_dir())
-            .filter_map(|entry| entry.file_name().into_string().ok())
-            .filter(|name| name.ends_with("_description"))
-            .collect();
-    }
+fn scan_models_directory(
+    mut available_models: ResMut<AvailableModels>,
+    search_paths: Res<ModelSearchPaths>,
+) {
+    refresh_available_models(&mut available_models, &search_paths);
 }
 
-use crate::design::loader::LoadedRobots;
+fn refresh_available_models(available_models: &mut AvailableModels, search_paths: &ModelSearchPaths) {
+    let labelled = |paths: Vec<PathBuf>| -> Vec<AvailableModel> {
+        paths.into_iter()
+            .map(|path| {
+                let label = search_paths.roots.iter()
+                    .find_map(|root| path.strip_prefix(root).ok())
+                    .unwrap_or(path.as_path())
+                    .to_string_lossy()
+                    .replace('\\', "/");
+                AvailableModel { label, path }
+            })
+            .collect()
+    };
+
+    available_models.models = labelled(discover_robot_descriptions(&search_paths.roots));
+    available_models.worlds = labelled(discover_world_descriptions(&search_paths.roots));
+}
 
+#[allow(clippy::too_many_arguments)]
 fn ui_system(
     mut contexts: EguiContexts,
-    available_models: Res<AvailableModels>,
+    mut available_models: ResMut<AvailableModels>,
+    mut search_paths: ResMut<ModelSearchPaths>,
     mut load_event_writer: EventWriter<LoadRobotRequest>,
-    loaded_robots: Res<LoadedRobots>,
+    mut unload_event_writer: EventWriter<UnloadRobotRequest>,
+    mut reload_event_writer: EventWriter<ReloadRobotRequest>,
+    mut duplicate_event_writer: EventWriter<DuplicateRobotRequest>,
+    mut registry: ResMut<RobotRegistry>,
+    progress: Res<RobotLoadProgress>,
+    mut hot_reload: ResMut<HotReloadSettings>,
+    mut export_event_writer: EventWriter<ExportRobotRequest>,
+    mut spawn_poses: ResMut<SpawnPoses>,
+    mut reset_event_writer: EventWriter<ResetRobotRequest>,
+    mut spawn_handles: ResMut<SpawnHandles>,
+    bases: Query<(&Transform, &SpawnTransform)>,
+    joints: Query<&RobotJoint>,
 ) {
     egui::TopBottomPanel::top("top_panel").show(contexts.ctx_mut(), |ui| {
         egui::menu::bar(ui, |ui| {
@@ -84,30 +142,372 @@ fn ui_system(
             });
 
             ui.menu_button("ロボット", |ui| {
-                for i in 1..=10 {
-                    let label = if let Some(name) = loaded_robots.robots.get(&i) {
-                        format!("スロット{}: {}", i, name)
-                    } else {
-                        format!("スロット{}", i)
+                if ui.button("モデル一覧を更新").clicked() {
+                    search_paths.rescan_packages();
+                    refresh_available_models(&mut available_models, &search_paths);
+                }
+                ui.checkbox(&mut hot_reload.enabled, "ファイル変更時に自動で再読み込み");
+                ui.checkbox(&mut spawn_handles.visible, "スポーン位置をシーン内に表示 (K でカーソル解放中)");
+                ui.separator();
+
+                ui.menu_button("新しく読み込む", |ui| {
+                    if available_models.models.is_empty() {
+                        ui.label("利用可能なモデルがありません");
+                    }
+                    for model in &available_models.models {
+                        if ui.button(model.label.as_str()).clicked() {
+                            let slot = registry.reserve_slot();
+                            load_event_writer.send(LoadRobotRequest {
+                                path: model.path.clone(),
+                                slot,
+                                spawn: spawn_poses.pose(slot),
+                                name: None,
+                            });
+                            ui.close_menu();
+                        }
+                    }
+
+                    ui.separator();
+                    let next_slot = registry.next_slot();
+                    let mut pose = spawn_poses.pose(next_slot);
+                    ui.menu_button("スポーン位置", |ui| {
+                        spawn_pose_editor(ui, next_slot, &mut pose);
+                    });
+                    if pose != spawn_poses.pose(next_slot) {
+                        spawn_poses.poses.insert(next_slot, pose);
+                    }
+                });
+
+                // 初回の読み込み中はまだ登録されていない
+                let mut loading: Vec<_> = progress.slots.iter()
+                    .filter(|(slot, _)| **slot != WORLD_SLOT && !registry.contains(**slot))
+                    .collect();
+                loading.sort_by_key(|(slot, _)| **slot);
+                for (slot, stage) in loading {
+                    ui.label(format!("スロット{}: {}", slot, stage_label(stage)));
+                }
+
+                let instances: Vec<(usize, String)> = registry.iter()
+                    .filter(|instance| instance.slot != WORLD_SLOT)
+                    .map(|instance| (instance.slot, instance.name.clone()))
+                    .collect();
+                if !instances.is_empty() {
+                    ui.separator();
+                }
+
+                for (i, name) in instances {
+                    let label = match progress.slots.get(&i) {
+                        Some(stage) => format!("{}: {}", name, stage_label(stage)),
+                        None => format!("{} (スロット{})", name, i),
                     };
 
                     ui.menu_button(label, |ui| {
-                        if available_models.models.is_empty() {
-                            ui.label("利用可能なモデルがありません");
-                        } else {
-                            for model_name in &available_models.models {
-                                if ui.button(model_name).clicked() {
+                        if let Some(instance) = registry.get(i) {
+                            ui.menu_button("情報", |ui| instance_info(ui, instance, &joints));
+                        }
+                        ui.menu_button("モデルを差し替える", |ui| {
+                            for model in &available_models.models {
+                                if ui.button(model.label.as_str()).clicked() {
                                     load_event_writer.send(LoadRobotRequest {
-                                        model_name: model_name.clone(),
+                                        path: model.path.clone(),
                                         slot: i,
+                                        spawn: spawn_poses.pose(i),
+                                        name: None,
                                     });
                                     ui.close_menu();
                                 }
                             }
+                        });
+
+                        let mut pose = spawn_poses.pose(i);
+                        ui.menu_button("スポーン位置", |ui| {
+                            spawn_pose_editor(ui, i, &mut pose);
+
+                            ui.separator();
+                            if ui.button("スポーン位置に戻す").clicked() {
+                                reset_event_writer.send(ResetRobotRequest { slot: i });
+                                ui.close_menu();
+                            }
+                            let base = registry.get(i)
+                                .and_then(|instance| Some((instance, bases.get(instance.base()?).ok()?)));
+                            if let Some((instance, (current, spawned))) = base {
+                                if ui.button("現在の位置をスポーン位置にする").clicked() {
+                                    // SpawnTransform は読み込んだときのスポーン位置に置いた姿勢
+                                    pose = SpawnPose {
+                                        drop_to_ground: pose.drop_to_ground,
+                                        ..instance.spawn.moved_with(&spawned.0, current)
+                                    };
+                                }
+                            }
+                        });
+                        if pose != spawn_poses.pose(i) {
+                            spawn_poses.poses.insert(i, pose);
+                        }
+
+                        ui.separator();
+                        if ui.button("再読み込み").clicked() {
+                            reload_event_writer.send(ReloadRobotRequest { slot: i });
+                            ui.close_menu();
+                        }
+                        if ui.button("複製").clicked() {
+                            duplicate_event_writer.send(DuplicateRobotRequest { slot: i, spawn: None });
+                            ui.close_menu();
+                        }
+                        if ui.button("取り外す").clicked() {
+                            unload_event_writer.send(UnloadRobotRequest { slot: i });
+                            ui.close_menu();
+                        }
+
+                        ui.separator();
+                        for (label, format) in [
+                            ("URDFとして書き出す", ExportFormat::Urdf),
+                            ("MJCFとして書き出す", ExportFormat::Mjcf),
+                        ] {
+                            if ui.button(label).clicked() {
+                                export_event_writer.send(ExportRobotRequest { slot: i, format });
+                                ui.close_menu();
+                            }
                         }
                     });
                 }
             });
+
+            ui.menu_button("環境", |ui| {
+                if let Some(stage) = progress.slots.get(&WORLD_SLOT) {
+                    ui.label(stage_label(stage));
+                } else if let Some(world) = registry.get(WORLD_SLOT) {
+                    ui.label(format!("読み込み済み: {}", world.name));
+                    if ui.button("取り外す").clicked() {
+                        unload_event_writer.send(UnloadRobotRequest { slot: WORLD_SLOT });
+                        ui.close_menu();
+                    }
+                }
+                ui.separator();
+
+                if available_models.worlds.is_empty() {
+                    ui.label("利用可能なワールドがありません");
+                }
+                for world in &available_models.worlds {
+                    if ui.button(world.label.as_str()).clicked() {
+                        load_event_writer.send(LoadRobotRequest {
+                            path: world.path.clone(),
+                            slot: WORLD_SLOT,
+                            spawn: spawn_poses.pose(WORLD_SLOT),
+                            name: None,
+                        });
+                        ui.close_menu();
+                    }
+                }
+            });
         });
     });
 }
+
+// 次に読み込むときの位置。向きは度で入力する
+fn spawn_pose_editor(ui: &mut egui::Ui, slot: usize, pose: &mut SpawnPose) {
+    egui::Grid::new(("spawn_pose", slot)).num_columns(2).show(ui, |ui| {
+        for (label, value) in [("X [m]", &mut pose.position.x), ("Y [m]", &mut pose.position.y), ("Z [m]", &mut pose.position.z)] {
+            ui.label(label);
+            ui.add(egui::DragValue::new(value).speed(0.05).fixed_decimals(2));
+            ui.end_row();
+        }
+
+        ui.label("向き [°]");
+        let mut heading = pose.heading.to_degrees();
+        if ui.add(egui::DragValue::new(&mut heading).speed(1.0).clamp_range(-180.0..=180.0)).changed() {
+            pose.heading = heading.to_radians();
+        }
+        ui.end_row();
+    });
+    ui.checkbox(&mut pose.drop_to_ground, "地面まで下ろす");
+}
+
+fn instance_info(ui: &mut egui::Ui, instance: &RobotInstance, joints: &Query<&RobotJoint>) {
+    let spawn = instance.spawn;
+    egui::Grid::new("instance_info").num_columns(2).show(ui, |ui| {
+        for (label, value) in [
+            ("モデル", instance.description_name.clone()),
+            ("ファイル", instance.source.display().to_string()),
+            ("読み込み時刻", instance.loaded_at.format("%Y-%m-%d %H:%M:%S").to_string()),
+            ("スポーン位置", format!(
+                "({:.2}, {:.2}, {:.2}) {:.0}°",
+                spawn.position.x,
+                spawn.position.y,
+                spawn.position.z,
+                spawn.heading.to_degrees(),
+            )),
+        ] {
+            ui.label(label);
+            ui.label(value);
+            ui.end_row();
+        }
+        // SDF のワールドなど 1 インスタンスに複数のモデルがあるときだけ内訳を出す
+        if instance.models.len() > 1 {
+            for model in &instance.models {
+                ui.label(model.name.as_str());
+                ui.label(format!("リンク{}個", model.links.len()));
+                ui.end_row();
+            }
+        }
+    });
+
+    let mut states: Vec<(&str, f32)> = instance.joints()
+        .filter_map(|(name, entity)| Some((name, joints.get(entity).ok()?.position)))
+        .collect();
+    if states.is_empty() {
+        return;
+    }
+    states.sort_by(|a, b| a.0.cmp(b.0));
+
+    ui.separator();
+    egui::Grid::new("instance_joints").num_columns(2).show(ui, |ui| {
+        for (name, position) in states {
+            ui.label(name);
+            ui.label(format!("{:.3}", position));
+            ui.end_row();
+        }
+    });
+}
+
+fn stage_label(stage: &LoadStage) -> String {
+    match stage {
+        LoadStage::Parsing => "モデルを解析中".to_string(),
+        LoadStage::Meshes { loaded, total } => format!("メッシュを読み込み中 ({}/{})", loaded, total),
+        LoadStage::Colliders { remaining } => format!("コライダーを生成中 (残り{})", remaining),
+    }
+}
+
+fn load_progress_window(
+    mut contexts: EguiContexts,
+    progress: Res<RobotLoadProgress>,
+) {
+    if progress.slots.is_empty() {
+        return;
+    }
+
+    let mut slots: Vec<_> = progress.slots.iter().collect();
+    slots.sort_by_key(|(slot, _)| **slot);
+
+    egui::Window::new("読み込み中")
+        .anchor(egui::Align2::RIGHT_BOTTOM, [-10.0, -10.0])
+        .resizable(false)
+        .collapsible(false)
+        .show(contexts.ctx_mut(), |ui| {
+            for (slot, stage) in slots {
+                ui.horizontal(|ui| {
+                    ui.spinner();
+                    ui.label(format!("スロット{}: {}", slot, stage_label(stage)));
+                });
+                if let LoadStage::Meshes { loaded, total } = stage {
+                    if *total > 0 {
+                        ui.add(egui::ProgressBar::new(*loaded as f32 / *total as f32).show_percentage());
+                    }
+                }
+            }
+        });
+}
+
+fn notify_exports(
+    mut exported_events: EventReader<RobotExported>,
+    mut notification: ResMut<ExportNotification>,
+) {
+    for event in exported_events.read() {
+        let message = match &event.result {
+            Ok(paths) => {
+                let files: Vec<String> = paths.iter()
+                    .map(|path| path.to_string_lossy().replace('\\', "/"))
+                    .collect();
+                format!("スロット{}を{}に書き出しました。", event.slot, files.join(", "))
+            }
+            Err(e) => format!("スロット{}の書き出しに失敗しました: {}", event.slot, e),
+        };
+        // 失敗の理由は読み終えられるよう長めに出す
+        let seconds = if event.result.is_ok() { 3.0 } else { 6.0 };
+        notification.message = Some(message);
+        notification.failed = event.result.is_err();
+        notification.timer = Timer::from_seconds(seconds, TimerMode::Once);
+    }
+}
+
+fn export_notification_ui(
+    mut contexts: EguiContexts,
+    mut notification: ResMut<ExportNotification>,
+    time: Res<Time>,
+) {
+    let Some(message) = notification.message.clone() else {
+        return;
+    };
+
+    notification.timer.tick(time.delta());
+    if notification.timer.finished() {
+        notification.message = None;
+        return;
+    }
+
+    let color = if notification.failed { egui::Color32::LIGHT_RED } else { egui::Color32::WHITE };
+    let ctx = contexts.ctx_mut();
+    egui::Window::new("Export Notification")
+        .anchor(egui::Align2::RIGHT_BOTTOM, egui::vec2(-10.0, -60.0))
+        .title_bar(false)
+        .resizable(false)
+        .frame(egui::Frame::popup(ctx.style().as_ref()).fill(egui::Color32::from_black_alpha(200)))
+        .show(ctx, |ui| {
+            ui.label(egui::RichText::new(message).color(color).size(16.0));
+        });
+}
+
+fn collect_load_reports(
+    mut finished_events: EventReader<RobotLoadFinished>,
+    mut report: ResMut<LoadReport>,
+) {
+    for event in finished_events.read() {
+        let name = event.robot_name.clone()
+            .unwrap_or_else(|| event.path.to_string_lossy().replace('\\', "/"));
+
+        *report = LoadReport {
+            open: true,
+            title: format!("スロット{}: {}", event.slot, name),
+            error: event.error.as_ref().map(|e| e.to_string()),
+            issues: event.issues.clone(),
+        };
+    }
+}
+
+fn load_report_window(
+    mut contexts: EguiContexts,
+    mut report: ResMut<LoadReport>,
+) {
+    let LoadReport { open, title, error, issues } = &mut *report;
+
+    egui::Window::new("読み込みレポート")
+        .open(open)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.heading(title.as_str());
+
+            match error {
+                Some(error) => {
+                    ui.colored_label(egui::Color32::RED, format!("読み込みに失敗しました: {}", error));
+                }
+                None => {
+                    ui.label("読み込みが完了しました");
+                }
+            }
+
+            ui.separator();
+
+            if issues.is_empty() {
+                ui.label("問題は見つかりませんでした");
+                return;
+            }
+
+            egui::ScrollArea::vertical().max_height(300.0).show(ui, |ui| {
+                for issue in issues.iter() {
+                    let (color, label) = match issue.severity {
+                        IssueSeverity::Error => (egui::Color32::RED, "エラー"),
+                        IssueSeverity::Warning => (egui::Color32::YELLOW, "警告"),
+                    };
+                    ui.colored_label(color, format!("[{}] {}", label, issue.message));
+                }
+            });
+        });
+}
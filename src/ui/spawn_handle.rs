@@ -0,0 +1,180 @@
+use bevy::prelude::*;
+use bevy::window::PrimaryWindow;
+use bevy_egui::EguiContexts;
+use crate::core::InputState;
+use crate::design::loader::WORLD_SLOT;
+use crate::design::registry::RobotRegistry;
+use crate::design::spawn::{SpawnPose, SpawnPoses};
+
+// 地面と平行な円の半径。円の内側をつかむと移動、向きの矢印の先をつかむと回転
+const HANDLE_RADIUS: f32 = 0.5;
+const KNOB_RADIUS: f32 = 0.12;
+
+// カーソルを解放しているとき (K キー) にだけ、スロットごとのスポーン位置をシーン内に出す
+#[derive(Resource)]
+pub struct SpawnHandles {
+    pub visible: bool,
+    drag: Option<HandleDrag>,
+}
+
+impl Default for SpawnHandles {
+    fn default() -> Self {
+        Self {
+            visible: true,
+            drag: None,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+struct HandleDrag {
+    slot: usize,
+    kind: DragKind,
+    // つかんだ高さの水平面の上で動かす
+    height: f32,
+}
+
+#[derive(Clone, Copy)]
+enum DragKind {
+    // つかんだ点からスポーン位置までのずれ
+    Move { offset: Vec3 },
+    Rotate,
+}
+
+pub struct SpawnHandlePlugin;
+
+impl Plugin for SpawnHandlePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SpawnHandles>()
+           .add_systems(Update, (drag_spawn_handles, draw_spawn_handles).chain());
+    }
+}
+
+// 読み込み済みのスロットと、次に読み込むスロット
+fn handle_slots(registry: &RobotRegistry) -> Vec<usize> {
+    let mut slots: Vec<usize> = registry.iter()
+        .map(|instance| instance.slot)
+        .filter(|slot| *slot != WORLD_SLOT)
+        .collect();
+    slots.push(registry.next_slot());
+    slots
+}
+
+// ロボットの X 軸が向く方向
+fn forward(pose: &SpawnPose) -> Vec3 {
+    Quat::from_rotation_y(pose.heading) * Vec3::X
+}
+
+fn knob_position(pose: &SpawnPose) -> Vec3 {
+    pose.position + forward(pose) * HANDLE_RADIUS
+}
+
+fn draw_spawn_handles(
+    mut gizmos: Gizmos,
+    handles: Res<SpawnHandles>,
+    input_state: Res<InputState>,
+    spawn_poses: Res<SpawnPoses>,
+    registry: Res<RobotRegistry>,
+) {
+    if !handles.visible || input_state.cursor_locked {
+        return;
+    }
+
+    let next_slot = registry.next_slot();
+    for slot in handle_slots(&registry) {
+        let pose = spawn_poses.pose(slot);
+        let active = handles.drag.is_some_and(|drag| drag.slot == slot);
+        let color = if active {
+            Color::ORANGE
+        } else if slot == next_slot {
+            Color::GRAY
+        } else {
+            Color::YELLOW
+        };
+
+        gizmos.circle(pose.position, Direction3d::Y, HANDLE_RADIUS, color);
+        gizmos.arrow(pose.position, knob_position(&pose), color);
+        gizmos.sphere(knob_position(&pose), Quat::IDENTITY, KNOB_RADIUS, color);
+        // 地面に下ろす場合でも、どこに置かれるか分かるように真下へ線を引く
+        if pose.drop_to_ground {
+            gizmos.line(pose.position, pose.position * Vec3::new(1.0, 0.0, 1.0), color);
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn drag_spawn_handles(
+    mut contexts: EguiContexts,
+    mut handles: ResMut<SpawnHandles>,
+    mut spawn_poses: ResMut<SpawnPoses>,
+    input_state: Res<InputState>,
+    mouse: Res<ButtonInput<MouseButton>>,
+    registry: Res<RobotRegistry>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    cameras: Query<(&Camera, &GlobalTransform), With<Camera3d>>,
+) {
+    if !handles.visible || input_state.cursor_locked || !mouse.pressed(MouseButton::Left) {
+        handles.drag = None;
+        return;
+    }
+
+    let ray = windows.get_single().ok()
+        .and_then(Window::cursor_position)
+        .zip(cameras.get_single().ok())
+        .and_then(|(cursor, (camera, camera_transform))| camera.viewport_to_world(camera_transform, cursor));
+    let Some(ray) = ray else {
+        return;
+    };
+    let point_at = |height: f32| {
+        ray.intersect_plane(Vec3::Y * height, Plane3d::new(Vec3::Y))
+            .map(|distance| ray.get_point(distance))
+    };
+
+    if mouse.just_pressed(MouseButton::Left) {
+        // UI の上で押したときはつかまない
+        let ctx = contexts.ctx_mut();
+        if ctx.is_pointer_over_area() || ctx.wants_pointer_input() {
+            return;
+        }
+
+        handles.drag = handle_slots(&registry).into_iter()
+            .find_map(|slot| {
+                let pose = spawn_poses.pose(slot);
+                let height = pose.position.y;
+                let point = point_at(height)?;
+                let kind = if point.distance(knob_position(&pose)) <= KNOB_RADIUS * 2.0 {
+                    DragKind::Rotate
+                } else if point.distance(pose.position) <= HANDLE_RADIUS {
+                    DragKind::Move { offset: pose.position - point }
+                } else {
+                    return None;
+                };
+                Some(HandleDrag { slot, kind, height })
+            });
+        return;
+    }
+
+    let Some(drag) = handles.drag else {
+        return;
+    };
+    let Some(point) = point_at(drag.height) else {
+        return;
+    };
+
+    let mut pose = spawn_poses.pose(drag.slot);
+    match drag.kind {
+        DragKind::Move { offset } => {
+            pose.position.x = point.x + offset.x;
+            pose.position.z = point.z + offset.z;
+        }
+        DragKind::Rotate => {
+            let direction = point - pose.position;
+            if direction.x.abs() + direction.z.abs() > f32::EPSILON {
+                pose.heading = (-direction.z).atan2(direction.x);
+            }
+        }
+    }
+    if pose != spawn_poses.pose(drag.slot) {
+        spawn_poses.poses.insert(drag.slot, pose);
+    }
+}